@@ -1,10 +1,12 @@
 //! Descriptions of the tokens that Sapling uses to render ASTs to the screen.
 
 use super::Ast;
+use crate::core::{Path, Size};
 use std::borrow::Cow;
+use std::collections::HashSet;
 
-/// How many spaces corespond to one indentation level
-const INDENT_WIDTH: usize = 4;
+/// How many spaces correspond to one indentation level, unless the user configures otherwise
+pub const DEFAULT_INDENT_WIDTH: usize = 4;
 
 /// A module of `const`s that represent the default [`SyntaxCategories`](SyntaxCategory)
 pub mod syntax_category {
@@ -43,16 +45,18 @@ pub type SyntaxCategory = &'static str;
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum DisplayToken {
     /// Some text (as either a `&'static str` or a [`String`]) that should be rendered verbatim to
-    /// the screen
+    /// the screen.  If the text contains newlines, then every line after the first is displayed at
+    /// the current indentation level (just like the lines after a [`Newline`](Self::Newline)),
+    /// but the text is still written to files verbatim.
     Text(Cow<'static, str>, SyntaxCategory),
     /// Add some number of spaces worth of whitespace
     Whitespace(usize),
     /// Put the next token onto a new line
     Newline,
-    /// Add another indent level to the code
-    Indent,
-    /// Remove an indent level from the code
-    Dedent,
+    /// Add another indent level, of the given number of spaces, to the code
+    Indent(usize),
+    /// Remove an indent level, of the given number of spaces, from the code
+    Dedent(usize),
 }
 
 /// A wrapper for [`DisplayToken`] that will be returned by [`Ast::display_tokens`] and allows for
@@ -78,6 +82,234 @@ impl<'arena, Node> RecTok<'arena, Node> {
     }
 }
 
+/// A location in the text of a tree, counted in lines and columns from the start of the root node.
+/// This is used to lay out [`DisplayToken`]s without having to render them.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Hash)]
+pub struct Position {
+    /// The number of lines above this `Position`
+    pub line: usize,
+    /// The number of columns to the left of this `Position`, including indentation
+    pub column: usize,
+    /// The number of columns that the next line will be indented by
+    pub indentation: usize,
+}
+
+impl Position {
+    /// Moves this `Position` past a single [`DisplayToken`]
+    pub fn advance(&mut self, token: &DisplayToken) {
+        match token {
+            DisplayToken::Text(s, _) => self.skip(Size::from(s.as_ref())),
+            DisplayToken::Whitespace(n) => self.column += n,
+            DisplayToken::Newline => {
+                self.line += 1;
+                self.column = self.indentation;
+            }
+            DisplayToken::Indent(width) => self.indentation += width,
+            DisplayToken::Dedent(width) => self.indentation -= width,
+        }
+    }
+
+    /// Moves this `Position` past some text (usually a whole node) which occupies a given
+    /// [`Size`].  This is much faster than [`advance`](Self::advance)ing past every token of a
+    /// node.
+    pub fn skip(&mut self, size: Size) {
+        if size.lines() == 0 {
+            self.column += size.last_line_length();
+        } else {
+            self.line += size.lines();
+            self.column = self.indentation + size.last_line_length();
+        }
+    }
+}
+
+/// The nodes of a tree which are folded, i.e. displayed as a one-line
+/// [`fold_summary`](Ast::fold_summary) instead of their full text.  This only affects how the
+/// tree is displayed, not the text that's written to files.
+#[derive(Debug, Clone)]
+pub struct Folds<Node> {
+    /// The nodes which are folded
+    folded: HashSet<*const Node>,
+    /// Every node which has a folded descendant.  The [`Size`]s of all other nodes aren't
+    /// affected by folding.
+    ancestors: HashSet<*const Node>,
+}
+
+impl<Node> Default for Folds<Node> {
+    fn default() -> Self {
+        Folds {
+            folded: HashSet::new(),
+            ancestors: HashSet::new(),
+        }
+    }
+}
+
+impl<'arena, Node: Ast<'arena>> Folds<Node> {
+    /// Creates a set of `Folds` where the nodes at the end of some [`Path`]s into `root` are
+    /// folded
+    pub fn new<'p>(root: &'arena Node, paths: impl IntoIterator<Item = &'p Path>) -> Self {
+        let mut folds = Folds::default();
+        for path in paths {
+            let mut node = root;
+            for &index in path.iter() {
+                folds.ancestors.insert(node as *const Node);
+                node = node.children()[index];
+            }
+            folds.folded.insert(node as *const Node);
+        }
+        folds
+    }
+
+    /// Returns `true` if `node` is folded
+    pub fn is_folded(&self, node: &'arena Node) -> bool {
+        self.folded.contains(&(node as *const Node))
+    }
+
+    /// Returns the outermost folded node on `path`, or the node at the end of `path` if none of
+    /// its ancestors are folded.  This is the node which displays where `path` is.
+    pub fn visible_node(&self, root: &'arena Node, path: &Path) -> &'arena Node {
+        let mut node = root;
+        for &index in path.iter() {
+            if self.is_folded(node) {
+                break;
+            }
+            node = node.children()[index];
+        }
+        node
+    }
+
+    /// Returns the tokens of `node`, as in [`Ast::display_tokens_rec`], unless `node` is folded
+    /// in which case its summary is returned instead
+    pub fn display_tokens_rec(
+        &self,
+        node: &'arena Node,
+        format_style: &Node::FormatStyle,
+    ) -> Vec<RecTok<'arena, Node>> {
+        if self.is_folded(node) {
+            vec![RecTok::from_string(
+                node.fold_summary(),
+                syntax_category::COMMENT,
+            )]
+        } else {
+            node.display_tokens_rec(format_style)
+        }
+    }
+
+    /// Returns the [`Size`] of `node` after folding, as in [`Ast::size`]
+    pub fn size(&self, node: &'arena Node, format_style: &Node::FormatStyle) -> Size {
+        if !self.ancestors.contains(&(node as *const Node)) && !self.is_folded(node) {
+            return node.size(format_style);
+        }
+        let mut size = Size::ZERO;
+        for tok in self.display_tokens_rec(node, format_style) {
+            size += match tok {
+                RecTok::Tok(DisplayToken::Text(s, _)) => Size::from(s.as_ref()),
+                RecTok::Tok(DisplayToken::Whitespace(n)) => Size::new(0, n),
+                RecTok::Tok(DisplayToken::Newline) => Size::new(1, 0),
+                RecTok::Tok(DisplayToken::Indent(_) | DisplayToken::Dedent(_)) => Size::ZERO,
+                RecTok::Child(child) => self.size(child, format_style),
+            };
+        }
+        size
+    }
+}
+
+/// Returns the [`Position`] where the node at the end of `path` starts in the text of `root`,
+/// or where its outermost folded ancestor starts if it's hidden inside a fold.  The nodes which
+/// aren't on `path` are skipped using their [`Size`], so this doesn't have to generate the tokens
+/// of the whole tree.
+pub fn position_of_path<'arena, Node: Ast<'arena>>(
+    root: &'arena Node,
+    path: &Path,
+    format_style: &Node::FormatStyle,
+    folds: &Folds<Node>,
+) -> Position {
+    let mut position = Position::default();
+    let mut node = root;
+    for &index in path.iter() {
+        if folds.is_folded(node) {
+            break;
+        }
+        let mut child_index = 0;
+        for tok in node.display_tokens_rec(format_style) {
+            match tok {
+                RecTok::Tok(token) => position.advance(&token),
+                RecTok::Child(child) if child_index == index => {
+                    node = child;
+                    break;
+                }
+                RecTok::Child(child) => {
+                    position.skip(folds.size(child, format_style));
+                    child_index += 1;
+                }
+            }
+        }
+    }
+    position
+}
+
+/// Returns the [`Path`] of the first visible node (in pre-order) which starts on or after a given
+/// `line` of the text of `root`.  If no node starts that late, then the last visible node is
+/// returned.
+pub fn path_to_line<'arena, Node: Ast<'arena>>(
+    root: &'arena Node,
+    line: usize,
+    format_style: &Node::FormatStyle,
+    folds: &Folds<Node>,
+) -> Path {
+    /// Finds the first node in the subtree of `node` which starts on or after `line`, given the
+    /// `Path` and `Position` of `node`
+    fn search<'arena, Node: Ast<'arena>>(
+        node: &'arena Node,
+        path: &mut Path,
+        mut position: Position,
+        line: usize,
+        format_style: &Node::FormatStyle,
+        folds: &Folds<Node>,
+    ) -> bool {
+        if position.line >= line {
+            return true;
+        }
+        let mut child_index = 0;
+        for tok in folds.display_tokens_rec(node, format_style) {
+            match tok {
+                RecTok::Tok(token) => position.advance(&token),
+                RecTok::Child(child) => {
+                    let size = folds.size(child, format_style);
+                    // Only children which finish on or after `line` can contain the node we want
+                    if position.line + size.lines() >= line {
+                        path.push(child_index);
+                        if search(child, path, position, line, format_style, folds) {
+                            return true;
+                        }
+                        path.pop();
+                    }
+                    position.skip(size);
+                    child_index += 1;
+                }
+            }
+        }
+        false
+    }
+
+    let mut path = Path::root();
+    if !search(
+        root,
+        &mut path,
+        Position::default(),
+        line,
+        format_style,
+        folds,
+    ) {
+        // No node starts on or after `line`, so we return the last visible node in pre-order
+        let mut node = root;
+        while let Some(last_child) = node.children().last().filter(|_| !folds.is_folded(node)) {
+            path.push(node.children().len() - 1);
+            node = last_child;
+        }
+    }
+    path
+}
+
 /// Write a stream of display tokens to a string
 pub fn write_tokens<'arena, Node: Ast<'arena>>(
     root: &'arena Node,
@@ -90,7 +322,8 @@ pub fn write_tokens<'arena, Node: Ast<'arena>>(
     for (_id, tok) in root.display_tokens(format_style) {
         match tok {
             DisplayToken::Text(s, _) => {
-                // Push the string we've been given
+                // Push the string we've been given.  This is written verbatim, since indenting the
+                // lines of multi-line text would change the contents of the file.
                 string.push_str(&s);
             }
             DisplayToken::Whitespace(n) => {
@@ -104,15 +337,15 @@ pub fn write_tokens<'arena, Node: Ast<'arena>>(
                 string.push('\n');
                 string.push_str(&indentation_string);
             }
-            DisplayToken::Indent => {
-                // Add `INDENT_WIDTH` spaces to the indentation_string
-                for _ in 0..INDENT_WIDTH {
+            DisplayToken::Indent(width) => {
+                // Add `width` spaces to the indentation_string
+                for _ in 0..width {
                     indentation_string.push(' ');
                 }
             }
-            DisplayToken::Dedent => {
-                // Remove `INDENT_WIDTH` spaces to the indentation_string
-                for _ in 0..INDENT_WIDTH {
+            DisplayToken::Dedent(width) => {
+                // Remove `width` spaces from the indentation_string
+                for _ in 0..width {
                     let popped_char = indentation_string.pop();
                     debug_assert_eq!(popped_char, Some(' '));
                 }
@@ -120,3 +353,122 @@ pub fn write_tokens<'arena, Node: Ast<'arena>>(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{path_to_line, position_of_path, Folds, Position};
+    use crate::arena::Arena;
+    use crate::ast::json::{add_value_to_arena, JsonFormat};
+    use crate::ast::Ast;
+    use crate::core::{Path, Size};
+
+    use serde_json::json;
+
+    #[test]
+    fn positions() {
+        let arena = Arena::new();
+        // [
+        //     true,
+        //     {
+        //         "a": [],
+        //         "b": null
+        //     },
+        //     false
+        // ]
+        let root = add_value_to_arena(json!([true, {"a": [], "b": null}, false]), &arena);
+        let pretty = &JsonFormat::Pretty(4);
+        let no_folds = Folds::default();
+        for (path, line, column) in [
+            (vec![], 0, 0),
+            (vec![0], 1, 4),
+            (vec![1], 2, 4),
+            (vec![1, 0], 3, 8),
+            (vec![1, 0, 1], 3, 13),
+            (vec![1, 1, 1], 4, 13),
+            (vec![2], 6, 4),
+        ] {
+            let position = position_of_path(root, &Path::from_vec(path.clone()), pretty, &no_folds);
+            assert_eq!(
+                (position.line, position.column),
+                (line, column),
+                "{:?}",
+                path
+            );
+        }
+        // In compact mode, everything is on one line
+        let position = position_of_path(
+            root,
+            &Path::from_vec(vec![2]),
+            &JsonFormat::Compact,
+            &no_folds,
+        );
+        assert_eq!(
+            position,
+            Position {
+                line: 0,
+                column: 29,
+                indentation: 0
+            }
+        );
+
+        for (line, path) in [
+            (0, vec![]),
+            (1, vec![0]),
+            (3, vec![1, 0]),
+            // Line 5 only contains a closing bracket, so the next node is chosen
+            (5, vec![2]),
+            // There are no nodes after the end of the tree, so the last one is chosen
+            (7, vec![2]),
+            (100, vec![2]),
+        ] {
+            assert_eq!(
+                path_to_line(root, line, pretty, &no_folds),
+                Path::from_vec(path)
+            );
+        }
+    }
+
+    #[test]
+    fn folds() {
+        let arena = Arena::new();
+        // [
+        //     true,
+        //     {… 2 fields},
+        //     [… 1 item]
+        // ]
+        let root = add_value_to_arena(json!([true, {"a": [], "b": null}, [false]]), &arena);
+        let pretty = &JsonFormat::Pretty(4);
+        let paths = [Path::from_vec(vec![1]), Path::from_vec(vec![2])];
+        let folds = Folds::new(root, &paths);
+        assert!(folds.is_folded(root.children()[1]));
+        assert!(!folds.is_folded(root));
+        assert_eq!(folds.size(root, pretty), Size::new(4, 1));
+        // Only the ancestors of folded nodes change size
+        assert_eq!(folds.size(root.children()[0], pretty), Size::new(0, 4));
+        assert_eq!(folds.size(root.children()[2], pretty), Size::new(0, 10));
+
+        // Nodes inside a fold are shown by the folded node
+        for (path, line, column) in [
+            (vec![1], 2, 4),
+            (vec![1, 1, 0], 2, 4),
+            (vec![2], 3, 4),
+            (vec![2, 0], 3, 4),
+        ] {
+            let path = Path::from_vec(path);
+            let position = position_of_path(root, &path, pretty, &folds);
+            assert_eq!((position.line, position.column), (line, column), "{}", path);
+        }
+        let hidden = Path::from_vec(vec![1, 1]);
+        assert!(std::ptr::eq(
+            folds.visible_node(root, &hidden),
+            root.children()[1]
+        ));
+        // The cursor can't be moved into a fold by scrolling
+        for (line, path) in [(2, vec![1]), (3, vec![2]), (100, vec![2])] {
+            assert_eq!(
+                path_to_line(root, line, pretty, &folds),
+                Path::from_vec(path)
+            );
+        }
+    }
+}
@@ -1,7 +1,7 @@
 //! A hard-coded specification of JSON ASTs in a format editable by Sapling
 
-use super::display_token::{syntax_category, DisplayToken, RecTok};
-use super::{Ast, AstClass, DeleteError, InsertError};
+use super::display_token::{syntax_category, DisplayToken, RecTok, DEFAULT_INDENT_WIDTH};
+use super::{Ast, AstClass, DeleteError, Format, InsertError};
 use crate::arena::Arena;
 use crate::ast_class;
 use crate::core::Size;
@@ -40,14 +40,89 @@ pub fn add_value_to_arena<'arena>(
     }
 }
 
+/// The error returned when parsing JSON that contains numbers, since Sapling can't represent them
+/// yet
+const NUMBER_ERROR: &str = "Sapling can't edit JSON numbers yet";
+
+/// The ways that parsing a JSON file can fail
+#[derive(Debug)]
+pub enum JsonParseErr {
+    /// The text isn't valid JSON
+    Syntax(serde_json::Error),
+    /// The text is valid JSON, but contains numbers (which Sapling can't represent yet)
+    Number,
+}
+
+impl From<serde_json::Error> for JsonParseErr {
+    fn from(e: serde_json::Error) -> Self {
+        JsonParseErr::Syntax(e)
+    }
+}
+
+impl std::fmt::Display for JsonParseErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            JsonParseErr::Syntax(e) => write!(f, "{}", e),
+            JsonParseErr::Number => write!(f, "{}", NUMBER_ERROR),
+        }
+    }
+}
+
+impl std::error::Error for JsonParseErr {}
+
+/// Returns the first of `""`, `"1"`, `"2"`, ... which isn't the key of any of the given fields
+fn unused_key(fields: &[&Json]) -> String {
+    let is_unused = |key: &String| fields.iter().all(|f| f.key_name() != Some(key.as_str()));
+    std::iter::once(String::new())
+        .chain((1..).map(|i: usize| i.to_string()))
+        .find(is_unused)
+        .unwrap()
+}
+
+/// Returns `true` if a [`serde_json::Value`] contains any numbers (which [`add_value_to_arena`]
+/// can't handle)
+fn contains_number(value: &Value) -> bool {
+    match value {
+        Value::Number(_) => true,
+        Value::Array(children) => children.iter().any(contains_number),
+        Value::Object(fields) => fields.values().any(contains_number),
+        Value::Null | Value::Bool(_) | Value::String(_) => false,
+    }
+}
+
 /// An enum to hold the different ways that a JSON AST can be formatted
 #[derive(Eq, PartialEq, Copy, Clone)]
 pub enum JsonFormat {
     /// The most compact representation, has minimal whitespace.
     /// E.g. `[{"foo": true, "bar": false}, true]`
     Compact,
-    /// A prettified representation, with pretty indenting and every element on a newline.
-    Pretty,
+    /// A prettified representation, with every element on a newline and each level of nesting
+    /// indented by the given number of spaces.
+    Pretty(usize),
+}
+
+impl std::str::FromStr for JsonFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "compact" => Ok(JsonFormat::Compact),
+            "pretty" => Ok(JsonFormat::Pretty(DEFAULT_INDENT_WIDTH)),
+            _ => Err(format!(
+                "Unknown format '{}' (expected 'compact' or 'pretty')",
+                s
+            )),
+        }
+    }
+}
+
+impl Format for JsonFormat {
+    fn with_indent_width(self, width: usize) -> Self {
+        match self {
+            JsonFormat::Compact => JsonFormat::Compact,
+            JsonFormat::Pretty(_) => JsonFormat::Pretty(width),
+        }
+    }
 }
 
 ast_class!(
@@ -93,7 +168,7 @@ impl Default for Json<'_> {
 impl<'arena> Ast<'arena> for Json<'arena> {
     type FormatStyle = JsonFormat;
     type Class = Class;
-    type ParseErr = serde_json::Error;
+    type ParseErr = JsonParseErr;
 
     /* FORMATTING FUNCTIONS */
 
@@ -101,7 +176,11 @@ impl<'arena> Ast<'arena> for Json<'arena> {
         &'arena self,
         format_style: &Self::FormatStyle,
     ) -> Vec<RecTok<'arena, Self>> {
-        let is_pretty = format_style == &JsonFormat::Pretty;
+        // The indentation width, or `None` if everything is on one line
+        let indent_width = match *format_style {
+            JsonFormat::Compact => None,
+            JsonFormat::Pretty(width) => Some(width),
+        };
         match self {
             Json::True => vec![RecTok::from_str("true", syntax_category::CONST)],
             Json::False => vec![RecTok::from_str("false", syntax_category::CONST)],
@@ -124,8 +203,8 @@ impl<'arena> Ast<'arena> for Json<'arena> {
                 let mut tokens: Vec<RecTok<'_, Self>> = Vec::with_capacity(6 + 3 * children.len());
                 // Push some initial tokens
                 tokens.push(RecTok::from_str("[", syntax_category::DEFAULT));
-                if is_pretty {
-                    tokens.push(RecTok::Tok(DisplayToken::Indent));
+                if let Some(width) = indent_width {
+                    tokens.push(RecTok::Tok(DisplayToken::Indent(width)));
                     tokens.push(RecTok::Tok(DisplayToken::Newline));
                 }
                 // Push the children, delimited by commas
@@ -134,7 +213,7 @@ impl<'arena> Ast<'arena> for Json<'arena> {
                     // Push the delimiting
                     if !is_first_child {
                         tokens.push(RecTok::from_str(",", syntax_category::DEFAULT));
-                        if is_pretty {
+                        if indent_width.is_some() {
                             tokens.push(RecTok::Tok(DisplayToken::Newline));
                         } else {
                             tokens.push(RecTok::Tok(DisplayToken::Whitespace(1)));
@@ -145,8 +224,8 @@ impl<'arena> Ast<'arena> for Json<'arena> {
                     tokens.push(RecTok::Child(c));
                 }
                 // Push the closing bracket
-                if is_pretty {
-                    tokens.push(RecTok::Tok(DisplayToken::Dedent));
+                if let Some(width) = indent_width {
+                    tokens.push(RecTok::Tok(DisplayToken::Dedent(width)));
                     tokens.push(RecTok::Tok(DisplayToken::Newline));
                 }
                 tokens.push(RecTok::from_str("]", syntax_category::DEFAULT));
@@ -162,8 +241,8 @@ impl<'arena> Ast<'arena> for Json<'arena> {
                 let mut tokens: Vec<RecTok<'_, Self>> = Vec::with_capacity(6 + 3 * fields.len());
                 // Push some initial tokens
                 tokens.push(RecTok::from_str("{", syntax_category::DEFAULT));
-                if is_pretty {
-                    tokens.push(RecTok::Tok(DisplayToken::Indent));
+                if let Some(width) = indent_width {
+                    tokens.push(RecTok::Tok(DisplayToken::Indent(width)));
                     tokens.push(RecTok::Tok(DisplayToken::Newline));
                 }
                 // Push the children, delimited by commas
@@ -172,7 +251,7 @@ impl<'arena> Ast<'arena> for Json<'arena> {
                     // Push the delimiting
                     if !is_first_child {
                         tokens.push(RecTok::from_str(",", syntax_category::DEFAULT));
-                        if is_pretty {
+                        if indent_width.is_some() {
                             tokens.push(RecTok::Tok(DisplayToken::Newline));
                         } else {
                             tokens.push(RecTok::Tok(DisplayToken::Whitespace(1)));
@@ -183,8 +262,8 @@ impl<'arena> Ast<'arena> for Json<'arena> {
                     tokens.push(RecTok::Child(f));
                 }
                 // Push the closing bracket
-                if is_pretty {
-                    tokens.push(RecTok::Tok(DisplayToken::Dedent));
+                if let Some(width) = indent_width {
+                    tokens.push(RecTok::Tok(DisplayToken::Dedent(width)));
                     tokens.push(RecTok::Tok(DisplayToken::Newline));
                 }
                 tokens.push(RecTok::from_str("}", syntax_category::DEFAULT));
@@ -196,15 +275,37 @@ impl<'arena> Ast<'arena> for Json<'arena> {
 
     fn parse_to_arena(
         text: impl std::io::Read,
-        arena: &'arena mut Arena<Self>,
+        arena: &'arena Arena<Self>,
     ) -> Result<&'arena Self, Self::ParseErr> {
         let value = serde_json::from_reader::<_, Value>(text)?;
+        if contains_number(&value) {
+            return Err(JsonParseErr::Number);
+        }
         Ok(add_value_to_arena(value, arena))
     }
 
+    fn parse_fragment(text: &str, arena: &'arena Arena<Self>) -> Result<&'arena Self, String> {
+        match serde_json::from_str::<Value>(text) {
+            Ok(value) if contains_number(&value) => Err(NUMBER_ERROR.to_owned()),
+            Ok(value) => Ok(add_value_to_arena(value, arena)),
+            // Fields aren't valid JSON on their own, so if the text isn't a valid value then we
+            // try to parse it as the only field of an object
+            Err(e) => match serde_json::from_str::<Value>(&format!("{{{}}}", text)) {
+                Ok(Value::Object(fields)) if fields.len() == 1 => {
+                    let object = Value::Object(fields);
+                    if contains_number(&object) {
+                        return Err(NUMBER_ERROR.to_owned());
+                    }
+                    Ok(add_value_to_arena(object, arena).children()[0])
+                }
+                _ => Err(e.to_string()),
+            },
+        }
+    }
+
     fn size(&self, format_style: &Self::FormatStyle) -> Size {
         match format_style {
-            JsonFormat::Pretty => {
+            JsonFormat::Pretty(_) => {
                 match self {
                     Json::True => Size::new(0, 4),  // same as Size::from("true")
                     Json::False => Size::new(0, 5), // same as Size::from("false")
@@ -337,10 +438,18 @@ impl<'arena> Ast<'arena> for Json<'arena> {
                 max_children: 2,
             }),
             Json::Object(fields) => {
+                // If we're inserting an existing field (e.g. one that's being moved between
+                // objects), then it can be inserted directly
+                if let Json::Field(_) = new_node {
+                    fields.insert(index, new_node);
+                    return Ok(());
+                }
                 /* Inserting into an object is a special case, since we need to allocate more
                  * objects in order to preserve the validity of the tree. */
-                // Allocate an empty string to act as the key
-                let key = arena.alloc(Json::Str("".to_string()));
+                // Allocate a string to act as the key.  This is empty unless another field already
+                // has an empty key, since keys must be unique (e.g. when moving several values into
+                // the same object)
+                let key = arena.alloc(Json::Str(unused_key(fields)));
                 // Allocate a field as the parent of the key and new_node
                 let field = arena.alloc(Json::Field([key, new_node]));
                 // Add the new field as a child of `self`
@@ -348,6 +457,13 @@ impl<'arena> Ast<'arena> for Json<'arena> {
                 Ok(())
             }
             Json::Array(children) => {
+                // Fields can only ever exist inside objects
+                if let Json::Field(_) = new_node {
+                    return Err(InsertError::InvalidChild {
+                        name: self.display_name(),
+                        child_name: new_node.display_name(),
+                    });
+                }
                 children.insert(index, new_node);
                 Ok(())
             }
@@ -403,6 +519,45 @@ impl<'arena> Ast<'arena> for Json<'arena> {
         }
     }
 
+    fn fold_summary(&'arena self) -> String {
+        /// Formats `count` along with either the singular or plural form of a noun
+        fn count_of(count: usize, singular: &str, plural: &str) -> String {
+            format!("{} {}", count, if count == 1 { singular } else { plural })
+        }
+
+        match self {
+            Json::Array(children) => format!("[… {}]", count_of(children.len(), "item", "items")),
+            Json::Object(fields) => format!("{{… {}}}", count_of(fields.len(), "field", "fields")),
+            // Folding a field folds its value, but keeps the key visible
+            Json::Field([key, value]) if value.children().is_empty() => {
+                format!("{}: {}", key.display_name(), value.display_name())
+            }
+            Json::Field([key, value]) => {
+                format!("{}: {}", key.display_name(), value.fold_summary())
+            }
+            Json::True | Json::False | Json::Null | Json::Str(_) => self.display_name(),
+        }
+    }
+
+    fn class_name(&self) -> &'static str {
+        match self {
+            Json::True => Class::True.name(),
+            Json::False => Class::False.name(),
+            Json::Null => Class::Null.name(),
+            Json::Array(_) => Class::Array.name(),
+            Json::Object(_) => Class::Object.name(),
+            Json::Field(_) => "field",
+            Json::Str(_) => Class::Str.name(),
+        }
+    }
+
+    fn key_name(&self) -> Option<&str> {
+        match self {
+            Json::Field([Json::Str(key), _]) => Some(key),
+            _ => None,
+        }
+    }
+
     /* AST EDITING FUNCTIONS */
 
     fn from_class(node_type: Self::Class) -> Self {
@@ -416,6 +571,18 @@ impl<'arena> Ast<'arena> for Json<'arena> {
         }
     }
 
+    fn class(&self) -> Option<Class> {
+        match self {
+            Json::True => Some(Class::True),
+            Json::False => Some(Class::False),
+            Json::Null => Some(Class::Null),
+            Json::Str(_) => Some(Class::Str),
+            Json::Array(_) => Some(Class::Array),
+            Json::Object(_) => Some(Class::Object),
+            Json::Field(_) => None,
+        }
+    }
+
     fn is_valid_child(&self, index: usize, node_type: Self::Class) -> bool {
         match self {
             // values like 'true' and 'false' can never have children
@@ -488,9 +655,9 @@ impl PartialEq<Value> for Json<'_> {
 
 #[cfg(test)]
 mod tests {
-    use super::{add_value_to_arena, JsonFormat};
+    use super::{add_value_to_arena, Json, JsonFormat};
     use crate::arena::Arena;
-    use crate::ast::Ast;
+    use crate::ast::{Ast, Format};
     use crate::core::Size;
 
     use serde_json::json;
@@ -555,6 +722,13 @@ mod tests {
       false
   true"#,
             ),
+            // Multi-line strings are written verbatim, without indenting their later lines
+            (
+                json!(["a\nb", true]),
+                "[\"a\nb\", true]",
+                "[\n    \"a\nb\",\n    true\n]",
+                "array\n  \"a\nb\"\n  true",
+            ),
         ] {
             println!("Testing {}", expected_compact_string);
 
@@ -568,10 +742,10 @@ mod tests {
                 Size::from(*expected_compact_string)
             );
             // Test pretty string
-            let pretty_string = root.to_text(&JsonFormat::Pretty);
+            let pretty_string = root.to_text(&JsonFormat::Pretty(4));
             assert_eq!(pretty_string, *expected_pretty_string);
             assert_eq!(
-                root.size(&JsonFormat::Pretty),
+                root.size(&JsonFormat::Pretty(4)),
                 Size::from(*expected_pretty_string)
             );
             // Test debug tree view
@@ -580,4 +754,32 @@ mod tests {
             assert_eq!(s, *tree_string);
         }
     }
+
+    #[test]
+    fn indent_width() {
+        let arena = Arena::new();
+        let root = add_value_to_arena(json!([{"a": "b\nc"}, []]), &arena);
+        let format = "pretty".parse::<JsonFormat>().unwrap().with_indent_width(2);
+        let expected = "[\n  {\n    \"a\": \"b\nc\"\n  },\n  []\n]";
+        assert_eq!(root.to_text(&format), expected);
+        assert_eq!(root.size(&format), Size::from(expected));
+        // Compact JSON has no indentation to change
+        assert!(JsonFormat::Compact.with_indent_width(2) == JsonFormat::Compact);
+    }
+
+    #[test]
+    fn parse_fragment() {
+        let arena = Arena::new();
+        // Values parse as themselves
+        let node = Json::parse_fragment(r#"[true, {"a": null}]"#, &arena).unwrap();
+        assert_eq!(*node, json!([true, {"a": null}]));
+        // Fields are parsed as if they were inside an object
+        let field = Json::parse_fragment(r#""key": [false]"#, &arena).unwrap();
+        assert_eq!(field.key_name(), Some("key"));
+        assert_eq!(*field.children()[1], json!([false]));
+        // Invalid or unsupported JSON is an error
+        assert!(Json::parse_fragment(r#""a": true, "b": false"#, &arena).is_err());
+        assert!(Json::parse_fragment("[true,", &arena).is_err());
+        assert!(Json::parse_fragment("[1, 2]", &arena).is_err());
+    }
 }
@@ -2,6 +2,7 @@
 
 pub mod display_token;
 pub mod json;
+pub mod pattern;
 
 use std::error::Error;
 
@@ -19,6 +20,13 @@ pub enum InsertError {
         /// The maximum number of children that the node being inserted into could have
         max_children: usize,
     },
+    /// The node being inserted can never be a child of the node it's being inserted into
+    InvalidChild {
+        /// The name of the node which was being inserted into
+        name: String,
+        /// The name of the node which couldn't be inserted
+        child_name: String,
+    },
 }
 
 impl std::fmt::Display for InsertError {
@@ -29,6 +37,9 @@ impl std::fmt::Display for InsertError {
                 "Can't exceed child count limit of {} in {}",
                 max_children, name
             ),
+            InsertError::InvalidChild { name, child_name } => {
+                write!(f, "{} can't be a child of {}", child_name, name)
+            }
         }
     }
 }
@@ -131,12 +142,16 @@ macro_rules! ast_class {
                     _ => None,
                 }
             }
+
+            fn all() -> &'static [Self] {
+                &[$( Class::$variant_name ),+]
+            }
         }
     };
 }
 
 /// All the possible types which [`Ast`] nodes can take.
-pub trait AstClass: Copy + std::fmt::Debug + Eq + std::hash::Hash {
+pub trait AstClass: Copy + std::fmt::Debug + Eq + std::hash::Hash + 'static {
     /// Gets the [`char`] that would have been used to create this value
     fn to_char(self) -> char;
 
@@ -145,12 +160,23 @@ pub trait AstClass: Copy + std::fmt::Debug + Eq + std::hash::Hash {
 
     /// Creates a `AstClass` from a [`char`], returning [`None`] if invalid.
     fn from_char(c: char) -> Option<Self>;
+
+    /// Returns every value of this `AstClass`, in the order they were declared
+    fn all() -> &'static [Self];
+}
+
+/// The settings which the [`FormatStyle`](Ast::FormatStyle) of every language has to support
+pub trait Format: Clone + std::str::FromStr<Err = String> {
+    /// Returns this style, but with each level of indentation `width` spaces wide.  Styles which
+    /// don't indent anything are returned unchanged.
+    fn with_indent_width(self, width: usize) -> Self;
 }
 
 /// The specification of an AST that sapling can edit
 pub trait Ast<'arena>: std::fmt::Debug + Clone + Eq + Default + std::hash::Hash {
-    /// A type parameter that will represent the different ways this AST can be rendered
-    type FormatStyle;
+    /// A type parameter that will represent the different ways this AST can be rendered.  This can
+    /// be parsed from a name (e.g. `"pretty"`), so that the user can change it.
+    type FormatStyle: Format;
     /// A type parameter that will represent the different node types this AST can use
     type Class: AstClass;
     /// The error type for ways that parsing can fail
@@ -168,9 +194,15 @@ pub trait Ast<'arena>: std::fmt::Debug + Clone + Eq + Default + std::hash::Hash
     /// Parses from text and adds to an arena, return a pointer to the allocated root node.
     fn parse_to_arena(
         text: impl std::io::Read,
-        arena: &'arena mut Arena<Self>,
+        arena: &'arena Arena<Self>,
     ) -> Result<&'arena Self, Self::ParseErr>;
 
+    /// Parses a single node (along with its descendants) from a snippet of text and adds it to an
+    /// arena.  Unlike [`parse_to_arena`](Self::parse_to_arena), the snippet doesn't have to be a
+    /// complete file, so this can create any node that could appear in a tree (e.g. a single field
+    /// of a JSON object).
+    fn parse_fragment(text: &str, arena: &'arena Arena<Self>) -> Result<&'arena Self, String>;
+
     /// Uses [`display_tokens_rec`](Self::display_tokens_rec) to build a stream of
     /// [`DisplayToken`]s representing this node, but where each [`DisplayToken`] is paired with a
     /// reference to the node that owns it.  This extra data is used by the rendering code to
@@ -241,9 +273,40 @@ pub trait Ast<'arena>: std::fmt::Debug + Clone + Eq + Default + std::hash::Hash
         index: usize,
     ) -> Result<(), InsertError>;
 
+    /// Allocates a copy of this node and all of its descendants in `arena`.  Unlike
+    /// [`clone`](Clone::clone), the copy shares no references with this node, so both can be
+    /// part of the same tree.
+    fn deep_copy(&'arena self, arena: &'arena Arena<Self>) -> &'arena Self {
+        let mut copy = self.clone();
+        for child in copy.children_mut() {
+            *child = child.deep_copy(arena);
+        }
+        arena.alloc(copy)
+    }
+
     /// Get the display name of this node
     fn display_name(&self) -> String;
 
+    /// Get the name of the type of this node (e.g. `"array"` or `"string"`).  Unlike
+    /// [`display_name`](Ast::display_name), this doesn't depend on the contents of the node.
+    fn class_name(&self) -> &'static str;
+
+    /// Returns the one-line summary which is shown in place of this node when it's folded (e.g.
+    /// `[… 3 items]`).  Only nodes with children can be folded.
+    fn fold_summary(&'arena self) -> String {
+        format!(
+            "{}(… {} children)",
+            self.class_name(),
+            self.children().len()
+        )
+    }
+
+    /// If this node associates a key with a value (e.g. a field in a JSON object), then return
+    /// that key.  By default, nodes have no key.
+    fn key_name(&self) -> Option<&str> {
+        None
+    }
+
     /// Append a debug-style tree view of this node to a [`String`], similar to the output of the
     /// Unix command 'tree'
     fn write_tree_view(&'arena self, string: &mut String) {
@@ -268,6 +331,10 @@ pub trait Ast<'arena>: std::fmt::Debug + Clone + Eq + Default + std::hash::Hash
     /// Generate a new node from a AstClass.
     fn from_class(node_type: Self::Class) -> Self;
 
+    /// Returns the [`AstClass`] of this node, or `None` if it can't be created from a class (e.g.
+    /// JSON fields, which are only created by inserting values into objects)
+    fn class(&self) -> Option<Self::Class>;
+
     /// Returns whether or not a given index and [`char`] is a valid child
     fn is_valid_child(&self, index: usize, node_type: Self::Class) -> bool;
 
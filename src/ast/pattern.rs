@@ -0,0 +1,604 @@
+//! A small regex-like language for searching syntax trees, inspired by
+//! [grasp](http://www.graspjs.com/) and CSS selectors.
+//!
+//! A pattern is made of one or more 'compound selectors', separated by either whitespace (meaning
+//! that the right-hand selector matches a **descendant** of a node matched by the left-hand
+//! selector) or `>` (meaning that the right-hand selector matches a direct **child**).  A node
+//! matches the pattern if it matches the last compound selector, and its ancestors can be matched
+//! to the other selectors.
+//!
+//! Each compound selector is a sequence of (at least one) of the following, with no whitespace in
+//! between.  A node matches a compound selector only if it matches every part of it:
+//! - `*`: matches any node
+//! - `<name>`: matches nodes with the given [`class_name`](Ast::class_name) (e.g. `array`)
+//! - `"<text>"`: matches nodes with the given [`display_name`](Ast::display_name), including the
+//!   quotes (e.g. `"foo"` matches JSON strings containing `foo`)
+//! - `.<key>` or `."<key>"`: matches nodes with the given [`key_name`](Ast::key_name) (e.g.
+//!   `.port` matches JSON fields with the key `port`)
+//! - `$<name>`: matches any node, and 'captures' the node matched by this compound selector under
+//!   the given name (so that it can be used by a replacement template)
+//!
+//! For example, `object > .servers array` matches any array inside the value of a `"servers"`
+//! field of any object, and `.old > $value` matches the children of every `"old"` field and
+//! captures them as `$value`.
+
+use super::Ast;
+use crate::core::Path;
+
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+/// The possible ways that parsing or using a [`Pattern`] could fail
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum PatternError {
+    /// The pattern contained no selectors
+    Empty,
+    /// A `>` wasn't followed by a selector
+    DanglingChildCombinator,
+    /// A `"` was never closed
+    UnclosedQuote,
+    /// A `.` wasn't followed by a key
+    MissingKey,
+    /// A `$` wasn't followed by a variable name
+    MissingCaptureName,
+    /// A replacement template used a variable which the pattern doesn't capture
+    UnknownVariable(String),
+    /// A replacement template referred to a child (e.g. `$x.2`) which doesn't exist
+    NoSuchChild(String),
+    /// The pattern contained a character that can't appear at that point
+    UnexpectedChar(char),
+}
+
+impl std::fmt::Display for PatternError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatternError::Empty => write!(f, "Empty search pattern"),
+            PatternError::DanglingChildCombinator => write!(f, "Expected a selector after '>'"),
+            PatternError::UnclosedQuote => write!(f, "Unclosed '\"' in search pattern"),
+            PatternError::MissingKey => write!(f, "Expected a key after '.'"),
+            PatternError::MissingCaptureName => write!(f, "Expected a variable name after '$'"),
+            PatternError::UnknownVariable(name) => {
+                write!(f, "Variable '${}' isn't captured by the pattern", name)
+            }
+            PatternError::NoSuchChild(reference) => write!(f, "'{}' doesn't exist", reference),
+            PatternError::UnexpectedChar(c) => write!(f, "Unexpected '{}' in search pattern", c),
+        }
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+/// A single requirement that a node must satisfy
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+enum Simple {
+    /// `*`: any node matches
+    Wildcard,
+    /// The node must have a given [`class_name`](Ast::class_name)
+    Class(String),
+    /// The node must have a given [`display_name`](Ast::display_name)
+    Text(String),
+    /// The node must have a given [`key_name`](Ast::key_name)
+    Key(String),
+    /// Any node matches, and is captured under the given name
+    Capture(String),
+}
+
+impl Simple {
+    fn matches<'arena, Node: Ast<'arena>>(&self, node: &Node) -> bool {
+        match self {
+            Simple::Wildcard | Simple::Capture(_) => true,
+            Simple::Class(name) => node.class_name() == name,
+            Simple::Text(text) => node.display_name() == *text,
+            Simple::Key(key) => node.key_name() == Some(key.as_str()),
+        }
+    }
+}
+
+/// A sequence of [`Simple`] selectors, all of which must match the same node
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+struct Compound {
+    parts: Vec<Simple>,
+}
+
+impl Compound {
+    /// Returns the name of the variable which captures the node matched by this `Compound` (if
+    /// any)
+    fn capture_name(&self) -> Option<&str> {
+        self.parts.iter().find_map(|s| match s {
+            Simple::Capture(name) => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
+    fn matches<'arena, Node: Ast<'arena>>(&self, node: &Node) -> bool {
+        self.parts.iter().all(|s| s.matches(node))
+    }
+}
+
+/// The relationship required between the nodes matched by two adjacent [`Compound`]s
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+enum Combinator {
+    /// The right-hand node must be a descendant of the left-hand node
+    Descendant,
+    /// The right-hand node must be a direct child of the left-hand node
+    Child,
+}
+
+/// A single node which matched a [`Pattern`], along with the nodes captured by the pattern's
+/// variables
+#[derive(Debug, Clone)]
+pub struct Match<'arena, Node: Ast<'arena>> {
+    /// The location of the matching node
+    pub path: Path,
+    /// The matching node
+    pub node: &'arena Node,
+    /// The nodes captured by each `$<name>` selector, keyed by name
+    pub captures: HashMap<String, &'arena Node>,
+}
+
+impl<'arena, Node: Ast<'arena>> Match<'arena, Node> {
+    /// Builds the text of a replacement for this match, by filling every `$<name>` in `template`
+    /// with the text of the node captured under that name.  A variable can be followed by
+    /// `.<index>` to refer to a child of the captured node instead (e.g. if `$f` is a JSON field,
+    /// then `$f.1` is its value).  Any `$` which isn't followed by a name is left untouched.
+    pub fn fill_template(
+        &self,
+        template: &str,
+        format_style: &Node::FormatStyle,
+    ) -> Result<String, PatternError> {
+        let mut text = String::with_capacity(template.len());
+        let mut chars = template.char_indices().peekable();
+        while let Some((_, c)) = chars.next() {
+            match chars.peek() {
+                Some((_, next_c)) if c == '$' && is_name_char(*next_c) => {
+                    let name = parse_name(template, &mut chars);
+                    let mut node = *self
+                        .captures
+                        .get(name)
+                        .ok_or_else(|| PatternError::UnknownVariable(name.to_owned()))?;
+                    // Follow any child indices
+                    let mut reference = format!("${}", name);
+                    while let Some(index) = parse_child_index(template, &mut chars) {
+                        reference.push_str(&format!(".{}", index));
+                        node = *node
+                            .children()
+                            .get(index)
+                            .ok_or_else(|| PatternError::NoSuchChild(reference.clone()))?;
+                    }
+                    node.write_text(&mut text, format_style);
+                }
+                _ => text.push(c),
+            }
+        }
+        Ok(text)
+    }
+}
+
+/// A compiled search pattern, which can be used to find nodes in any [`Ast`].  See the
+/// [module-level docs](self) for the syntax.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Pattern {
+    /// The source string that this `Pattern` was parsed from
+    source: String,
+    /// The compound selectors in this pattern.  This is never empty.
+    compounds: Vec<Compound>,
+    /// `combinators[i]` is the relationship between `compounds[i]` and `compounds[i + 1]`
+    combinators: Vec<Combinator>,
+}
+
+impl Pattern {
+    /// Parses a `Pattern` from a string
+    pub fn parse(source: &str) -> Result<Pattern, PatternError> {
+        let mut chars = source.char_indices().peekable();
+        let mut compounds = Vec::new();
+        let mut combinators = Vec::new();
+        // Set to `Some(_)` when we've read a combinator which hasn't yet been followed by a
+        // compound selector
+        let mut pending_combinator: Option<Combinator> = None;
+
+        loop {
+            // Consume whitespace, which is either a descendant combinator or padding around a `>`
+            while chars.peek().is_some_and(|(_, c)| c.is_whitespace()) {
+                chars.next();
+                if !compounds.is_empty() && pending_combinator.is_none() {
+                    pending_combinator = Some(Combinator::Descendant);
+                }
+            }
+            match chars.peek() {
+                None => break,
+                Some((_, '>')) => {
+                    chars.next();
+                    if compounds.is_empty() || pending_combinator == Some(Combinator::Child) {
+                        return Err(PatternError::UnexpectedChar('>'));
+                    }
+                    pending_combinator = Some(Combinator::Child);
+                }
+                Some(_) => {
+                    // `parse_compound` only stops at whitespace, `>` or the end of the string, so
+                    // every compound after the first must be preceded by a combinator
+                    if let Some(combinator) = pending_combinator.take() {
+                        combinators.push(combinator);
+                    }
+                    compounds.push(parse_compound(source, &mut chars)?);
+                }
+            }
+        }
+
+        if pending_combinator == Some(Combinator::Child) {
+            return Err(PatternError::DanglingChildCombinator);
+        }
+        if compounds.is_empty() {
+            return Err(PatternError::Empty);
+        }
+        Ok(Pattern {
+            source: source.to_owned(),
+            compounds,
+            combinators,
+        })
+    }
+
+    /// Returns the string that this `Pattern` was parsed from
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Returns `true` if the last node in `ancestors` matches this `Pattern`.  `ancestors` should
+    /// contain every node from the root of the tree down to the node being tested (as generated
+    /// by [`Path::node_iter`]).
+    pub fn matches<'arena, Node: Ast<'arena>>(&self, ancestors: &[&'arena Node]) -> bool {
+        self.captures(ancestors).is_some()
+    }
+
+    /// If the last node in `ancestors` matches this `Pattern`, then return the nodes captured by
+    /// each of this `Pattern`'s variables.  Otherwise, return `None`.  Like
+    /// [`matches`](Self::matches), `ancestors` should contain every node from the root down to the
+    /// node being tested.
+    pub fn captures<'arena, Node: Ast<'arena>>(
+        &self,
+        ancestors: &[&'arena Node],
+    ) -> Option<HashMap<String, &'arena Node>> {
+        let node = ancestors.last()?;
+        // `self.compounds` is never empty, so we can unwrap
+        if !self.compounds.last().unwrap().matches(*node) {
+            return None;
+        }
+        // `assignment[i]` will contain the index (into `ancestors`) of the node matched by
+        // `self.compounds[i]`
+        let mut assignment = vec![0; self.compounds.len()];
+        *assignment.last_mut().unwrap() = ancestors.len() - 1;
+        if !self.matches_rest(
+            self.compounds.len() - 1,
+            ancestors.len() - 1,
+            ancestors,
+            &mut assignment,
+        ) {
+            return None;
+        }
+        Some(
+            self.compounds
+                .iter()
+                .zip(assignment)
+                .filter_map(|(compound, i)| {
+                    Some((compound.capture_name()?.to_owned(), ancestors[i]))
+                })
+                .collect(),
+        )
+    }
+
+    /// Returns `true` if `compounds[..compound_index]` can be matched against the ancestors of
+    /// `ancestors[node_index]`, given that `compounds[compound_index]` matched
+    /// `ancestors[node_index]`.  If this returns `true`, then `assignment[..compound_index]` will
+    /// contain the indices of the nodes matched by each compound.
+    fn matches_rest<'arena, Node: Ast<'arena>>(
+        &self,
+        compound_index: usize,
+        node_index: usize,
+        ancestors: &[&'arena Node],
+        assignment: &mut [usize],
+    ) -> bool {
+        if compound_index == 0 {
+            return true;
+        }
+        let compound = &self.compounds[compound_index - 1];
+        let try_node = |i: usize, assignment: &mut [usize]| {
+            assignment[compound_index - 1] = i;
+            compound.matches(ancestors[i])
+                && self.matches_rest(compound_index - 1, i, ancestors, assignment)
+        };
+        match self.combinators[compound_index - 1] {
+            Combinator::Child => node_index > 0 && try_node(node_index - 1, assignment),
+            // Try every ancestor, starting with the closest
+            Combinator::Descendant => (0..node_index).rev().any(|i| try_node(i, assignment)),
+        }
+    }
+
+    /// Returns every node in the tree under `root` which matches this `Pattern`.  The matches are
+    /// returned in pre-order (i.e. sorted by [`Path`]).
+    pub fn find_all<'arena, Node: Ast<'arena>>(
+        &self,
+        root: &'arena Node,
+    ) -> Vec<Match<'arena, Node>> {
+        let mut matches = Vec::new();
+        let mut ancestors = vec![root];
+        let mut path = Path::root();
+        self.find_all_recursive(&mut ancestors, &mut path, &mut matches);
+        matches
+    }
+
+    /// Helper function for [`Pattern::find_all`], which searches the subtree of the last node in
+    /// `ancestors` (whose location is `path`).
+    fn find_all_recursive<'arena, Node: Ast<'arena>>(
+        &self,
+        ancestors: &mut Vec<&'arena Node>,
+        path: &mut Path,
+        matches: &mut Vec<Match<'arena, Node>>,
+    ) {
+        let node = *ancestors.last().unwrap();
+        if let Some(captures) = self.captures(ancestors) {
+            matches.push(Match {
+                path: path.clone(),
+                node,
+                captures,
+            });
+        }
+        for (i, child) in node.children().iter().enumerate() {
+            ancestors.push(*child);
+            path.push(i);
+            self.find_all_recursive(ancestors, path, matches);
+            path.pop();
+            ancestors.pop();
+        }
+    }
+}
+
+/// Parses a single compound selector, stopping at whitespace, `>` or the end of the string
+fn parse_compound(
+    source: &str,
+    chars: &mut Peekable<CharIndices<'_>>,
+) -> Result<Compound, PatternError> {
+    let mut parts = Vec::new();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() || c == '>' {
+            break;
+        }
+        chars.next();
+        parts.push(match c {
+            '*' => Simple::Wildcard,
+            '$' => match chars.peek() {
+                Some((_, c)) if is_name_char(*c) => {
+                    Simple::Capture(parse_name(source, chars).to_owned())
+                }
+                _ => return Err(PatternError::MissingCaptureName),
+            },
+            '"' => Simple::Text(format!(r#""{}""#, parse_quoted(source, chars)?)),
+            '.' => match chars.peek() {
+                Some((_, '"')) => {
+                    chars.next();
+                    Simple::Key(parse_quoted(source, chars)?.to_owned())
+                }
+                Some((_, c)) if is_name_char(*c) => {
+                    Simple::Key(parse_name(source, chars).to_owned())
+                }
+                _ => return Err(PatternError::MissingKey),
+            },
+            c if is_name_char(c) => {
+                // The rest of the name immediately follows `c` in `source`
+                let rest = parse_name(source, chars);
+                Simple::Class(source[start..start + c.len_utf8() + rest.len()].to_owned())
+            }
+            c => return Err(PatternError::UnexpectedChar(c)),
+        });
+    }
+    Ok(Compound { parts })
+}
+
+/// Parses the contents of a quoted string (assuming the opening `"` has been consumed), consuming
+/// the closing `"`.
+fn parse_quoted<'s>(
+    source: &'s str,
+    chars: &mut Peekable<CharIndices<'_>>,
+) -> Result<&'s str, PatternError> {
+    let start = chars.peek().map_or(source.len(), |(i, _)| *i);
+    for (i, c) in chars {
+        if c == '"' {
+            return Ok(&source[start..i]);
+        }
+    }
+    Err(PatternError::UnclosedQuote)
+}
+
+/// Parses as many name characters as possible, returning the resulting name
+fn parse_name<'s>(source: &'s str, chars: &mut Peekable<CharIndices<'_>>) -> &'s str {
+    let start = chars.peek().map_or(source.len(), |(i, _)| *i);
+    let mut end = start;
+    while let Some(&(i, c)) = chars.peek() {
+        if !is_name_char(c) {
+            break;
+        }
+        chars.next();
+        end = i + c.len_utf8();
+    }
+    &source[start..end]
+}
+
+/// Parses a `.<index>` suffix off a variable in a template, returning `None` (and consuming
+/// nothing) if the next characters aren't a `.` followed by a digit.
+fn parse_child_index(source: &str, chars: &mut Peekable<CharIndices<'_>>) -> Option<usize> {
+    let mut lookahead = chars.clone();
+    match (lookahead.next(), lookahead.peek()) {
+        (Some((_, '.')), Some((start, d))) if d.is_ascii_digit() => {
+            let start = *start;
+            let mut end = start;
+            while let Some(&(i, d)) = lookahead.peek() {
+                if !d.is_ascii_digit() {
+                    break;
+                }
+                lookahead.next();
+                end = i + 1;
+            }
+            *chars = lookahead;
+            // A huge index can't refer to a real child, so treat it like any other missing child
+            Some(source[start..end].parse().unwrap_or(usize::MAX))
+        }
+        _ => None,
+    }
+}
+
+/// Returns `true` if `c` can appear in a class name, unquoted key or variable name
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Pattern, PatternError};
+    use crate::arena::Arena;
+    use crate::ast::json::{add_value_to_arena, JsonFormat};
+    use crate::ast::Ast;
+    use crate::core::Path;
+
+    use serde_json::json;
+
+    #[test]
+    fn parse_errors() {
+        for (source, expected_err) in &[
+            ("", PatternError::Empty),
+            ("   ", PatternError::Empty),
+            ("array >", PatternError::DanglingChildCombinator),
+            ("> array", PatternError::UnexpectedChar('>')),
+            ("array > > true", PatternError::UnexpectedChar('>')),
+            (r#""foo"#, PatternError::UnclosedQuote),
+            ("object .", PatternError::MissingKey),
+            ("array+", PatternError::UnexpectedChar('+')),
+            ("array > $", PatternError::MissingCaptureName),
+        ] {
+            println!("Testing {:?}", source);
+            assert_eq!(Pattern::parse(source), Err(expected_err.clone()));
+        }
+    }
+
+    #[test]
+    fn wildcard() {
+        let arena = Arena::new();
+        let root = add_value_to_arena(json!([true, {"foo": [null]}]), &arena);
+        let paths = Pattern::parse("*")
+            .unwrap()
+            .find_all(root)
+            .into_iter()
+            .map(|m| m.path)
+            .collect::<Vec<_>>();
+        // Every node should be matched exactly once, in pre-order
+        assert_eq!(
+            paths,
+            vec![
+                Path::root(),
+                Path::from_vec(vec![0]),
+                Path::from_vec(vec![1]),
+                Path::from_vec(vec![1, 0]),
+                Path::from_vec(vec![1, 0, 0]),
+                Path::from_vec(vec![1, 0, 1]),
+                Path::from_vec(vec![1, 0, 1, 0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn find_all() {
+        let arena = Arena::new();
+        let root = add_value_to_arena(
+            json!({
+                "servers": [{"port": "80", "tls": false}, {"port": "443", "tls": true}],
+                "port": [true]
+            }),
+            &arena,
+        );
+        let tests: &[(&str, &[&[usize]])] = &[
+            // Class names
+            ("array", &[&[0, 1], &[1, 1]]),
+            ("true", &[&[0, 1, 1, 1, 1], &[1, 1, 0]]),
+            // Display text
+            (r#""443""#, &[&[0, 1, 1, 0, 1]]),
+            // Keys
+            (".port", &[&[0, 1, 0, 0], &[0, 1, 1, 0], &[1]]),
+            (r#"."tls""#, &[&[0, 1, 0, 1], &[0, 1, 1, 1]]),
+            // Compound selectors
+            ("field.port", &[&[0, 1, 0, 0], &[0, 1, 1, 0], &[1]]),
+            ("string.port", &[]),
+            // Descendant combinators
+            (".servers true", &[&[0, 1, 1, 1, 1]]),
+            ("array array", &[]),
+            // Child combinators
+            ("array > object", &[&[0, 1, 0], &[0, 1, 1]]),
+            ("object > array", &[]),
+            (".port > array > true", &[&[1, 1, 0]]),
+            (
+                "  object   >   field.port>*  ",
+                &[
+                    &[0, 1, 0, 0, 0],
+                    &[0, 1, 0, 0, 1],
+                    &[0, 1, 1, 0, 0],
+                    &[0, 1, 1, 0, 1],
+                    &[1, 0],
+                    &[1, 1],
+                ],
+            ),
+        ];
+        for (source, expected_paths) in tests {
+            println!("Testing {:?}", source);
+            let paths = Pattern::parse(source)
+                .unwrap()
+                .find_all(root)
+                .into_iter()
+                .map(|m| m.path)
+                .collect::<Vec<_>>();
+            let expected_paths = expected_paths
+                .iter()
+                .map(|v| Path::from_vec(v.to_vec()))
+                .collect::<Vec<_>>();
+            assert_eq!(paths, expected_paths);
+        }
+    }
+
+    #[test]
+    fn captures() {
+        let arena = Arena::new();
+        let root = add_value_to_arena(json!([{"a": [true, null]}, {"b": [false]}]), &arena);
+        let pattern = Pattern::parse("object$obj .a > array > $x").unwrap();
+        let matches = pattern.find_all(root);
+        assert_eq!(
+            matches.iter().map(|m| m.path.clone()).collect::<Vec<_>>(),
+            vec![
+                Path::from_vec(vec![0, 0, 1, 0]),
+                Path::from_vec(vec![0, 0, 1, 1])
+            ]
+        );
+        for m in &matches {
+            assert_eq!(m.captures.len(), 2);
+            assert!(std::ptr::eq(m.captures["obj"], root.children()[0]));
+            assert!(std::ptr::eq(m.captures["x"], m.node));
+        }
+    }
+
+    #[test]
+    fn fill_template() {
+        let arena = Arena::new();
+        let root = add_value_to_arena(json!({"a": [true, null], "b": false}), &arena);
+        let matches = Pattern::parse(".a > $x").unwrap().find_all(root);
+        let texts = matches
+            .iter()
+            .map(|m| m.fill_template("[$x, $ $x.0]", &JsonFormat::Compact))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            texts,
+            vec![
+                Err(PatternError::NoSuchChild("$x.0".to_owned())),
+                Ok("[[true, null], $ true]".to_owned())
+            ]
+        );
+        assert_eq!(
+            matches[0].fill_template("$y", &JsonFormat::Compact),
+            Err(PatternError::UnknownVariable("y".to_owned()))
+        );
+    }
+}
@@ -1,11 +1,17 @@
-//! Module to hold all user-configurable parameters, until we find a better way to handle
-//! configuration
+//! Module to hold all user-configurable parameters, along with the defaults that are used when
+//! the user's config file doesn't set them
 
-use crate::ast::display_token::{syntax_category::*, SyntaxCategory};
-use crate::core::Direction;
-use crate::editor::normal_mode::CmdType;
+use crate::ast::display_token::{syntax_category::*, SyntaxCategory, DEFAULT_INDENT_WIDTH};
+use crate::core::{parse_key, parse_keys, Direction, KeyDisplay, Side};
+use crate::editor::normal_mode::{CmdType, Operator};
 
-use crossterm::event::KeyCode;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crossterm::event::KeyEvent;
+use serde_json::Value;
 use tui::style::Color;
 
 /* DEBUG FLAGS */
@@ -18,6 +24,28 @@ pub const DEBUG_HIGHLIGHTING: bool = false;
 
 /* COLOR SCHEME */
 
+/// The names of the [`Color`]s which can be used in the config file, as well as `"#rrggbb"` and
+/// the numbers `0` to `255` of the terminal's palette
+const COLOR_NAMES: [(&str, Color); 17] = [
+    ("reset", Color::Reset),
+    ("black", Color::Black),
+    ("red", Color::Red),
+    ("green", Color::Green),
+    ("yellow", Color::Yellow),
+    ("blue", Color::Blue),
+    ("magenta", Color::Magenta),
+    ("cyan", Color::Cyan),
+    ("gray", Color::Gray),
+    ("dark-gray", Color::DarkGray),
+    ("light-red", Color::LightRed),
+    ("light-green", Color::LightGreen),
+    ("light-yellow", Color::LightYellow),
+    ("light-blue", Color::LightBlue),
+    ("light-magenta", Color::LightMagenta),
+    ("light-cyan", Color::LightCyan),
+    ("white", Color::White),
+];
+
 /// A mapping from syntax highlighting categories to terminal [`Color`]s
 pub type ColorScheme = std::collections::HashMap<SyntaxCategory, Color>;
 
@@ -38,30 +66,399 @@ pub fn default_color_scheme() -> ColorScheme {
     }
 }
 
+/// Reads a [`Color`] from the config file, which is either a name from [`COLOR_NAMES`], a hex
+/// code like `"#ff8000"` or the index of a color in the terminal's palette
+fn parse_color(json: &Value) -> Result<Color, String> {
+    if let Some(index) = json.as_u64() {
+        return u8::try_from(index)
+            .map(Color::Indexed)
+            .map_err(|_| format!("color index {} is bigger than 255", index));
+    }
+    let name = json
+        .as_str()
+        .ok_or_else(|| format!("expected a color name or number, found {}", json))?;
+    if let Some(hex) = name.strip_prefix('#') {
+        let component = |i: usize| {
+            hex.get(i..i + 2)
+                .and_then(|s| u8::from_str_radix(s, 16).ok())
+        };
+        return match (hex.len(), component(0), component(2), component(4)) {
+            (6, Some(r), Some(g), Some(b)) => Ok(Color::Rgb(r, g, b)),
+            _ => Err(format!("invalid hex color '{}' (expected '#rrggbb')", name)),
+        };
+    }
+    COLOR_NAMES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, color)| *color)
+        .ok_or_else(|| {
+            let names = COLOR_NAMES.iter().map(|(n, _)| *n).collect::<Vec<_>>();
+            format!(
+                "unknown color '{}' (expected one of {})",
+                name,
+                names.join(", ")
+            )
+        })
+}
+
 /* KEY BINDINGS */
 
-/// Mapping of keys to keystrokes.
-/// Shortcut definition, also allows us to change the type if needed.
-pub type KeyMap = std::collections::HashMap<KeyCode, CmdType>;
+/// A mapping from sequences of keystrokes to the [`CmdType`]s that they're bound to.  The bindings
+/// are stored in a trie, so that keys can be recognised as the start of a binding before the
+/// whole binding has been typed (e.g. `g` is the start of `g g`).
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct KeyMap {
+    /// The command bound to the keys which lead to this node of the trie
+    cmd: Option<CmdType>,
+    /// The sub-tries of the bindings which continue with each key
+    next: HashMap<KeyEvent, KeyMap>,
+}
+
+impl KeyMap {
+    /// Binds a sequence of keys to a [`CmdType`].  Commands run as soon as their keys have been
+    /// typed, so this removes any bindings which start with `keys` (and any binding that `keys`
+    /// starts with), since they could never be typed alongside this one.  Binding an empty
+    /// sequence does nothing.
+    pub fn bind(&mut self, keys: &[KeyEvent], cmd: CmdType) {
+        if keys.is_empty() {
+            return;
+        }
+        let mut node = self;
+        for key in keys {
+            node.cmd = None;
+            node = node.next.entry(*key).or_default();
+        }
+        node.cmd = Some(cmd);
+        node.next.clear();
+    }
+
+    /// Removes the binding of a sequence of keys, along with all the bindings which start with
+    /// those keys
+    pub fn unbind(&mut self, keys: &[KeyEvent]) {
+        if let Some((last, first)) = keys.split_last() {
+            if let Some(node) = self.after_keys_mut(first) {
+                node.next.remove(last);
+            }
+            self.remove_empty_nodes();
+        }
+    }
+
+    /// Returns the [`CmdType`] bound to exactly the given keys
+    pub fn get(&self, keys: &[KeyEvent]) -> Option<CmdType> {
+        self.after_keys(keys)?.cmd
+    }
+
+    /// Returns the command bound to the keys that lead to this part of the trie, if any.  For
+    /// the whole `KeyMap`, this is always `None`.
+    pub fn cmd(&self) -> Option<CmdType> {
+        self.cmd
+    }
+
+    /// Returns the part of the trie which contains the bindings that start with `key`, or `None`
+    /// if no bindings start with `key`
+    pub fn after(&self, key: KeyEvent) -> Option<&KeyMap> {
+        self.next.get(&key)
+    }
+
+    /// Returns the part of the trie which contains the bindings that start with `keys`
+    pub fn after_keys(&self, keys: &[KeyEvent]) -> Option<&KeyMap> {
+        keys.iter().try_fold(self, |node, key| node.after(*key))
+    }
+
+    fn after_keys_mut(&mut self, keys: &[KeyEvent]) -> Option<&mut KeyMap> {
+        keys.iter()
+            .try_fold(self, |node, key| node.next.get_mut(key))
+    }
+
+    /// Removes the parts of the trie that don't lead to any bindings, returning `true` if this
+    /// whole node is empty
+    fn remove_empty_nodes(&mut self) -> bool {
+        self.next.retain(|_, node| !node.remove_empty_nodes());
+        self.cmd.is_none() && self.next.is_empty()
+    }
+
+    /// Returns every binding in this `KeyMap`, in no particular order.  The keys are relative to
+    /// this part of the trie, so for the result of [`after_keys`](Self::after_keys) they don't
+    /// include the keys that have already been typed.
+    pub fn bindings(&self) -> Vec<(Vec<KeyEvent>, CmdType)> {
+        let mut bindings = Vec::new();
+        self.add_bindings(&mut Vec::new(), &mut bindings);
+        bindings
+    }
+
+    fn add_bindings(&self, keys: &mut Vec<KeyEvent>, bindings: &mut Vec<(Vec<KeyEvent>, CmdType)>) {
+        if let Some(cmd) = self.cmd {
+            bindings.push((keys.clone(), cmd));
+        }
+        for (key, node) in &self.next {
+            keys.push(*key);
+            node.add_bindings(keys, bindings);
+            keys.pop();
+        }
+    }
+}
 
 /// Generates a 'canonical' [`KeyMap`].  These keybindings will be very similar to those of Vim.
+/// Each binding is written as in the config file, i.e. as keys separated by spaces.
 pub fn default_keymap() -> KeyMap {
-    hmap::hmap! {
-        KeyCode::Char('q') => CmdType::Quit,
-        KeyCode::Char('w') => CmdType::Write,
-        KeyCode::Char('i') => CmdType::InsertBefore,
-        KeyCode::Char('a') => CmdType::InsertAfter,
-        KeyCode::Char('o') => CmdType::InsertChild,
-        KeyCode::Char('r') => CmdType::Replace,
-        KeyCode::Char('x') => CmdType::Delete,
-        KeyCode::Char('c') => CmdType::MoveCursor(Direction::Down),
-        KeyCode::Char('p') => CmdType::MoveCursor(Direction::Up),
-        KeyCode::Char('h') => CmdType::MoveCursor(Direction::Prev),
-        KeyCode::Char('j') => CmdType::MoveCursor(Direction::Next),
-        KeyCode::Char('k') => CmdType::MoveCursor(Direction::Prev),
-        KeyCode::Char('l') => CmdType::MoveCursor(Direction::Next),
-        KeyCode::Char('u') => CmdType::Undo,
-        KeyCode::Char('R') => CmdType::Redo
+    let bindings = [
+        ("q", CmdType::Quit),
+        ("w", CmdType::Write),
+        ("Z", CmdType::Exit),
+        ("i", CmdType::InsertBefore),
+        ("a", CmdType::InsertAfter),
+        ("o", CmdType::InsertChild),
+        ("r", CmdType::Replace),
+        ("x", CmdType::Delete),
+        ("d", CmdType::Operator(Operator::Delete)),
+        ("y", CmdType::Operator(Operator::Yank)),
+        ("P", CmdType::Put),
+        ("<", CmdType::MoveNode(Side::Prev)),
+        (">", CmdType::MoveNode(Side::Next)),
+        ("{", CmdType::Slurp(Side::Prev)),
+        ("}", CmdType::Slurp(Side::Next)),
+        ("(", CmdType::Barf(Side::Prev)),
+        (")", CmdType::Barf(Side::Next)),
+        ("c", CmdType::Motion(Direction::Down)),
+        ("p", CmdType::Motion(Direction::Up)),
+        ("h", CmdType::Motion(Direction::Prev)),
+        ("j", CmdType::Motion(Direction::Next)),
+        ("k", CmdType::Motion(Direction::Prev)),
+        ("l", CmdType::Motion(Direction::Next)),
+        ("H", CmdType::Motion(Direction::FirstSibling)),
+        ("L", CmdType::Motion(Direction::LastSibling)),
+        ("^", CmdType::Motion(Direction::Root)),
+        ("g g", CmdType::Motion(Direction::Root)),
+        ("J", CmdType::Motion(Direction::PreorderNext)),
+        ("K", CmdType::Motion(Direction::PreorderPrev)),
+        ("]", CmdType::Motion(Direction::NextLeaf)),
+        ("[", CmdType::Motion(Direction::PrevLeaf)),
+        ("*", CmdType::Motion(Direction::NextOfClass)),
+        ("#", CmdType::Motion(Direction::PrevOfClass)),
+        ("C", CmdType::Motion(Direction::NthChild)),
+        ("/", CmdType::Search),
+        (":", CmdType::CommandLine),
+        ("n", CmdType::NextMatch(Side::Next)),
+        ("N", CmdType::NextMatch(Side::Prev)),
+        ("m", CmdType::SetMark),
+        ("'", CmdType::MoveToMark),
+        ("^o", CmdType::Jump(Side::Prev)),
+        ("<Tab>", CmdType::Jump(Side::Next)),
+        ("D", CmdType::HalfPage(Side::Next)),
+        ("U", CmdType::HalfPage(Side::Prev)),
+        ("z", CmdType::View),
+        ("s", CmdType::SearchReplace),
+        ("Q", CmdType::RecordMacro),
+        ("@", CmdType::PlayMacro),
+        (".", CmdType::Repeat),
+        ("u", CmdType::Undo),
+        ("R", CmdType::Redo),
+        ("^r", CmdType::Redo),
+        ("?", CmdType::Help),
+    ];
+    let mut keymap = KeyMap::default();
+    for (keys, cmd) in bindings {
+        keymap.bind(&parse_keys(keys).expect("Invalid default key binding"), cmd);
+    }
+    keymap
+}
+
+/* FILES */
+
+/// Returns the directory where Sapling's configuration files are stored.  This is
+/// `$XDG_CONFIG_HOME/sapling`, falling back to `$HOME/.config/sapling`, or `None` if neither
+/// variable is set.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("sapling"))
+}
+
+/// Returns the path of the file which the user's [`Config`] is read from
+fn config_file() -> Option<PathBuf> {
+    Some(config_dir()?.join("config.json"))
+}
+
+/// Reads and parses a JSON file, returning a message describing the problem if either step fails
+fn read_json_file(path: &Path) -> Result<Value, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&contents).map_err(|e| e.to_string())
+}
+
+/* MACROS */
+
+/// A mapping from register names to the keystrokes of the macros stored in them
+pub type Macros = HashMap<char, Vec<KeyEvent>>;
+
+/// Returns the path of the file where macros are saved
+fn macro_file() -> Option<PathBuf> {
+    Some(config_dir()?.join("macros.json"))
+}
+
+/// Converts [`Macros`] into JSON, where each register maps to an array of keystroke strings (in
+/// the format of [`KeyDisplay::compact_string`]).  We use one string per keystroke, since
+/// concatenated keystrokes can be ambiguous (e.g. `^r` vs `^` followed by `r`).
+fn macros_to_json(macros: &Macros) -> Value {
+    // Sort the registers so that the file doesn't change order every time it's saved
+    let mut registers = macros.keys().collect::<Vec<_>>();
+    registers.sort();
+    Value::Object(
+        registers
+            .into_iter()
+            .map(|register| {
+                let keys = macros[register]
+                    .iter()
+                    .map(|k| Value::String(k.compact_string().into_owned()))
+                    .collect();
+                (register.to_string(), Value::Array(keys))
+            })
+            .collect(),
+    )
+}
+
+/// Reads [`Macros`] from the JSON generated by [`macros_to_json`], returning `None` if the JSON
+/// is malformed.
+fn macros_from_json(json: &Value) -> Option<Macros> {
+    let mut macros = Macros::new();
+    for (register, keys) in json.as_object()? {
+        let mut register_chars = register.chars();
+        let register = match (register_chars.next(), register_chars.next()) {
+            (Some(c), None) => c,
+            _ => return None,
+        };
+        let keys = keys
+            .as_array()?
+            .iter()
+            .map(|k| parse_key(k.as_str()?))
+            .collect::<Option<Vec<_>>>()?;
+        macros.insert(register, keys);
+    }
+    Some(macros)
+}
+
+/// Loads the macros saved by [`save_macros`].  Any problems are logged, and cause no macros to be
+/// loaded.
+pub fn load_macros() -> Macros {
+    let path = match macro_file() {
+        Some(path) if path.exists() => path,
+        _ => return Macros::new(),
+    };
+    match read_json_file(&path).map(|json| macros_from_json(&json)) {
+        Ok(Some(macros)) => macros,
+        Ok(None) => {
+            log::warn!("Ignoring malformed macros in {:?}", path);
+            Macros::new()
+        }
+        Err(e) => {
+            log::warn!("Couldn't read macros from {:?}: {}", path, e);
+            Macros::new()
+        }
+    }
+}
+
+/// Saves macros to the config directory, so that they can be loaded by [`load_macros`] the next
+/// time Sapling starts.  Any problems are logged.
+pub fn save_macros(macros: &Macros) {
+    let path = match macro_file() {
+        Some(path) => path,
+        None => {
+            log::warn!("Can't find a config directory to save macros to");
+            return;
+        }
+    };
+    let result = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|()| std::fs::write(&path, macros_to_json(macros).to_string()));
+    if let Err(e) = result {
+        log::warn!("Couldn't save macros to {:?}: {}", path, e);
+    }
+}
+
+/* STATUS LINE */
+
+/// A piece of information which can be shown in the status line
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum StatusItem {
+    /// The mode that Sapling is in (e.g. `NORMAL`)
+    Mode,
+    /// The name of the file being edited
+    FileName,
+    /// `[+]` if the buffer has unsaved changes, otherwise nothing
+    Dirty,
+    /// The ancestors of the cursor, from the root down to the cursor
+    Breadcrumb,
+    /// The class name of the cursor (e.g. `array`)
+    ClassName,
+    /// How many changes have been made, and how many of those haven't been undone
+    UndoPosition,
+    /// The register of the macro being recorded, or nothing if no macro is being recorded
+    Recording,
+    /// The keystrokes of the command that is being typed
+    Keystrokes,
+}
+
+impl StatusItem {
+    /// Every `StatusItem`, in the order that they're listed in error messages
+    const ALL: [StatusItem; 8] = [
+        StatusItem::Mode,
+        StatusItem::FileName,
+        StatusItem::Dirty,
+        StatusItem::Breadcrumb,
+        StatusItem::ClassName,
+        StatusItem::UndoPosition,
+        StatusItem::Recording,
+        StatusItem::Keystrokes,
+    ];
+
+    /// Returns the name used to refer to this `StatusItem` in the config file (e.g. `"file-name"`)
+    pub fn name(&self) -> &'static str {
+        match self {
+            StatusItem::Mode => "mode",
+            StatusItem::FileName => "file-name",
+            StatusItem::Dirty => "dirty",
+            StatusItem::Breadcrumb => "breadcrumb",
+            StatusItem::ClassName => "class-name",
+            StatusItem::UndoPosition => "undo-position",
+            StatusItem::Recording => "recording",
+            StatusItem::Keystrokes => "keystrokes",
+        }
+    }
+
+    /// Returns the `StatusItem` with a given [`name`](Self::name), or `None` if there isn't one
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|item| item.name() == name)
+    }
+}
+
+/// Which [`StatusItem`]s are shown on each side of the status line, in order from left to right
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct StatusLine {
+    /// The items shown on the left of the status line.  These are replaced by the prompt whilst
+    /// the user is typing a search pattern or a command.
+    pub left: Vec<StatusItem>,
+    /// The items shown on the right of the status line
+    pub right: Vec<StatusItem>,
+}
+
+impl Default for StatusLine {
+    fn default() -> Self {
+        StatusLine {
+            left: vec![
+                StatusItem::Mode,
+                StatusItem::FileName,
+                StatusItem::Dirty,
+                StatusItem::Breadcrumb,
+            ],
+            right: vec![
+                StatusItem::Recording,
+                StatusItem::Keystrokes,
+                StatusItem::ClassName,
+                StatusItem::UndoPosition,
+            ],
+        }
     }
 }
 
@@ -70,10 +467,24 @@ pub fn default_keymap() -> KeyMap {
 /// A struct to hold the entire run-time configuration of Sapling
 #[derive(Debug, Clone)]
 pub struct Config {
-    /// A mapping between [`char`]s and [`CmdType`]s
+    /// A mapping from key sequences to [`CmdType`]s
     pub keymap: KeyMap,
     /// The current [`ColorScheme`] of Sapling
     pub color_scheme: ColorScheme,
+    /// The number of lines which are kept visible above and below the cursor when scrolling
+    pub scroll_margin: usize,
+    /// The number of lines scrolled by each step of the mouse wheel
+    pub wheel_scroll_lines: usize,
+    /// The layout of the status line
+    pub status_line: StatusLine,
+    /// How often the swap files of buffers with unsaved changes are updated.  They are also
+    /// updated whenever the user stops typing for this long.
+    pub swap_interval: Duration,
+    /// The name of the format style that files are opened with (e.g. `"pretty"`).  This is parsed
+    /// by the language being edited, since every language has different styles.
+    pub format_style: String,
+    /// The number of spaces in each level of indentation
+    pub indent_width: usize,
 }
 
 impl Default for Config {
@@ -81,6 +492,397 @@ impl Default for Config {
         Config {
             keymap: default_keymap(),
             color_scheme: default_color_scheme(),
+            scroll_margin: 3,
+            wheel_scroll_lines: 3,
+            status_line: StatusLine::default(),
+            swap_interval: Duration::from_secs(4),
+            format_style: "pretty".to_owned(),
+            indent_width: DEFAULT_INDENT_WIDTH,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the user's configuration from `config.json` in the [`config_dir`], using the
+    /// defaults for anything which the file doesn't set.  Invalid entries are logged and
+    /// ignored, so that a mistake in the config file never stops Sapling from starting.
+    pub fn load() -> Config {
+        let path = match config_file() {
+            Some(path) if path.exists() => path,
+            _ => return Config::default(),
+        };
+        match read_json_file(&path) {
+            Ok(json) => {
+                let (config, errors) = Config::from_json(&json);
+                for e in errors {
+                    log::warn!("Ignoring invalid config in {:?}: {}", path, e);
+                }
+                config
+            }
+            Err(e) => {
+                log::warn!("Couldn't read config from {:?}: {}", path, e);
+                Config::default()
+            }
+        }
+    }
+
+    /// Builds a `Config` from the contents of a config file, starting from the default `Config`.
+    /// Along with the `Config`, this returns a message for each entry that was ignored because it
+    /// was invalid.
+    fn from_json(json: &Value) -> (Config, Vec<String>) {
+        let mut config = Config::default();
+        let mut errors = Vec::new();
+        let options = match json.as_object() {
+            Some(options) => options,
+            None => {
+                errors.push(format!("expected an object of options, found {}", json));
+                return (config, errors);
+            }
+        };
+        for (option, value) in options {
+            let result = match option.as_str() {
+                "keymap" => update_keymap(&mut config.keymap, value, &mut errors),
+                "colors" => update_color_scheme(&mut config.color_scheme, value, &mut errors),
+                "format" => value
+                    .as_str()
+                    .map(|style| config.format_style = style.to_owned())
+                    .ok_or_else(|| format!("expected a string, found {}", value)),
+                "indent_width" => as_whole_number(value).map(|width| config.indent_width = width),
+                "scroll_margin" => {
+                    as_whole_number(value).map(|margin| config.scroll_margin = margin)
+                }
+                "wheel_scroll_lines" => {
+                    as_whole_number(value).map(|lines| config.wheel_scroll_lines = lines)
+                }
+                "swap_interval" => value
+                    .as_f64()
+                    .filter(|secs| secs.is_finite() && *secs > 0.0)
+                    .map(|secs| config.swap_interval = Duration::from_secs_f64(secs))
+                    .ok_or_else(|| {
+                        format!("expected a positive number of seconds, found {}", value)
+                    }),
+                "status_line" => update_status_line(&mut config.status_line, value, &mut errors),
+                _ => Err("unknown option".to_owned()),
+            };
+            if let Err(e) = result {
+                errors.push(format!("'{}': {}", option, e));
+            }
+        }
+        (config, errors)
+    }
+}
+
+/// Reads a non-negative integer from the config file
+fn as_whole_number(value: &Value) -> Result<usize, String> {
+    value
+        .as_u64()
+        .map(|n| n as usize)
+        .ok_or_else(|| format!("expected a whole number, found {}", value))
+}
+
+/// Applies the `"status_line"` entry of the config file, which can set the `"left"` and
+/// `"right"` sides of the status line to arrays of [`StatusItem`] [`name`](StatusItem::name)s.
+/// Sides which aren't mentioned keep their default items.
+fn update_status_line(
+    status_line: &mut StatusLine,
+    json: &Value,
+    errors: &mut Vec<String>,
+) -> Result<(), String> {
+    let sides = json
+        .as_object()
+        .ok_or_else(|| format!("expected an object with 'left' and 'right', found {}", json))?;
+    for (side_name, items_json) in sides {
+        let side = match side_name.as_str() {
+            "left" => &mut status_line.left,
+            "right" => &mut status_line.right,
+            _ => {
+                errors.push(format!(
+                    "'status_line': unknown side '{}' (expected left or right)",
+                    side_name
+                ));
+                continue;
+            }
+        };
+        let items_json = match items_json.as_array() {
+            Some(items) => items,
+            None => {
+                errors.push(format!(
+                    "'status_line': '{}': expected an array of items, found {}",
+                    side_name, items_json
+                ));
+                continue;
+            }
+        };
+        side.clear();
+        for item in items_json {
+            match item.as_str().and_then(StatusItem::from_name) {
+                Some(item) => side.push(item),
+                None => errors.push(format!(
+                    "'status_line': '{}': unknown item {} (expected one of {})",
+                    side_name,
+                    item,
+                    StatusItem::ALL
+                        .iter()
+                        .map(StatusItem::name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Applies the `"keymap"` entry of the config file, which maps sequences of keys (separated by
+/// spaces, as read by [`parse_keys`]) to the [`name`](CmdType::name)s of the commands they're
+/// bound to.  Binding keys to `null` unbinds them, and keys which aren't mentioned keep their
+/// default bindings unless a new binding overlaps with them (see [`KeyMap::bind`]).
+fn update_keymap(
+    keymap: &mut KeyMap,
+    json: &Value,
+    errors: &mut Vec<String>,
+) -> Result<(), String> {
+    let bindings = json
+        .as_object()
+        .ok_or_else(|| format!("expected an object of key bindings, found {}", json))?;
+    for (key_string, name) in bindings {
+        let keys = match parse_keys(key_string) {
+            Some(keys) => keys,
+            None => {
+                errors.push(format!("'keymap': '{}': invalid keys", key_string));
+                continue;
+            }
+        };
+        match name {
+            Value::Null => keymap.unbind(&keys),
+            Value::String(name) => match CmdType::from_name(name) {
+                Some(cmd) => keymap.bind(&keys, cmd),
+                None => errors.push(format!(
+                    "'keymap': '{}': unknown command '{}'",
+                    key_string, name
+                )),
+            },
+            _ => errors.push(format!(
+                "'keymap': '{}': expected a command name or null, found {}",
+                key_string, name
+            )),
+        }
+    }
+    Ok(())
+}
+
+/// Applies the `"colors"` entry of the config file, which maps [`SyntaxCategory`]s to colors (as
+/// read by [`parse_color`])
+fn update_color_scheme(
+    color_scheme: &mut ColorScheme,
+    json: &Value,
+    errors: &mut Vec<String>,
+) -> Result<(), String> {
+    let colors = json
+        .as_object()
+        .ok_or_else(|| format!("expected an object of colors, found {}", json))?;
+    // Only the categories in the default color scheme are known, and `SyntaxCategory`s have to be
+    // `'static`, so we use the keys of the default scheme rather than the strings from the file
+    let categories = default_color_scheme().into_keys().collect::<Vec<_>>();
+    for (category_string, color) in colors {
+        let category = match categories.iter().find(|c| **c == category_string) {
+            Some(category) => *category,
+            None => {
+                let mut categories = categories.clone();
+                categories.sort_unstable();
+                errors.push(format!(
+                    "'colors': unknown syntax category '{}' (expected one of {})",
+                    category_string,
+                    categories.join(", ")
+                ));
+                continue;
+            }
+        };
+        match parse_color(color) {
+            Ok(color) => {
+                color_scheme.insert(category, color);
+            }
+            Err(e) => errors.push(format!("'colors': '{}': {}", category_string, e)),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        default_keymap, macros_from_json, macros_to_json, Config, KeyMap, Macros, StatusItem,
+        StatusLine,
+    };
+    use crate::ast::display_token::syntax_category;
+    use crate::core::{parse_keys, Direction, Side};
+    use crate::editor::normal_mode::CmdType;
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    use serde_json::json;
+    use std::time::Duration;
+    use tui::style::Color;
+
+    #[test]
+    fn keymap_trie() {
+        let keys = |s| parse_keys(s).unwrap();
+        let mut keymap = KeyMap::default();
+        keymap.bind(&keys("g g"), CmdType::Undo);
+        keymap.bind(&keys("g d"), CmdType::Redo);
+        keymap.bind(&keys("^r"), CmdType::Redo);
+        assert_eq!(keymap.get(&keys("g g")), Some(CmdType::Undo));
+        assert_eq!(keymap.get(&keys("g")), None);
+        assert_eq!(keymap.get(&keys("r")), None);
+        // `g` is the start of two bindings
+        let after_g = keymap.after(keys("g")[0]).unwrap();
+        assert_eq!(after_g.cmd(), None);
+        let mut bindings = after_g.bindings();
+        bindings.sort_by_key(|(_, cmd)| cmd.name());
+        assert_eq!(
+            bindings,
+            vec![(keys("d"), CmdType::Redo), (keys("g"), CmdType::Undo)]
+        );
+        assert_eq!(
+            keymap.after_keys(&keys("^r")).unwrap().cmd(),
+            Some(CmdType::Redo)
+        );
+        assert!(keymap.after_keys(&keys("g x")).is_none());
+
+        // Binding a prefix of other bindings replaces them, since they could never be typed
+        let mut shadowed = keymap.clone();
+        shadowed.bind(&keys("g"), CmdType::Help);
+        assert_eq!(shadowed.get(&keys("g")), Some(CmdType::Help));
+        assert_eq!(shadowed.bindings().len(), 2);
+        // ...and so does extending a binding
+        shadowed.bind(&keys("g x"), CmdType::Quit);
+        assert_eq!(shadowed.get(&keys("g")), None);
+        assert_eq!(shadowed.get(&keys("g x")), Some(CmdType::Quit));
+
+        // Unbinding removes every binding which starts with the keys
+        let mut unbound = keymap.clone();
+        unbound.unbind(&keys("g d"));
+        assert_eq!(unbound.bindings().len(), 2);
+        unbound.unbind(&keys("g"));
+        assert_eq!(unbound.bindings(), vec![(keys("^r"), CmdType::Redo)]);
+        unbound.unbind(&keys("^r"));
+        assert_eq!(unbound, KeyMap::default());
+    }
+
+    #[test]
+    fn config_from_json() {
+        let (config, errors) = Config::from_json(&json!({
+            "keymap": {"x": "redo", "R": null, "<F5>": "jump-prev", "^w <A-h>": "help"},
+            "colors": {"const": "light-blue", "ident": "#ff8000", "comment": 244},
+            "format": "compact",
+            "indent_width": 2,
+            "scroll_margin": 0,
+            "wheel_scroll_lines": 5,
+            "swap_interval": 0.5,
+            "status_line": {"right": ["keystrokes", "undo-position"]}
+        }));
+        assert!(errors.is_empty());
+        let get = |keys| config.keymap.get(&parse_keys(keys).unwrap());
+        assert_eq!(get("x"), Some(CmdType::Redo));
+        assert_eq!(get("R"), None);
+        assert_eq!(get("<F5>"), Some(CmdType::Jump(Side::Prev)));
+        assert_eq!(get("^w <A-h>"), Some(CmdType::Help));
+        // Keys which aren't mentioned keep their default bindings
+        assert_eq!(get("u"), Some(CmdType::Undo));
+        assert_eq!(get("g g"), Some(CmdType::Motion(Direction::Root)));
+        assert_eq!(
+            config.keymap.bindings().len(),
+            default_keymap().bindings().len() + 2 - 1
+        );
+        let colors = &config.color_scheme;
+        assert_eq!(colors[syntax_category::CONST], Color::LightBlue);
+        assert_eq!(colors[syntax_category::IDENT], Color::Rgb(0xff, 0x80, 0x00));
+        assert_eq!(colors[syntax_category::COMMENT], Color::Indexed(244));
+        assert_eq!(colors[syntax_category::LITERAL], Color::Yellow);
+        assert_eq!(config.format_style, "compact");
+        assert_eq!(config.indent_width, 2);
+        assert_eq!(config.scroll_margin, 0);
+        assert_eq!(config.wheel_scroll_lines, 5);
+        assert_eq!(config.swap_interval, Duration::from_millis(500));
+        // Sides of the status line which aren't mentioned keep their default items
+        assert_eq!(config.status_line.left, StatusLine::default().left);
+        assert_eq!(
+            config.status_line.right,
+            vec![StatusItem::Keystrokes, StatusItem::UndoPosition]
+        );
+
+        // Invalid entries are reported and ignored, but the valid entries are still used
+        let (config, errors) = Config::from_json(&json!({
+            "keymap": {"x": "deleet", "xy": "undo", "u": 5, "q": "help"},
+            "colors": {"ident": "purple", "const": "#12345", "bogus": "red", "type": 256},
+            "indent_width": -1,
+            "scroll_margin": "3",
+            "swap_interval": 0,
+            "status_line": {"left": ["mode", "cursor"], "middle": [], "right": "mode"},
+            "tab_width": 4
+        }));
+        assert_eq!(
+            errors,
+            vec![
+                "'keymap': 'x': unknown command 'deleet'",
+                "'keymap': 'xy': invalid keys",
+                "'keymap': 'u': expected a command name or null, found 5",
+                "'colors': 'ident': unknown color 'purple' (expected one of reset, black, red, \
+                 green, yellow, blue, magenta, cyan, gray, dark-gray, light-red, light-green, \
+                 light-yellow, light-blue, light-magenta, light-cyan, white)",
+                "'colors': 'const': invalid hex color '#12345' (expected '#rrggbb')",
+                "'colors': unknown syntax category 'bogus' (expected one of comment, const, \
+                 default, error, ident, keyword, literal, pre-proc, special, type, underlined)",
+                "'colors': 'type': color index 256 is bigger than 255",
+                "'indent_width': expected a whole number, found -1",
+                r#"'scroll_margin': expected a whole number, found "3""#,
+                "'swap_interval': expected a positive number of seconds, found 0",
+                "'status_line': 'left': unknown item \"cursor\" (expected one of mode, \
+                 file-name, dirty, breadcrumb, class-name, undo-position, recording, keystrokes)",
+                "'status_line': unknown side 'middle' (expected left or right)",
+                r#"'status_line': 'right': expected an array of items, found "mode""#,
+                "'tab_width': unknown option",
+            ]
+        );
+        let get = |keys| config.keymap.get(&parse_keys(keys).unwrap());
+        assert_eq!(get("x"), Some(CmdType::Delete));
+        assert_eq!(get("q"), Some(CmdType::Help));
+        assert_eq!(config.color_scheme[syntax_category::IDENT], Color::Cyan);
+        assert_eq!(config.indent_width, 4);
+        assert_eq!(config.status_line.left, vec![StatusItem::Mode]);
+        assert_eq!(config.status_line.right, StatusLine::default().right);
+
+        let (_, errors) = Config::from_json(&json!({"keymap": ["x"]}));
+        assert_eq!(
+            errors,
+            vec![r#"'keymap': expected an object of key bindings, found ["x"]"#]
+        );
+        let (_, errors) = Config::from_json(&json!([]));
+        assert_eq!(errors, vec!["expected an object of options, found []"]);
+    }
+
+    #[test]
+    fn macro_json_round_trip() {
+        let mut macros = Macros::new();
+        macros.insert(
+            'a',
+            vec![
+                KeyEvent::new(KeyCode::Char('^'), KeyModifiers::empty()),
+                KeyEvent::new(KeyCode::Char('r'), KeyModifiers::CONTROL),
+                KeyEvent::new(KeyCode::Enter, KeyModifiers::empty()),
+            ],
+        );
+        macros.insert('b', vec![]);
+        let json = macros_to_json(&macros);
+        assert_eq!(json, json!({"a": ["^", "^r", "<CR>"], "b": []}));
+        assert_eq!(macros_from_json(&json), Some(macros));
+        // Malformed macros aren't loaded
+        for malformed in &[
+            json!([]),
+            json!({"ab": []}),
+            json!({"a": "x"}),
+            json!({"a": ["xy"]}),
+        ] {
+            assert_eq!(macros_from_json(malformed), None);
         }
     }
 }
@@ -12,12 +12,19 @@ pub trait KeyDisplay {
 
 impl KeyDisplay for KeyEvent {
     fn compact_string(self) -> Cow<'static, str> {
-        if self.modifiers.contains(KeyModifiers::CONTROL) {
-            if let KeyCode::Char(c) = self.code {
+        if let KeyCode::Char(c) = self.code {
+            if self.modifiers.contains(KeyModifiers::CONTROL) {
                 return Cow::from(format!("^{}", c));
             }
+            if self.modifiers == KeyModifiers::ALT {
+                return Cow::from(format!("<A-{}>", c));
+            }
         }
-        if self.modifiers.is_empty() {
+        // Upper-case characters are sometimes reported with the shift modifier, but the
+        // character already tells us that shift was pressed
+        let is_shifted_char =
+            self.modifiers == KeyModifiers::SHIFT && matches!(self.code, KeyCode::Char(_));
+        if self.modifiers.is_empty() || is_shifted_char {
             match self.code {
                 KeyCode::Char(c) => Cow::from(String::from(c)),
 
@@ -33,6 +40,7 @@ impl KeyDisplay for KeyEvent {
                 KeyCode::Delete => Cow::from("<Del>"),
 
                 KeyCode::Tab => Cow::from("<Tab>"),
+                KeyCode::BackTab => Cow::from("<BackTab>"),
                 KeyCode::Enter => Cow::from("<CR>"),
 
                 KeyCode::Insert => Cow::from("<Insert>"),
@@ -56,3 +64,118 @@ pub fn keystrokes_to_string(keystroke_buffer: &[KeyEvent]) -> String {
         .map(|x| x.compact_string())
         .collect()
 }
+
+/// The names of the special keys, as they appear inside `<...>` in
+/// [`compact_string`](KeyDisplay::compact_string)
+const NAMED_KEYS: [(&str, KeyCode); 16] = [
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("Esc", KeyCode::Esc),
+    ("BS", KeyCode::Backspace),
+    ("Del", KeyCode::Delete),
+    ("Tab", KeyCode::Tab),
+    ("CR", KeyCode::Enter),
+    ("Insert", KeyCode::Insert),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+    ("BackTab", KeyCode::BackTab),
+    // A space is displayed as itself, but needs a name when keys are separated by spaces
+    ("Space", KeyCode::Char(' ')),
+];
+
+/// Parses a single keystroke from the format generated by
+/// [`compact_string`](KeyDisplay::compact_string) (e.g. `x`, `^r`, `<A-x>` or `<CR>`), returning
+/// `None` if the string doesn't represent exactly one keystroke.
+pub fn parse_key(string: &str) -> Option<KeyEvent> {
+    let mut chars = string.chars();
+    let key = match (chars.next()?, chars.next()) {
+        // Single characters (including `^` and `<`) represent themselves
+        (c, None) => KeyEvent::new(KeyCode::Char(c), KeyModifiers::empty()),
+        ('^', Some(c)) if chars.next().is_none() => {
+            KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL)
+        }
+        ('<', Some(_)) => {
+            let name = string.strip_prefix('<')?.strip_suffix('>')?;
+            if let Some(alt_key) = name.strip_prefix("A-") {
+                let mut chars = alt_key.chars();
+                return match (chars.next(), chars.next()) {
+                    (Some(c), None) => Some(KeyEvent::new(KeyCode::Char(c), KeyModifiers::ALT)),
+                    _ => None,
+                };
+            }
+            let code = match NAMED_KEYS.iter().find(|(n, _)| *n == name) {
+                Some((_, code)) => *code,
+                None => KeyCode::F(name.strip_prefix('F')?.parse().ok()?),
+            };
+            KeyEvent::new(code, KeyModifiers::empty())
+        }
+        _ => return None,
+    };
+    Some(key)
+}
+
+/// Parses a sequence of keystrokes separated by whitespace (e.g. `g g` or `^w <Space>`), where
+/// each keystroke is in the format read by [`parse_key`].  Returns `None` if any of the
+/// keystrokes are invalid, or if there aren't any.
+pub fn parse_keys(string: &str) -> Option<Vec<KeyEvent>> {
+    let keys = string
+        .split_whitespace()
+        .map(parse_key)
+        .collect::<Option<Vec<_>>>()?;
+    Some(keys).filter(|keys| !keys.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_key, parse_keys, KeyDisplay};
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    #[test]
+    fn parse_key_round_trip() {
+        for string in &[
+            "x",
+            "^",
+            "<",
+            ">",
+            "^r",
+            "^^",
+            "<CR>",
+            "<Esc>",
+            "<BS>",
+            "<F5>",
+            "<F12>",
+            "<BackTab>",
+            "<A-x>",
+        ] {
+            println!("Testing {:?}", string);
+            assert_eq!(parse_key(string).unwrap().compact_string(), *string);
+        }
+        for string in &[
+            "", "xy", "^ab", "<Foo>", "<CR", "<F>", "<Fx>", "<A-xy>", "<A->",
+        ] {
+            println!("Testing {:?}", string);
+            assert_eq!(parse_key(string), None);
+        }
+    }
+
+    #[test]
+    fn parse_key_sequences() {
+        let key = |c| KeyEvent::from(KeyCode::Char(c));
+        assert_eq!(parse_keys("g g"), Some(vec![key('g'), key('g')]));
+        assert_eq!(
+            parse_keys(" ^r  <Space>\t"),
+            Some(vec![
+                KeyEvent::new(KeyCode::Char('r'), KeyModifiers::CONTROL),
+                key(' ')
+            ])
+        );
+        assert_eq!(parse_keys("z"), Some(vec![key('z')]));
+        assert_eq!(parse_keys(""), None);
+        assert_eq!(parse_keys("gg"), None);
+        assert_eq!(parse_keys("g <Foo>"), None);
+    }
+}
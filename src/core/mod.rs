@@ -4,7 +4,7 @@ mod key_display;
 mod path;
 
 // Re-export `core::path::Path` and `core::key_display::KeyDisplay` as `core::{Path, KeyDisplay}`
-pub use key_display::{keystrokes_to_string, KeyDisplay};
+pub use key_display::{keystrokes_to_string, parse_key, parse_keys, KeyDisplay};
 pub use path::Path;
 
 /// The possible ways you can move the cursor
@@ -18,6 +18,29 @@ pub enum Direction {
     Prev,
     /// the [`Direction`] from a node to its higher-indexed siblings
     Next,
+    /// The [`Direction`] from a node to the first of its siblings
+    FirstSibling,
+    /// The [`Direction`] from a node to the last of its siblings
+    LastSibling,
+    /// The [`Direction`] from a node to the root of the tree
+    Root,
+    /// The [`Direction`] from a node to the nodes after it in a pre-order traversal of the tree
+    PreorderNext,
+    /// The [`Direction`] from a node to the nodes before it in a pre-order traversal of the tree
+    PreorderPrev,
+    /// The [`Direction`] from a node to the leaves after it in a pre-order traversal
+    NextLeaf,
+    /// The [`Direction`] from a node to the leaves before it in a pre-order traversal
+    PrevLeaf,
+    /// The [`Direction`] from a node to the nodes of the same class after it in a pre-order
+    /// traversal
+    NextOfClass,
+    /// The [`Direction`] from a node to the nodes of the same class before it in a pre-order
+    /// traversal
+    PrevOfClass,
+    /// The [`Direction`] from a node to one of its children, chosen by index rather than by
+    /// distance
+    NthChild,
 }
 
 /// An enum to represent the two sides of a node
@@ -38,6 +61,44 @@ impl Side {
     }
 }
 
+/// The ways that the folds in a tree can be opened or closed.  A folded node is displayed as a
+/// one-line summary instead of its full text.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Fold {
+    /// Fold the cursor, or its closest ancestor which has children
+    Close,
+    /// Unfold the cursor
+    Open,
+    /// Fold the cursor if it's unfolded, otherwise unfold it
+    Toggle,
+    /// Fold the cursor and every descendant which has children
+    CloseRecursive,
+    /// Unfold the cursor and every descendant
+    OpenRecursive,
+    /// Either unfold the cursor and every descendant (if the cursor is folded), or fold them
+    ToggleRecursive,
+    /// Fold every node with children at the depth given by the count, unfolding everything else
+    ToDepth,
+    /// Unfold every node in the tree
+    OpenAll,
+}
+
+impl Fold {
+    /// Returns a lower-case description of this `Fold`
+    pub fn description(&self) -> &'static str {
+        match self {
+            Fold::Close => "fold cursor",
+            Fold::Open => "unfold cursor",
+            Fold::Toggle => "toggle fold",
+            Fold::CloseRecursive => "fold cursor recursively",
+            Fold::OpenRecursive => "unfold cursor recursively",
+            Fold::ToggleRecursive => "toggle fold recursively",
+            Fold::ToDepth => "fold to depth",
+            Fold::OpenAll => "unfold everything",
+        }
+    }
+}
+
 /// A struct used to represent the screen space occupied by a single node of an AST.  This can be
 /// thought of as the size of the bounding box of that node.  The important thing about this is
 /// that it is independent of the text indentation, meaning that if a node gets reused multiple
@@ -3,7 +3,11 @@
 use crate::ast::Ast;
 
 /// A tree-independent struct for representing the locations of nodes within trees.
-#[derive(Debug, Clone, Eq, PartialEq)]
+///
+/// `Path`s are ordered the same way that their nodes would be visited by a pre-order traversal
+/// (i.e. a node comes after its ancestors and its earlier siblings' subtrees, but before its
+/// descendants).
+#[derive(Debug, Clone, Eq, PartialEq, Hash, PartialOrd, Ord)]
 pub struct Path {
     child_indices: Vec<usize>,
 }
@@ -35,6 +39,20 @@ impl Path {
         self.node_iter(root).last().unwrap()
     }
 
+    /// Walks this path down from the given root, and returns the node that lies underneath the
+    /// cursor, or `None` if this path doesn't point to a node in the tree.  Unlike
+    /// [`cursor`](Self::cursor), this never panics.
+    pub fn try_cursor<'arena, Node: Ast<'arena>>(
+        &self,
+        root: &'arena Node,
+    ) -> Option<&'arena Node> {
+        let mut node = root;
+        for index in self.iter() {
+            node = node.children().get(*index)?;
+        }
+        Some(node)
+    }
+
     /// Walks this path down from the given root, and returns the node that lies underneath the
     /// cursor, along with the direct parent of that node (if it exists).
     pub fn cursor_and_parent<'arena, Node: Ast<'arena>>(
@@ -78,6 +96,12 @@ impl Path {
         self.child_indices.is_empty()
     }
 
+    /// Returns `true` if this path refers to `prefix` or one of its descendants.
+    #[inline]
+    pub fn starts_with(&self, prefix: &Path) -> bool {
+        self.child_indices.starts_with(&prefix.child_indices)
+    }
+
     /// Returns the last child index in the path (if it exists).
     #[inline]
     pub fn last(&self) -> Option<usize> {
@@ -105,6 +129,219 @@ impl Path {
     {
         NodeIter::new(root, self)
     }
+
+    /// Searches the tree under `root` for the node `target` (compared by reference, not by
+    /// value), returning the `Path` from `root` to `target` if `target` is in the tree.  Because
+    /// every node within a given tree has a unique reference, this `Path` is unique.
+    pub fn to_node<'arena, Node: Ast<'arena>>(
+        root: &'arena Node,
+        target: &'arena Node,
+    ) -> Option<Path> {
+        let mut path = Path::root();
+        if find_node_recursive(root, target, &mut path) {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the `Path` to the node after this one in a pre-order traversal of the tree under
+    /// `root`, or `None` if this `Path` points to the last node in the traversal.
+    pub fn preorder_next<'arena, Node: Ast<'arena>>(&self, root: &'arena Node) -> Option<Path> {
+        let mut path = self.clone();
+        // If the node has children, then the next node is its first child
+        if !self.cursor(root).children().is_empty() {
+            path.push(0);
+            return Some(path);
+        }
+        // Otherwise, it's the next sibling of the closest ancestor which has one
+        while let Some(index) = path.pop() {
+            if index + 1 < path.cursor(root).children().len() {
+                path.push(index + 1);
+                return Some(path);
+            }
+        }
+        None
+    }
+
+    /// Returns the `Path` to the node before this one in a pre-order traversal of the tree under
+    /// `root`, or `None` if this `Path` points to the root.
+    pub fn preorder_prev<'arena, Node: Ast<'arena>>(&self, root: &'arena Node) -> Option<Path> {
+        let mut path = self.clone();
+        let index = path.pop()?;
+        // The first child comes straight after its parent
+        if index == 0 {
+            return Some(path);
+        }
+        // Otherwise, the previous node is the last descendant of the previous sibling
+        path.push(index - 1);
+        let mut node = path.cursor(root);
+        while let Some(last_child) = node.children().last() {
+            path.push(node.children().len() - 1);
+            node = *last_child;
+        }
+        Some(path)
+    }
+
+    /// Returns `true` if this `Path` points to the same node in the trees under `old_root` and
+    /// `new_root`, because following it down both trees reaches a node which they share.  An edit
+    /// only clones the spine of nodes from the root to the edited node, so this is `true` for any
+    /// `Path` which branches off that spine, and such `Path`s never need to be
+    /// [rebased](Self::rebase).
+    pub fn is_unchanged<'arena, Node: Ast<'arena>>(
+        &self,
+        old_root: &'arena Node,
+        new_root: &'arena Node,
+    ) -> bool {
+        let (mut old_node, mut new_node) = (old_root, new_root);
+        for &index in self.iter() {
+            if std::ptr::eq(old_node, new_node) {
+                return true;
+            }
+            match (
+                old_node.children().get(index),
+                new_node.children().get(index),
+            ) {
+                (Some(old_child), Some(new_child)) => {
+                    old_node = old_child;
+                    new_node = new_child;
+                }
+                _ => return false,
+            }
+        }
+        std::ptr::eq(old_node, new_node)
+    }
+
+    /// Given a `Path` into the tree under `old_root`, returns the `Path` to the same node in the
+    /// (edited) tree under `new_root`.  If that node no longer exists, the returned `Path` points
+    /// to the nearest node which does.  The returned `Path` is always valid for `new_root`.
+    pub fn rebase<'arena, Node: Ast<'arena>>(
+        &self,
+        old_root: &'arena Node,
+        new_root: &'arena Node,
+    ) -> Path {
+        self.rebase_inner(old_root, new_root, false)
+            .expect("Inexact rebasing always succeeds")
+    }
+
+    /// Like [`rebase`](Self::rebase), but returns `None` if the node has been deleted rather than
+    /// moving to its nearest neighbour.  If the node was replaced, then the `Path` to its
+    /// replacement is returned.
+    pub fn try_rebase<'arena, Node: Ast<'arena>>(
+        &self,
+        old_root: &'arena Node,
+        new_root: &'arena Node,
+    ) -> Option<Path> {
+        self.rebase_inner(old_root, new_root, true)
+    }
+
+    /// The implementation of [`rebase`](Self::rebase) and [`try_rebase`](Self::try_rebase).  If
+    /// `exact` is `false`, then this always returns `Some`.
+    fn rebase_inner<'arena, Node: Ast<'arena>>(
+        &self,
+        old_root: &'arena Node,
+        new_root: &'arena Node,
+        exact: bool,
+    ) -> Option<Path> {
+        // Nodes which weren't touched by the edit are shared between the two trees, so if the
+        // node still exists we can find it by reference
+        let old_node = self.try_cursor(old_root);
+        if let Some(path) = old_node.and_then(|node| Path::to_node(new_root, node)) {
+            return Some(path);
+        }
+        if exact && old_node.is_none() {
+            return None;
+        }
+        // Otherwise, the node (or one of its descendants) was cloned by the edit.  So we follow
+        // the path down both trees, at each level picking the new child which is closest to the
+        // old index but isn't shared with the old tree (i.e. is the clone of the node we want).
+        let mut new_path = Path::root();
+        let (mut old_node, mut new_node) = (old_root, new_root);
+        for &index in self.iter() {
+            let old_children = old_node.children();
+            let new_children = new_node.children();
+            let old_child = match old_children.get(index) {
+                Some(child) => *child,
+                None => break,
+            };
+            if new_children.is_empty() {
+                if exact {
+                    return None;
+                }
+                break;
+            }
+            let is_new =
+                |child: &&'arena Node| !old_children.iter().any(|c| std::ptr::eq(*c, *child));
+            let new_index = match new_children
+                .iter()
+                .enumerate()
+                .filter(|(_, child)| is_new(child))
+                .min_by_key(|(i, _)| (*i as isize - index as isize).abs())
+            {
+                Some((i, _)) => i,
+                // If no children were cloned, then the node was deleted
+                None if exact => return None,
+                None => index.min(new_children.len() - 1),
+            };
+            new_path.push(new_index);
+            old_node = old_child;
+            new_node = new_children[new_index];
+        }
+        Some(new_path)
+    }
+}
+
+impl std::fmt::Display for Path {
+    /// Formats a `Path` as its child indices separated by `/`s, e.g. `/0/3/1`.  The root is
+    /// formatted as `/`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_root() {
+            return write!(f, "/");
+        }
+        for index in self.iter() {
+            write!(f, "/{}", index)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Path {
+    type Err = std::num::ParseIntError;
+
+    /// Parses a `Path` from the format generated by its [`Display`](std::fmt::Display)
+    /// implementation (e.g. `/0/3/1`, or `/` for the root).  The leading `/` is optional.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "/" {
+            return Ok(Path::root());
+        }
+        s.strip_prefix('/')
+            .unwrap_or(s)
+            .split('/')
+            .map(str::parse)
+            .collect::<Result<Vec<usize>, _>>()
+            .map(Path::from_vec)
+    }
+}
+
+/// Helper function for [`Path::to_node`].  This performs a depth-first search for `target`,
+/// leaving `path` pointing to `target` if it was found (and returning `true`), or leaving `path`
+/// unchanged (and returning `false`).
+fn find_node_recursive<'arena, Node: Ast<'arena>>(
+    node: &'arena Node,
+    target: &'arena Node,
+    path: &mut Path,
+) -> bool {
+    if std::ptr::eq(node, target) {
+        return true;
+    }
+    for (i, child) in node.children().iter().enumerate() {
+        path.push(i);
+        if find_node_recursive(*child, target, path) {
+            return true;
+        }
+        path.pop();
+    }
+    false
 }
 
 /// An iterator that walks down a tree following a [`Path`].  The first item returned from
@@ -247,6 +484,47 @@ mod tests {
         assert_eq!(path.depth(), 0);
     }
 
+    #[test]
+    fn parse_and_display() {
+        for string in &["/", "/0", "/0/3/1", "/12/0"] {
+            let path: Path = string.parse().unwrap();
+            assert_eq!(path.to_string(), *string);
+        }
+        assert_eq!("2/1".parse(), Ok(Path::from_vec(vec![2, 1])));
+        for string in &["", "//", "/a", "/0/", "/-1", "/1//2"] {
+            println!("Testing {:?}", string);
+            assert!(string.parse::<Path>().is_err());
+        }
+    }
+
+    #[test]
+    fn preorder() {
+        let arena = Arena::new();
+        let root = add_value_to_arena(json!([[true, []], false, { "value": null }]), &arena);
+        let expected_paths: Vec<Path> = [
+            vec![],
+            vec![0],
+            vec![0, 0],
+            vec![0, 1],
+            vec![1],
+            vec![2],
+            vec![2, 0],
+            vec![2, 0, 0],
+            vec![2, 0, 1],
+        ]
+        .iter()
+        .map(|v| Path::from_vec(v.clone()))
+        .collect();
+        // Walk forwards then backwards through the whole tree
+        for pair in expected_paths.windows(2) {
+            assert_eq!(pair[0].preorder_next(root).as_ref(), Some(&pair[1]));
+            assert_eq!(pair[1].preorder_prev(root).as_ref(), Some(&pair[0]));
+        }
+        // There's nothing either side of the ends of the traversal
+        assert_eq!(expected_paths.last().unwrap().preorder_next(root), None);
+        assert_eq!(Path::root().preorder_prev(root), None);
+    }
+
     #[test]
     fn node_iter() {
         // Create some test Json and add it to an arena
@@ -345,4 +623,30 @@ mod tests {
         assert_eq!(c.display_name(), "true");
         assert_eq!(p.unwrap().display_name(), "field");
     }
+
+    #[test]
+    fn is_unchanged() {
+        let arena = Arena::new();
+        let old_root = add_value_to_arena(json!([[true, false], null]), &arena);
+        // Replace the `null` by hand, sharing the untouched `[true, false]` between the trees
+        let new_root = arena.alloc(Json::Array(vec![
+            old_root.children()[0],
+            arena.alloc(Json::True),
+        ]));
+        for (path, expected) in &[
+            (vec![], false),
+            (vec![0], true),
+            (vec![0, 1], true),
+            (vec![1], false),
+        ] {
+            assert_eq!(
+                Path::from_vec(path.clone()).is_unchanged(old_root, new_root),
+                *expected,
+                "{:?}",
+                path
+            );
+        }
+        // Nothing is changed between a tree and itself
+        assert!(Path::from_vec(vec![1]).is_unchanged(old_root, old_root));
+    }
 }
@@ -0,0 +1,677 @@
+//! A single tree open in the editor, along with the file that it came from
+
+use super::dag::{Dag, LogMessage};
+use crate::arena::Arena;
+use crate::ast::Ast;
+use crate::core::Path;
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// An error that stopped a [`Buffer`] from being written to disk
+#[derive(Debug)]
+pub enum WriteErr {
+    /// The [`Buffer`] has no file, and no file was given to write to
+    NoFilePath,
+    /// Writing to the file failed.  The file on disk is left untouched.
+    Io(PathBuf, io::Error),
+    /// The file has been changed by another program since it was read or written, so writing it
+    /// would throw those changes away
+    FileChanged(PathBuf),
+}
+
+impl WriteErr {
+    /// Logs a message describing this error to the appropriate log channel
+    pub fn log_message(self) {
+        match self {
+            WriteErr::NoFilePath => log::warn!("No file to write to!"),
+            WriteErr::Io(path, error) => log::error!("Error writing to {:?}: {}", path, error),
+            WriteErr::FileChanged(path) => log::warn!(
+                "{:?} has been changed outside Sapling (use :e to reload it or :w! to overwrite it)",
+                path
+            ),
+        }
+    }
+}
+
+/// The result of writing a [`Buffer`] to disk, containing the path of the file that was written
+pub type WriteResult = Result<PathBuf, WriteErr>;
+
+impl LogMessage for WriteResult {
+    fn log_message(self) {
+        match self {
+            Ok(path) => log::info!("Written to {:?}", path),
+            Err(err) => err.log_message(),
+        }
+    }
+}
+
+/// What to do with a swap file which was left behind for a [`Buffer`]'s file (e.g. by a crash)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapAction {
+    /// Replace the tree with the contents of the swap file
+    Recover,
+    /// Open the swap file in a new [`Buffer`] so that it can be compared with the file
+    Diff,
+    /// Delete the swap file
+    Discard,
+}
+
+/// A tree which is open in Sapling.  Every `Buffer` has its own undo history, cursor and marks
+/// (all stored in its [`Dag`]), but all `Buffer`s allocate their nodes in the same [`Arena`] so
+/// that nodes can be yanked from one `Buffer` and put into another.
+pub struct Buffer<'arena, Node: Ast<'arena>> {
+    /// The `Dag` that is storing the history of this `Buffer`
+    pub tree: Dag<'arena, Node>,
+    /// The file that this `Buffer` is written to, if it has one
+    pub file_path: Option<PathBuf>,
+    /// The style that the tree is printed with, both to the screen and to the file
+    pub format_style: Node::FormatStyle,
+    /// The root of the tree which was last written to the [swap file](swap_paths), or `None` if
+    /// this `Buffer` has no swap file
+    swapped_root: Option<&'arena Node>,
+    /// The swap file which was left behind for this `Buffer`'s file (e.g. by a crash), if the
+    /// user hasn't yet decided what to do with it.  The swap file isn't overwritten until they do,
+    /// so this `Buffer` uses the file's other swap file in the meantime.
+    pub old_swap_file: Option<PathBuf>,
+    /// The modification time of this `Buffer`'s file when it was last read or written
+    file_mtime: Option<SystemTime>,
+    /// The modification time of this `Buffer`'s file which the user was last warned about, so that
+    /// each change made by another program is only reported once
+    warned_mtime: Option<SystemTime>,
+}
+
+impl<'arena, Node: Ast<'arena>> Buffer<'arena, Node> {
+    /// Creates a `Buffer` containing a given tree
+    pub fn new(
+        tree: Dag<'arena, Node>,
+        file_path: Option<PathBuf>,
+        format_style: Node::FormatStyle,
+    ) -> Self {
+        Buffer {
+            tree,
+            file_path,
+            format_style,
+            swapped_root: None,
+            old_swap_file: None,
+            file_mtime: None,
+            warned_mtime: None,
+        }
+    }
+
+    /// Reads and parses the file at `path` into a new `Buffer`, returning a message for the user
+    /// if the file couldn't be read or parsed
+    pub fn open(
+        path: PathBuf,
+        arena: &'arena Arena<Node>,
+        format_style: Node::FormatStyle,
+    ) -> Result<Self, String> {
+        let root = read_tree(&path, arena)?;
+        let mut buffer = Self::new(Dag::new(arena, root, Path::root()), None, format_style);
+        let old_swap_file = swap_paths(&path)
+            .find_map(|swap_path| Some((fs::metadata(&swap_path).ok()?, swap_path)));
+        if let Some((swap_metadata, swap_path)) = old_swap_file {
+            let is_older = match (swap_metadata.modified().ok(), modified_time(&path)) {
+                (Some(swap_time), Some(file_time)) => swap_time < file_time,
+                _ => false,
+            };
+            log::warn!(
+                "Found swap file {:?}{} (use :recover to restore it, :recover diff to compare it \
+                 or :recover discard to delete it)",
+                swap_path,
+                if is_older {
+                    ", which is older than the file"
+                } else {
+                    ""
+                },
+            );
+            buffer.old_swap_file = Some(swap_path);
+        }
+        buffer.file_mtime = modified_time(&path);
+        buffer.file_path = Some(path);
+        Ok(buffer)
+    }
+
+    /// Replaces the tree with the current contents of this `Buffer`'s file.  The reload is a
+    /// single edit, so it can be undone to get back the tree from before the reload.
+    pub fn reload(&mut self) -> Result<(), String> {
+        let path = self.file_path.as_ref().ok_or("No file to reload!")?;
+        let root = read_tree(path, self.tree.arena())?;
+        self.file_mtime = modified_time(path);
+        self.tree.replace_tree(root);
+        self.tree.mark_saved();
+        Ok(())
+    }
+
+    /// Warns the user if this `Buffer`'s file has been changed by another program since it was
+    /// last read or written.  Each change is only reported once.
+    pub fn check_for_external_changes(&mut self) {
+        let mtime = match &self.file_path {
+            Some(path) => modified_time(path),
+            None => return,
+        };
+        if mtime.is_none() || mtime == self.file_mtime || mtime == self.warned_mtime {
+            return;
+        }
+        self.warned_mtime = mtime;
+        log::warn!(
+            "{} has been changed outside Sapling (use :e to reload it or :w! to overwrite it)",
+            self.name()
+        );
+    }
+
+    /// Returns `true` if this `Buffer`'s file has been changed by another program since it was
+    /// last read or written
+    pub fn is_file_changed(&self) -> bool {
+        match (&self.file_path, self.file_mtime) {
+            (Some(path), Some(mtime)) => modified_time(path).is_some_and(|m| m != mtime),
+            _ => false,
+        }
+    }
+
+    /// Returns the name that should be shown to the user for this `Buffer`
+    pub fn name(&self) -> String {
+        match &self.file_path {
+            Some(path) => path.display().to_string(),
+            None => "[No Name]".to_owned(),
+        }
+    }
+
+    /// Writes the tree to the file at `path`, or to this `Buffer`'s file if `path` is `None`.  If
+    /// this `Buffer` has no file, then `path` becomes its file.  The `Buffer` is only marked as
+    /// saved if it was written to its own file.  Unless `force` is `true`, this refuses to
+    /// overwrite this `Buffer`'s file if it has been [changed by another
+    /// program](Self::is_file_changed).
+    pub fn write(&mut self, path: Option<PathBuf>, force: bool) -> WriteResult {
+        let is_own_file = path.is_none() || self.file_path.is_none() || path == self.file_path;
+        let path = path
+            .or_else(|| self.file_path.clone())
+            .ok_or(WriteErr::NoFilePath)?;
+        if !force && self.file_path.as_ref() == Some(&path) && self.is_file_changed() {
+            return Err(WriteErr::FileChanged(path));
+        }
+        self.write_to(path, is_own_file)
+    }
+
+    /// Writes the tree to the file at `path`, which then becomes this `Buffer`'s file
+    pub fn save_as(&mut self, path: PathBuf) -> WriteResult {
+        self.write_to(path, true)
+    }
+
+    /// Writes the tree to `path`, making `path` this `Buffer`'s file if `is_own_file` is `true`
+    fn write_to(&mut self, path: PathBuf, is_own_file: bool) -> WriteResult {
+        let mut content = self.tree.to_text(&self.format_style);
+        // Force the file to finish with a newline.
+        if !content.ends_with('\n') {
+            content.push('\n');
+        }
+        if let Err(e) = write_atomically(&path, content.as_bytes()) {
+            return Err(WriteErr::Io(path, e));
+        }
+        if is_own_file {
+            // The swap file belongs to the old file, so we're now finished with it
+            if self.file_path.as_ref() != Some(&path) {
+                self.remove_swap_file();
+            }
+            self.file_mtime = modified_time(&path);
+            self.file_path = Some(path.clone());
+            self.tree.mark_saved();
+        }
+        Ok(path)
+    }
+
+    /* SWAP FILES */
+
+    /// Keeps this `Buffer`'s [swap file](Self::own_swap_path) up to date, so that unsaved changes
+    /// can be recovered if Sapling crashes.  The current tree is written to the swap file if it
+    /// has changed since the swap file was last written, and the swap file is removed once there
+    /// are no unsaved changes.
+    pub fn update_swap_file(&mut self) {
+        let swap_path = match self.own_swap_path() {
+            Some(swap_path) => swap_path,
+            None => return,
+        };
+        if !self.tree.is_dirty() {
+            self.remove_swap_file();
+            return;
+        }
+        if self
+            .swapped_root
+            .is_some_and(|root| std::ptr::eq(root, self.tree.root()))
+        {
+            return;
+        }
+        let content = self.tree.to_text(&self.format_style);
+        if let Err(e) = write_atomically(&swap_path, content.as_bytes()) {
+            log::error!("Error writing swap file {:?}: {}", swap_path, e);
+        }
+        // Even if the write failed, we don't retry until the tree changes so that the log isn't
+        // flooded with errors
+        self.swapped_root = Some(self.tree.root());
+    }
+
+    /// Removes the [swap file](Self::own_swap_path) which this `Buffer` has written, if it
+    /// exists.  Swap files left by other sessions are left alone.
+    pub fn remove_swap_file(&mut self) {
+        if let (Some(swap_path), Some(_)) = (self.own_swap_path(), self.swapped_root.take()) {
+            if let Err(e) = fs::remove_file(&swap_path) {
+                if e.kind() != io::ErrorKind::NotFound {
+                    log::error!("Error removing swap file {:?}: {}", swap_path, e);
+                }
+            }
+        }
+    }
+
+    /// Replaces the tree with the contents of the swap file left behind for this `Buffer`'s file.
+    /// The recovery is a single edit, so it can be undone to get back the file's contents.
+    pub fn recover_swap_file(&mut self) -> Result<(), String> {
+        let swap_path = self.old_swap_path()?;
+        let root = read_tree(&swap_path, self.tree.arena())?;
+        self.tree.replace_tree(root);
+        // The recovered tree now belongs to this session, so it's written to this session's swap
+        // file straight away.  That can change which swap file is this session's, so the one
+        // that was being used is removed first, as is the old one (if it's no longer needed).
+        self.remove_swap_file();
+        self.old_swap_file = None;
+        self.update_swap_file();
+        if self.own_swap_path().as_ref() != Some(&swap_path) {
+            if let Err(e) = fs::remove_file(&swap_path) {
+                log::error!("Error removing swap file {:?}: {}", swap_path, e);
+            }
+        }
+        log::info!("Recovered {:?} (use :w to write it to the file)", swap_path);
+        Ok(())
+    }
+
+    /// Reads the swap file left behind for this `Buffer`'s file into a new `Buffer` which has no
+    /// file, so that it can be compared with this one
+    pub fn open_swap_file(&self) -> Result<Self, String> {
+        let swap_path = self.old_swap_path()?;
+        let root = read_tree(&swap_path, self.tree.arena())?;
+        let tree = Dag::new(self.tree.arena(), root, Path::root());
+        Ok(Self::new(tree, None, self.format_style.clone()))
+    }
+
+    /// Deletes the swap file left behind for this `Buffer`'s file
+    pub fn discard_swap_file(&mut self) -> Result<(), String> {
+        let swap_path = self.old_swap_path()?;
+        fs::remove_file(&swap_path)
+            .map_err(|e| format!("Error removing {:?}: {}", swap_path, e))?;
+        // This session's swap file might change, so the one it was using is removed
+        self.remove_swap_file();
+        self.old_swap_file = None;
+        log::info!("Deleted {:?}", swap_path);
+        Ok(())
+    }
+
+    /// Writes the tree somewhere it can be recovered from after Sapling has crashed, returning the
+    /// path it was written to.  This is the [swap file](Self::own_swap_path), unless this
+    /// `Buffer` has no file, in which case a file named after this `Buffer`'s `index` is written
+    /// to the temporary directory.
+    pub fn write_emergency_copy(&self, index: usize) -> io::Result<PathBuf> {
+        let path = match self.own_swap_path() {
+            Some(swap_path) => swap_path,
+            None => std::env::temp_dir().join(format!(
+                "sapling-{}-{}.swp",
+                std::process::id(),
+                index + 1
+            )),
+        };
+        write_atomically(&path, self.tree.to_text(&self.format_style).as_bytes())?;
+        Ok(path)
+    }
+
+    /// Returns the path of the swap file left behind for this `Buffer`'s file, or a message for
+    /// the user if there isn't one
+    fn old_swap_path(&self) -> Result<PathBuf, String> {
+        self.old_swap_file
+            .clone()
+            .ok_or_else(|| format!("{} has no swap file to recover", self.name()))
+    }
+
+    /// Returns the path of the swap file that this `Buffer` writes its unsaved changes to, or
+    /// `None` if it has no file.  This is the first of the file's [swap files](swap_paths) which
+    /// wasn't left behind by another session.
+    fn own_swap_path(&self) -> Option<PathBuf> {
+        let path = self.file_path.as_ref()?;
+        swap_paths(path).find(|swap_path| self.old_swap_file.as_ref() != Some(swap_path))
+    }
+}
+
+/// Returns the paths of the swap files for the file at `path`.  For example, the swap files of
+/// `data/file.json` are `data/.file.json.swp` and `data/.file.json.swo`.  The second is only used
+/// while the first was left behind by another session (like Vim's `.swo` files).
+pub fn swap_paths(path: &std::path::Path) -> impl Iterator<Item = PathBuf> + '_ {
+    ["swp", "swo"].iter().map(move |extension| {
+        let mut name = std::ffi::OsString::from(".");
+        name.push(path.file_name().unwrap_or_default());
+        name.push(".");
+        name.push(extension);
+        path.with_file_name(name)
+    })
+}
+
+/// Returns the time that the file at `path` was last modified, or `None` if it can't be found
+fn modified_time(path: &std::path::Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Reads and parses the file at `path` into `arena`, returning a message for the user if the file
+/// couldn't be read or parsed
+fn read_tree<'arena, Node: Ast<'arena>>(
+    path: &std::path::Path,
+    arena: &'arena Arena<Node>,
+) -> Result<&'arena Node, String> {
+    let file = fs::File::open(path).map_err(|e| format!("Error opening {:?}: {}", path, e))?;
+    Node::parse_to_arena(file, arena).map_err(|e| format!("Error parsing {:?}: {}", path, e))
+}
+
+/// Replaces the contents of the file at `path` with `content`, so that the file is never left
+/// half-written.  The new contents are written and synced to a temporary file next to `path`,
+/// which is then renamed over `path`.  If `path` is a symlink, the file it points to is replaced
+/// and the link is left alone.  The permissions of any existing file are kept.
+fn write_atomically(path: &std::path::Path, content: &[u8]) -> io::Result<()> {
+    let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+    let file_name = target
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(format!(".sapling-tmp-{}", std::process::id()));
+    let temp_path = target.with_file_name(temp_name);
+
+    let write_temp_file = || -> io::Result<()> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)?;
+        if let Ok(metadata) = fs::metadata(&target) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.write_all(content)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, &target)
+    };
+    if let Err(e) = write_temp_file() {
+        // The temporary file may not exist, in which case there's nothing to clean up
+        let _ = fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    // Sync the directory so that the rename itself survives a crash.  Not every platform allows
+    // directories to be opened, so this is only attempted on Unix.
+    #[cfg(unix)]
+    if let Some(dir) = target.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::{swap_paths, write_atomically, Buffer, WriteErr};
+    use crate::arena::Arena;
+    use crate::ast::json::{add_value_to_arena, Json, JsonFormat};
+    use crate::core::Path;
+    use crate::editor::dag::Dag;
+
+    use std::fs;
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+
+    use serde_json::{json, Value};
+
+    /// Creates an empty directory, unique to this test, to write files into
+    pub(crate) fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sapling-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Replaces the contents of the file at `path` as if another program had changed it, and
+    /// returns the file so that its modification time can be changed again.  The time is set
+    /// explicitly, since the change may happen too quickly for the modification time to change.
+    pub(crate) fn change_externally(path: &std::path::Path, contents: &str) -> fs::File {
+        fs::write(path, contents).unwrap();
+        let file = fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH).unwrap();
+        file
+    }
+
+    #[test]
+    fn atomic_write() {
+        let dir = test_dir("atomic-write");
+        let path = dir.join("file.json");
+        write_atomically(&path, b"first").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "first");
+        // Overwriting keeps the file's permissions
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+            write_atomically(&path, b"second").unwrap();
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o640);
+        }
+        write_atomically(&path, b"third").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "third");
+        // Writing into a directory which doesn't exist fails without leaving anything behind
+        assert!(write_atomically(&dir.join("missing/file.json"), b"x").is_err());
+        let names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        assert_eq!(names, vec![path]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn write_and_save_as() {
+        let dir = test_dir("save-as");
+        let arena = Arena::new();
+        let root = add_value_to_arena(json!([true, null]), &arena);
+        let mut buffer = Buffer::new(
+            Dag::new(&arena, root, Path::root()),
+            None,
+            JsonFormat::Compact,
+        );
+        assert!(matches!(
+            buffer.write(None, false),
+            Err(WriteErr::NoFilePath)
+        ));
+
+        // Writing to a different file leaves the buffer's file alone
+        let own_path = dir.join("own.json");
+        let other_path = dir.join("other.json");
+        buffer.file_path = Some(own_path.clone());
+        assert_eq!(
+            buffer.write(Some(other_path.clone()), false).unwrap(),
+            other_path
+        );
+        assert_eq!(fs::read_to_string(&other_path).unwrap(), "[true, null]\n");
+        assert_eq!(buffer.file_path, Some(own_path.clone()));
+        assert_eq!(buffer.write(None, false).unwrap(), own_path);
+
+        // Saving as a different file changes the buffer's file
+        let new_path = dir.join("new.json");
+        assert_eq!(buffer.save_as(new_path.clone()).unwrap(), new_path);
+        assert_eq!(buffer.file_path, Some(new_path));
+
+        let missing_path = dir.join("missing/file.json");
+        assert!(matches!(
+            buffer.write(Some(missing_path), false),
+            Err(WriteErr::Io(..))
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn swap_files() {
+        /// Replaces the buffer's tree with `value`, then brings its swap file up to date
+        fn replace_tree<'arena>(buffer: &mut Buffer<'arena, Json<'arena>>, value: Value) {
+            let root = add_value_to_arena(value, buffer.tree.arena());
+            buffer.tree.replace_tree(root);
+            buffer.update_swap_file();
+        }
+
+        let dir = test_dir("swap-files");
+        let path = dir.join("file.json");
+        let swap_paths: Vec<_> = swap_paths(&path).collect();
+        let (swap_path, alternate_path) = (&swap_paths[0], &swap_paths[1]);
+        assert_eq!(*swap_path, dir.join(".file.json.swp"));
+        assert_eq!(*alternate_path, dir.join(".file.json.swo"));
+        fs::write(&path, "[true]").unwrap();
+        let arena = Arena::new();
+        let open = || Buffer::<Json>::open(path.clone(), &arena, JsonFormat::Compact).unwrap();
+
+        // No swap file is written until there are unsaved changes
+        let mut buffer = open();
+        buffer.update_swap_file();
+        assert!(!swap_path.exists());
+        replace_tree(&mut buffer, json!([null]));
+        assert_eq!(fs::read_to_string(swap_path).unwrap(), "[null]");
+
+        // Opening the file again (e.g. after a crash) finds the swap file and leaves it alone,
+        // writing its own changes to the alternate swap file instead
+        let mut recovered = open();
+        assert_eq!(recovered.old_swap_file, Some(swap_path.clone()));
+        replace_tree(&mut recovered, json!([false]));
+        assert_eq!(fs::read_to_string(swap_path).unwrap(), "[null]");
+        assert_eq!(fs::read_to_string(alternate_path).unwrap(), "[false]");
+        let compared = recovered.open_swap_file().unwrap();
+        assert_eq!(compared.file_path, None);
+        assert_eq!(compared.tree.to_text(&JsonFormat::Compact), "[null]");
+        // Recovering makes the old swap file this session's, so the alternate one is removed
+        recovered.recover_swap_file().unwrap();
+        assert_eq!(recovered.old_swap_file, None);
+        assert!(recovered.tree.is_dirty());
+        assert_eq!(recovered.tree.to_text(&JsonFormat::Compact), "[null]");
+        assert_eq!(fs::read_to_string(swap_path).unwrap(), "[null]");
+        assert!(!alternate_path.exists());
+        // The recovery can be undone
+        recovered.tree.undo(1).unwrap();
+        assert_eq!(recovered.tree.to_text(&JsonFormat::Compact), "[false]");
+        assert!(recovered.recover_swap_file().is_err());
+
+        // Writing the file removes the swap file
+        recovered.write(None, false).unwrap();
+        recovered.update_swap_file();
+        assert!(!swap_path.exists());
+
+        // Old swap files can also be discarded, after which the first swap file is used again
+        replace_tree(&mut buffer, json!([]));
+        let mut discarded = open();
+        replace_tree(&mut discarded, json!([true, true]));
+        assert!(alternate_path.exists());
+        discarded.discard_swap_file().unwrap();
+        assert!(!swap_path.exists());
+        assert!(!alternate_path.exists());
+        assert_eq!(discarded.old_swap_file, None);
+        discarded.update_swap_file();
+        assert_eq!(fs::read_to_string(swap_path).unwrap(), "[true, true]");
+        assert!(!alternate_path.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn emergency_copies() {
+        let dir = test_dir("emergency-copies");
+        let path = dir.join("file.json");
+        let swap_paths: Vec<_> = swap_paths(&path).collect();
+        fs::write(&path, "[true]").unwrap();
+        let arena = Arena::new();
+        let open = || Buffer::<Json>::open(path.clone(), &arena, JsonFormat::Compact).unwrap();
+
+        // Buffers with files are written to their own swap file
+        let buffer = open();
+        assert_eq!(buffer.write_emergency_copy(0).unwrap(), swap_paths[0]);
+        assert_eq!(fs::read_to_string(&swap_paths[0]).unwrap(), "[true]");
+        // ...which isn't the swap file left by another session
+        let buffer = open();
+        assert_eq!(buffer.write_emergency_copy(0).unwrap(), swap_paths[1]);
+        assert_eq!(fs::read_to_string(&swap_paths[1]).unwrap(), "[true]");
+
+        // Buffers without files are written to the temporary directory
+        let root = add_value_to_arena(json!([null]), &arena);
+        let buffer = Buffer::new(
+            Dag::new(&arena, root, Path::root()),
+            None,
+            JsonFormat::Compact,
+        );
+        let copy_path = buffer.write_emergency_copy(2).unwrap();
+        assert_eq!(copy_path.parent(), Some(std::env::temp_dir().as_path()));
+        assert_eq!(
+            copy_path.file_name().unwrap(),
+            format!("sapling-{}-3.swp", std::process::id()).as_str()
+        );
+        assert_eq!(fs::read_to_string(&copy_path).unwrap(), "[null]");
+        fs::remove_file(copy_path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn external_changes() {
+        let dir = test_dir("external-changes");
+        let path = dir.join("file.json");
+        fs::write(&path, "[true]").unwrap();
+        let arena = Arena::new();
+        let mut buffer = Buffer::<Json>::open(path.clone(), &arena, JsonFormat::Compact).unwrap();
+        assert!(!buffer.is_file_changed());
+        buffer.write(None, false).unwrap();
+        assert!(!buffer.is_file_changed());
+
+        // Another program changes the file
+        let file = change_externally(&path, "[null]");
+        assert!(buffer.is_file_changed());
+        assert!(matches!(
+            buffer.write(None, false),
+            Err(WriteErr::FileChanged(_))
+        ));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[null]");
+
+        // Reloading the file can be undone
+        buffer.reload().unwrap();
+        assert!(!buffer.is_file_changed());
+        assert!(!buffer.tree.is_dirty());
+        assert_eq!(buffer.tree.to_text(&JsonFormat::Compact), "[null]");
+        buffer.tree.undo(1).unwrap();
+        assert!(buffer.tree.is_dirty());
+        assert_eq!(buffer.tree.to_text(&JsonFormat::Compact), "[true]");
+
+        // Forcing a write overwrites the changes
+        let later = SystemTime::UNIX_EPOCH + Duration::from_secs(60);
+        file.set_modified(later).unwrap();
+        assert!(buffer.is_file_changed());
+        buffer.write(None, true).unwrap();
+        assert!(!buffer.is_file_changed());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[true]\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn open_numbers() {
+        let dir = test_dir("open-numbers");
+        let path = dir.join("file.json");
+        fs::write(&path, "[1]").unwrap();
+        let arena = Arena::new();
+        // Sapling can't represent numbers, so opening the file fails instead of panicking
+        let err = Buffer::<Json>::open(path.clone(), &arena, JsonFormat::Compact)
+            .err()
+            .unwrap();
+        assert!(
+            err.ends_with("Sapling can't edit JSON numbers yet"),
+            "{}",
+            err
+        );
+
+        // ...and so does reloading a file which has had numbers added to it
+        fs::write(&path, "[true]").unwrap();
+        let mut buffer = Buffer::<Json>::open(path.clone(), &arena, JsonFormat::Compact).unwrap();
+        fs::write(&path, "[true, 2]").unwrap();
+        assert!(buffer.reload().is_err());
+        assert_eq!(buffer.tree.to_text(&JsonFormat::Compact), "[true]");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
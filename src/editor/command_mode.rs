@@ -0,0 +1,683 @@
+//! The code for 'command-mode', where the user types a command (e.g. `:w file.json`) into the
+//! command line at the bottom of the screen
+
+use super::buffer::SwapAction;
+use super::dag::LogMessage;
+use super::{keystroke_log::Category, normal_mode, state, Editor};
+use crate::ast::{Ast, Format};
+use crate::core::{Path, Side};
+
+use std::borrow::Cow;
+use std::path::PathBuf;
+
+use crossterm::event::{KeyCode, KeyEvent};
+use tui::layout::Direction;
+
+/// The full name of every command, along with the number of characters that the shortest
+/// abbreviation of that command needs.  For example, `:w`, `:wr` and `:write` are all
+/// `:write`.
+const COMMANDS: [(&str, usize); 20] = [
+    ("bdelete", 2),
+    ("bnext", 2),
+    ("bprevious", 2),
+    ("buffer", 1),
+    ("close", 3),
+    ("edit", 1),
+    ("goto", 1),
+    ("ls", 2),
+    ("quit", 1),
+    ("recover", 3),
+    ("saveas", 3),
+    ("set", 2),
+    ("split", 2),
+    ("undo", 1),
+    ("vsplit", 2),
+    ("wnext", 2),
+    ("wprevious", 2),
+    ("wq", 2),
+    ("write", 1),
+    ("xit", 1),
+];
+
+/// A command which can be typed into the command line
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Command {
+    /// Write the tree to a file, or to the file being edited if no file is given.  `force` is
+    /// `true` if the command was typed with a `!`, in which case the file being edited is written
+    /// even if it has been changed by another program.
+    Write { path: Option<PathBuf>, force: bool },
+    /// Write the tree to a file, which then becomes the file being edited
+    SaveAs(PathBuf),
+    /// Quit Sapling.  `force` is `true` if the command was typed with a `!`, in which case any
+    /// unsaved changes are thrown away.
+    Quit { force: bool },
+    /// Write the tree (to a given file, if there is one) and then quit Sapling.  If
+    /// `only_if_dirty` is `true` then the tree is only written to its own file if it has unsaved
+    /// changes.  `force` is `true` if the command was typed with a `!`, in which case the unsaved
+    /// changes in other buffers are thrown away (and the file is written even if it has been
+    /// changed by another program).
+    WriteQuit {
+        path: Option<PathBuf>,
+        only_if_dirty: bool,
+        force: bool,
+    },
+    /// Open a file in a new buffer, or switch to its buffer if it's already open
+    Edit(PathBuf),
+    /// Deal with the swap file left behind for the current buffer's file
+    Recover(SwapAction),
+    /// Reload the current buffer from its file.  `force` is `true` if the command was typed with a
+    /// `!`, in which case any unsaved changes are thrown away.
+    Reload { force: bool },
+    /// Switch to the buffer with a given number (counting from 1)
+    Buffer(usize),
+    /// Move to the next or previous buffer
+    CycleBuffer(Side),
+    /// List every open buffer
+    ListBuffers,
+    /// Close the current buffer.  `force` is `true` if the command was typed with a `!`, in which
+    /// case any unsaved changes are thrown away.
+    CloseBuffer { force: bool },
+    /// Split the focused pane in two, with the new pane either below (if the [`Direction`] is
+    /// [`Direction::Vertical`]) or to the right
+    Split(Direction),
+    /// Close the focused pane
+    ClosePane,
+    /// Move the focus to the next or previous pane
+    CyclePane(Side),
+    /// Set an option (e.g. `format`) to a value
+    Set { option: String, value: String },
+    /// Move the cursor to the node at a given [`Path`]
+    Goto(Path),
+    /// Undo a given number of changes
+    Undo(usize),
+}
+
+/// Returns the full name of the command which `name` is an abbreviation of, if it exists
+fn resolve_name(name: &str) -> Option<&'static str> {
+    COMMANDS
+        .iter()
+        .find(|(full_name, min_length)| name.len() >= *min_length && full_name.starts_with(name))
+        .map(|(full_name, _)| *full_name)
+}
+
+/// Parses a line typed into the command line (without the leading `:`) into a [`Command`],
+/// returning a message for the user if the line isn't a valid command
+fn parse_command(line: &str) -> Result<Command, String> {
+    let line = line.trim();
+    let (word, args) = match line.split_once(char::is_whitespace) {
+        Some((word, args)) => (word, args.trim()),
+        None => (line, ""),
+    };
+    let (word, force) = match word.strip_suffix('!') {
+        Some(word) => (word, true),
+        None => (word, false),
+    };
+    let name = resolve_name(word).ok_or_else(|| format!("Unknown command ':{}'", word))?;
+    if force && !["write", "quit", "wq", "xit", "edit", "bdelete"].contains(&name) {
+        return Err(format!("':{}' can't be forced with '!'", name));
+    }
+    let path_arg = || (!args.is_empty()).then(|| PathBuf::from(args));
+    // Commands which take no arguments can all be handled in the same way
+    let no_args_command = match name {
+        "quit" => Some(Command::Quit { force }),
+        "bnext" => Some(Command::CycleBuffer(Side::Next)),
+        "bprevious" => Some(Command::CycleBuffer(Side::Prev)),
+        "ls" => Some(Command::ListBuffers),
+        "bdelete" => Some(Command::CloseBuffer { force }),
+        "split" => Some(Command::Split(Direction::Vertical)),
+        "vsplit" => Some(Command::Split(Direction::Horizontal)),
+        "close" => Some(Command::ClosePane),
+        "wnext" => Some(Command::CyclePane(Side::Next)),
+        "wprevious" => Some(Command::CyclePane(Side::Prev)),
+        _ => None,
+    };
+    if let Some(command) = no_args_command {
+        return match args.is_empty() {
+            true => Ok(command),
+            false => Err(format!("':{}' doesn't take any arguments", name)),
+        };
+    }
+    match name {
+        "write" => Ok(Command::Write {
+            path: path_arg(),
+            force,
+        }),
+        "saveas" => path_arg()
+            .map(Command::SaveAs)
+            .ok_or_else(|| "Expected ':saveas <path>'".to_owned()),
+        "wq" | "xit" => Ok(Command::WriteQuit {
+            path: path_arg(),
+            only_if_dirty: name == "xit",
+            force,
+        }),
+        "edit" => match path_arg() {
+            // Opening a file never throws away changes, so there's nothing to force
+            Some(_) if force => Err("':edit!' can't be given a file".to_owned()),
+            Some(path) => Ok(Command::Edit(path)),
+            None => Ok(Command::Reload { force }),
+        },
+        "recover" => match args {
+            "" => Ok(Command::Recover(SwapAction::Recover)),
+            "diff" => Ok(Command::Recover(SwapAction::Diff)),
+            "discard" => Ok(Command::Recover(SwapAction::Discard)),
+            _ => Err("Expected ':recover', ':recover diff' or ':recover discard'".to_owned()),
+        },
+        "buffer" => args
+            .parse()
+            .ok()
+            .filter(|number| *number > 0)
+            .map(Command::Buffer)
+            .ok_or_else(|| format!("Invalid buffer number '{}'", args)),
+        "set" => match args.split_once('=') {
+            Some((option, value)) => Ok(Command::Set {
+                option: option.trim().to_owned(),
+                value: value.trim().to_owned(),
+            }),
+            None => Err("Expected ':set <option>=<value>'".to_owned()),
+        },
+        "goto" => args
+            .parse()
+            .map(Command::Goto)
+            .map_err(|_| format!("Invalid path '{}' (expected e.g. '/0/3/1')", args)),
+        "undo" if args.is_empty() => Ok(Command::Undo(1)),
+        "undo" => args
+            .parse()
+            .map(Command::Undo)
+            .map_err(|_| format!("Invalid number of changes '{}'", args)),
+        _ => unreachable!("Every command name should be handled"),
+    }
+}
+
+/// Completes the command name at the start of `line` as far as possible, returning the names of
+/// every command that it could complete to.  If there's only one such command, `line` is
+/// completed to its full name.
+fn complete_command_name(line: &mut String) -> Vec<&'static str> {
+    // Only the command name (i.e. the first word) can be completed
+    if line.contains(char::is_whitespace) {
+        return Vec::new();
+    }
+    let candidates: Vec<&'static str> = COMMANDS
+        .iter()
+        .map(|(name, _)| *name)
+        .filter(|name| name.starts_with(line.as_str()))
+        .collect();
+    if let Some(first) = candidates.first() {
+        // Extend the line to the longest prefix shared by all the candidates
+        let common_length = candidates
+            .iter()
+            .map(|name| {
+                name.chars()
+                    .zip(first.chars())
+                    .take_while(|(a, b)| a == b)
+                    .count()
+            })
+            .min()
+            .unwrap_or(0);
+        *line = first[..common_length].to_owned();
+    }
+    candidates
+}
+
+/// The [`State`](state::State) that Sapling enters whilst the user is typing a command.
+#[derive(Debug, Clone)]
+pub struct State {
+    /// The text typed so far (not including the leading `:`)
+    line: String,
+    /// The index into [`Editor::command_history`] of the command being shown, which is the
+    /// length of the history if the user is typing a new command
+    history_index: usize,
+    /// The new command that the user was typing before they started moving through the history
+    draft: String,
+}
+
+impl State {
+    /// Creates a `State` with an empty command line, given the length of the command history
+    pub fn new(history_len: usize) -> Self {
+        State {
+            line: String::new(),
+            history_index: history_len,
+            draft: String::new(),
+        }
+    }
+}
+
+impl<'arena, Node: Ast<'arena>> state::State<'arena, Node> for State {
+    fn transition(
+        mut self: Box<Self>,
+        key: KeyEvent,
+        editor: &mut Editor<'arena, Node>,
+    ) -> (
+        Box<dyn state::State<'arena, Node>>,
+        Option<(String, Category)>,
+    ) {
+        match key.code {
+            // Run the command
+            KeyCode::Enter => {
+                if editor.command_history.last() != Some(&self.line) && !self.line.is_empty() {
+                    editor.command_history.push(self.line.clone());
+                }
+                let (new_state, log_entry) = match parse_command(&self.line) {
+                    Ok(command) => run_command(command, editor),
+                    Err(message) => {
+                        log::warn!("{}", message);
+                        (
+                            Box::new(normal_mode::State::default())
+                                as Box<dyn state::State<'arena, Node>>,
+                            Category::Undefined,
+                        )
+                    }
+                };
+                (new_state, Some((format!(":{}", self.line), log_entry)))
+            }
+            // Cancel the command, as does deleting the `:`
+            KeyCode::Esc => cancel(),
+            KeyCode::Backspace if self.line.is_empty() => cancel(),
+            KeyCode::Backspace => {
+                self.line.pop();
+                (self, None)
+            }
+            KeyCode::Tab => {
+                let candidates = complete_command_name(&mut self.line);
+                if candidates.len() > 1 {
+                    log::info!("Possible commands: {}", candidates.join(", "));
+                }
+                (self, None)
+            }
+            // Move through the command history
+            KeyCode::Up if self.history_index > 0 => {
+                if self.history_index == editor.command_history.len() {
+                    self.draft = std::mem::take(&mut self.line);
+                }
+                self.history_index -= 1;
+                self.line
+                    .clone_from(&editor.command_history[self.history_index]);
+                (self, None)
+            }
+            KeyCode::Down if self.history_index < editor.command_history.len() => {
+                self.history_index += 1;
+                self.line = match editor.command_history.get(self.history_index) {
+                    Some(line) => line.clone(),
+                    None => std::mem::take(&mut self.draft),
+                };
+                (self, None)
+            }
+            KeyCode::Char(c) => {
+                self.line.push(c);
+                (self, None)
+            }
+            // Ignore any other keys
+            _ => (self, None),
+        }
+    }
+
+    fn mode_name(&self) -> &'static str {
+        "COMMAND"
+    }
+
+    fn prompt(&self) -> Option<Cow<'_, str>> {
+        Some(Cow::from(format!(":{}", self.line)))
+    }
+}
+
+/// Returns to normal mode without running a command
+fn cancel<'arena, Node: Ast<'arena>>() -> (
+    Box<dyn state::State<'arena, Node>>,
+    Option<(String, Category)>,
+) {
+    (
+        Box::new(normal_mode::State::default()),
+        Some(("cancel command".to_owned(), Category::Undefined)),
+    )
+}
+
+/// Runs a [`Command`], returning the [`State`](state::State) that Sapling should enter
+/// afterwards and the [`Category`] of the command
+fn run_command<'arena, Node: Ast<'arena>>(
+    command: Command,
+    editor: &mut Editor<'arena, Node>,
+) -> (Box<dyn state::State<'arena, Node>>, Category) {
+    let category = match command {
+        Command::Quit { force } => {
+            if editor.can_quit(force) {
+                return (Box::new(state::Quit), Category::Quit);
+            }
+            Category::Quit
+        }
+        Command::WriteQuit {
+            path,
+            only_if_dirty,
+            force,
+        } => {
+            // A `!` both overwrites changes made to the file by other programs and throws away
+            // the unsaved changes in other buffers
+            if editor.write_and_quit(path, only_if_dirty, force, force) {
+                return (Box::new(state::Quit), Category::Quit);
+            }
+            Category::Quit
+        }
+        Command::Write { path, force } => {
+            editor.buffer_mut().write(path, force).log_message();
+            Category::IO
+        }
+        Command::SaveAs(path) => {
+            editor.buffer_mut().save_as(path).log_message();
+            Category::IO
+        }
+        Command::Edit(path) => {
+            editor.open_file(path);
+            Category::IO
+        }
+        Command::Recover(action) => {
+            editor.handle_old_swap_file(action);
+            Category::IO
+        }
+        Command::Reload { force } => {
+            editor.reload_buffer(force);
+            Category::IO
+        }
+        Command::Buffer(number) => {
+            editor.switch_buffer(number - 1);
+            Category::IO
+        }
+        Command::CycleBuffer(side) => {
+            editor.cycle_buffer(1, side);
+            Category::IO
+        }
+        Command::ListBuffers => {
+            editor.list_buffers();
+            Category::IO
+        }
+        Command::CloseBuffer { force } => {
+            editor.close_buffer(force);
+            Category::IO
+        }
+        Command::Split(direction) => {
+            editor.split_pane(direction);
+            Category::Move
+        }
+        Command::ClosePane => {
+            editor.close_pane();
+            Category::Move
+        }
+        Command::CyclePane(side) => {
+            editor.cycle_pane(1, side);
+            Category::Move
+        }
+        Command::Set { option, value } => {
+            match option.as_str() {
+                "format" => match value.parse::<Node::FormatStyle>() {
+                    Ok(format_style) => {
+                        let indent_width = editor.config.indent_width;
+                        editor.buffer_mut().format_style =
+                            format_style.with_indent_width(indent_width);
+                    }
+                    Err(message) => log::warn!("{}", message),
+                },
+                _ => log::warn!("Unknown option '{}'", option),
+            }
+            Category::IO
+        }
+        Command::Goto(path) => {
+            editor.buffer_mut().tree.move_to_path(path).log_message();
+            Category::Move
+        }
+        Command::Undo(count) => {
+            editor.buffer_mut().tree.undo(count).log_message();
+            Category::History
+        }
+    };
+    (Box::new(normal_mode::State::default()), category)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{complete_command_name, parse_command, run_command, Command, SwapAction};
+    use crate::arena::Arena;
+    use crate::ast::json::{add_value_to_arena, Json, JsonFormat};
+    use crate::config::Config;
+    use crate::core::{Path, Side};
+    use crate::editor::buffer::tests::{change_externally, test_dir};
+    use crate::editor::{buffer::Buffer, dag::Dag, Editor};
+
+    use std::fs;
+    use std::path::PathBuf;
+
+    use serde_json::json;
+    use tui::layout::Direction;
+
+    #[test]
+    fn parse_valid() {
+        for (line, expected) in [
+            (
+                "w",
+                Command::Write {
+                    path: None,
+                    force: false,
+                },
+            ),
+            (
+                "write  out.json ",
+                Command::Write {
+                    path: Some(PathBuf::from("out.json")),
+                    force: false,
+                },
+            ),
+            (
+                "w!",
+                Command::Write {
+                    path: None,
+                    force: true,
+                },
+            ),
+            ("sav new.json", Command::SaveAs(PathBuf::from("new.json"))),
+            ("q", Command::Quit { force: false }),
+            ("qui!", Command::Quit { force: true }),
+            (
+                "wq",
+                Command::WriteQuit {
+                    path: None,
+                    only_if_dirty: false,
+                    force: false,
+                },
+            ),
+            (
+                "wq! out.json",
+                Command::WriteQuit {
+                    path: Some(PathBuf::from("out.json")),
+                    only_if_dirty: false,
+                    force: true,
+                },
+            ),
+            (
+                "x",
+                Command::WriteQuit {
+                    path: None,
+                    only_if_dirty: true,
+                    force: false,
+                },
+            ),
+            ("e a b.json", Command::Edit(PathBuf::from("a b.json"))),
+            ("rec", Command::Recover(SwapAction::Recover)),
+            ("recover  diff", Command::Recover(SwapAction::Diff)),
+            ("rec discard", Command::Recover(SwapAction::Discard)),
+            ("e", Command::Reload { force: false }),
+            ("edit!", Command::Reload { force: true }),
+            ("b 2", Command::Buffer(2)),
+            ("bn", Command::CycleBuffer(Side::Next)),
+            ("bprev", Command::CycleBuffer(Side::Prev)),
+            ("ls", Command::ListBuffers),
+            ("bd!", Command::CloseBuffer { force: true }),
+            ("sp", Command::Split(Direction::Vertical)),
+            ("vsplit", Command::Split(Direction::Horizontal)),
+            ("clo", Command::ClosePane),
+            ("wn", Command::CyclePane(Side::Next)),
+            ("wp", Command::CyclePane(Side::Prev)),
+            (
+                "se format = compact",
+                Command::Set {
+                    option: "format".to_owned(),
+                    value: "compact".to_owned(),
+                },
+            ),
+            ("goto /0/3/1", Command::Goto(Path::from_vec(vec![0, 3, 1]))),
+            ("g /", Command::Goto(Path::root())),
+            ("u", Command::Undo(1)),
+            ("undo 12", Command::Undo(12)),
+        ] {
+            assert_eq!(parse_command(line), Ok(expected));
+        }
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for line in &[
+            "",
+            "s format=pretty",
+            "writer",
+            "saveas",
+            "sa out.json",
+            "re",
+            "rec all",
+            "sav! out.json",
+            "q now",
+            "set format",
+            "goto 0/x",
+            "undo -1",
+            "e! other.json",
+            "b 0",
+            "b",
+            "ls -a",
+            "bn!",
+            "cl",
+            "sp other.json",
+        ] {
+            println!("Testing {:?}", line);
+            assert!(parse_command(line).is_err());
+        }
+    }
+
+    #[test]
+    fn completion() {
+        for (line, expected_line, expected_candidates) in [
+            ("wr", "write", vec!["write"]),
+            (
+                "",
+                "",
+                vec![
+                    "bdelete",
+                    "bnext",
+                    "bprevious",
+                    "buffer",
+                    "close",
+                    "edit",
+                    "goto",
+                    "ls",
+                    "quit",
+                    "recover",
+                    "saveas",
+                    "set",
+                    "split",
+                    "undo",
+                    "vsplit",
+                    "wnext",
+                    "wprevious",
+                    "wq",
+                    "write",
+                    "xit",
+                ],
+            ),
+            ("w", "w", vec!["wnext", "wprevious", "wq", "write"]),
+            ("bp", "bprevious", vec!["bprevious"]),
+            ("x", "xit", vec!["xit"]),
+            ("z", "z", vec![]),
+            ("w foo", "w foo", vec![]),
+        ] {
+            let mut line = line.to_owned();
+            assert_eq!(complete_command_name(&mut line), expected_candidates);
+            assert_eq!(line, expected_line);
+        }
+    }
+
+    #[test]
+    fn switch_buffers() {
+        let arena = Arena::new();
+        let new_buffer = |path: Vec<usize>| {
+            let root = add_value_to_arena(json!([[true, false], null]), &arena);
+            let tree = Dag::new(&arena, root, Path::from_vec(path));
+            Buffer::new(tree, None, JsonFormat::Compact)
+        };
+        let mut editor = Editor::headless(new_buffer(vec![0, 1]), Config::default());
+        editor.buffers.push(new_buffer(vec![1]));
+        editor.panes.focused_mut().line_offset = 5;
+        editor.panes.focused_mut().column_offset = 3;
+
+        // Switching shows the other buffer from the top, with its own cursor
+        run_command(Command::Buffer(2), &mut editor);
+        let pane = editor.panes.focused();
+        assert_eq!(pane.buffer, 1);
+        assert_eq!((pane.line_offset, pane.column_offset), (0, 0));
+        assert_eq!(*pane.cursor_path(), Path::from_vec(vec![1]));
+        // The pane follows edits to the buffer it's now showing
+        editor.buffer_mut().tree.delete_cursor(1).unwrap();
+        let Editor { panes, buffers, .. } = &mut editor;
+        panes.focused_mut().follow_edits(&buffers[1].tree);
+        assert_eq!(*panes.focused().cursor_path(), Path::from_vec(vec![0]));
+        assert!(std::ptr::eq(
+            panes.focused().cursor(),
+            buffers[1].tree.cursor()
+        ));
+
+        // Switching back restores the first buffer's cursor
+        run_command(Command::Buffer(1), &mut editor);
+        let pane = editor.panes.focused();
+        assert_eq!(pane.buffer, 0);
+        assert_eq!(*pane.cursor_path(), Path::from_vec(vec![0, 1]));
+        assert_eq!(
+            *editor.buffer().tree.cursor_path(),
+            Path::from_vec(vec![0, 1])
+        );
+    }
+
+    #[test]
+    fn quit_and_write_quit() {
+        fn quits<'arena>(editor: &mut Editor<'arena, Json<'arena>>, line: &str) -> bool {
+            let (state, _) = run_command(parse_command(line).unwrap(), editor);
+            state.is_quit()
+        }
+
+        let dir = test_dir("quit");
+        let path = dir.join("file.json");
+        let arena = Arena::new();
+        let root = add_value_to_arena(json!([true, false]), &arena);
+        let tree = Dag::new(&arena, root, Path::from_vec(vec![0]));
+        let buffer = Buffer::new(tree, None, JsonFormat::Compact);
+        let mut editor = Editor::headless(buffer, Config::default());
+        // Clean buffers can be quit straight away, and `:x` doesn't need to write them.  `:wq`
+        // always writes, so fails without a file.
+        assert!(quits(&mut editor, "q"));
+        assert!(quits(&mut editor, "x"));
+        assert!(!quits(&mut editor, "wq"));
+        // Unsaved changes can only be thrown away with `!`
+        editor.buffer_mut().tree.delete_cursor(1).unwrap();
+        assert!(!quits(&mut editor, "q"));
+        assert!(!quits(&mut editor, "x"));
+        assert!(quits(&mut editor, "q!"));
+
+        // `:x` writes unsaved changes before quitting
+        editor.buffer_mut().file_path = Some(path.clone());
+        assert!(quits(&mut editor, "x"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[false]\n");
+        assert!(!editor.buffer().tree.is_dirty());
+        // Another program changes the file
+        change_externally(&path, "[null]");
+        // The buffer is clean, so `:x` quits without overwriting the file
+        assert!(quits(&mut editor, "x"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[null]");
+        // `:wq` won't overwrite the other program's changes, but `:wq!` will
+        assert!(!quits(&mut editor, "wq"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[null]");
+        assert!(quits(&mut editor, "wq!"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[false]\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -2,18 +2,26 @@
 
 use crate::arena::Arena;
 use crate::ast;
-use crate::ast::{Ast, AstClass};
+use crate::ast::{display_token::Folds, pattern::Pattern, Ast, AstClass};
 
-use crate::core::{Direction, Path, Side};
-use std::{collections::HashMap, hash::Hash};
+use crate::core::{Direction, Fold, Path, Side};
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+};
 
-/// The two possible locations where an edit could cause nodes to be replaced
+/// The most locations that the jump list remembers.  Older locations are forgotten first.
+const MAX_JUMP_LIST_LEN: usize = 100;
+
+/// The three possible locations where an edit could cause nodes to be replaced
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 enum EditLocation {
     /// The edit caused the cursor to be replaced
     Cursor = 0,
     /// The edit caused the parent to be replaced
     Parent = 1,
+    /// The edit caused the grandparent to be replaced
+    Grandparent = 2,
 }
 
 impl EditLocation {
@@ -33,8 +41,49 @@ pub enum EditSuccess<C: AstClass> {
     Move(usize, Direction),
     Replace(C),
     InsertChild(C),
-    InsertNextToCursor { side: Side, class: C },
-    Delete { name: String },
+    InsertNextToCursor {
+        side: Side,
+        class: C,
+    },
+    Delete {
+        name: String,
+    },
+    MoveNode {
+        name: String,
+        distance: usize,
+        side: Side,
+    },
+    Slurp {
+        name: String,
+        side: Side,
+    },
+    Barf {
+        name: String,
+        side: Side,
+    },
+    MoveToMatch {
+        path: Path,
+        index: usize,
+        total: usize,
+    },
+    ReplaceMatches {
+        count: usize,
+    },
+    Put {
+        count: usize,
+    },
+    MoveToPath(Path),
+    SetMark(char),
+    MoveToMark {
+        name: char,
+        path: Path,
+    },
+    Jump {
+        side: Side,
+        path: Path,
+    },
+    Fold(usize),
+    Unfold(usize),
 }
 
 impl<C: AstClass> EditSuccess<C> {
@@ -51,6 +100,31 @@ impl<C: AstClass> EditSuccess<C> {
                 log::info!("Moving to {}th previous sibling", n)
             }
             EditSuccess::Move(n, Direction::Next) => log::info!("Moving to {}th next sibling", n),
+            EditSuccess::Move(n, Direction::FirstSibling) => {
+                log::info!("Moving {} places back to the first sibling", n)
+            }
+            EditSuccess::Move(n, Direction::LastSibling) => {
+                log::info!("Moving {} places forward to the last sibling", n)
+            }
+            EditSuccess::Move(n, Direction::Root) => {
+                log::info!("Moving {} levels up to the root", n)
+            }
+            EditSuccess::Move(n, Direction::PreorderNext) => {
+                log::info!("Moving {} nodes forward", n)
+            }
+            EditSuccess::Move(n, Direction::PreorderPrev) => log::info!("Moving {} nodes back", n),
+            EditSuccess::Move(n, Direction::NextLeaf) => log::info!("Moving to {}th next leaf", n),
+            EditSuccess::Move(n, Direction::PrevLeaf) => {
+                log::info!("Moving to {}th previous leaf", n)
+            }
+            EditSuccess::Move(n, Direction::NextOfClass) => {
+                log::info!("Moving to {}th next node of the same type", n)
+            }
+            EditSuccess::Move(n, Direction::PrevOfClass) => {
+                log::info!("Moving to {}th previous node of the same type", n)
+            }
+            EditSuccess::Move(0, Direction::NthChild) => log::info!("Cursor has no children"),
+            EditSuccess::Move(n, Direction::NthChild) => log::info!("Moving to child #{}", n),
             EditSuccess::Replace(class) => {
                 log::info!("Replacing with '{}'/{}", class.to_char(), class.name())
             }
@@ -68,6 +142,51 @@ impl<C: AstClass> EditSuccess<C> {
                 side.relational_word()
             ),
             EditSuccess::Delete { name } => log::info!("Deleting {}", name),
+            EditSuccess::MoveNode {
+                name,
+                distance,
+                side,
+            } => log::info!(
+                "Moving {} {} places towards the {} sibling",
+                name,
+                distance,
+                match side {
+                    Side::Prev => "first",
+                    Side::Next => "last",
+                }
+            ),
+            EditSuccess::Slurp { name, side } => log::info!(
+                "Moving {} into the container {} it",
+                name,
+                side.relational_word()
+            ),
+            EditSuccess::Barf { name, side } => log::info!(
+                "Moving {} out of its parent, to {} the parent",
+                name,
+                side.relational_word()
+            ),
+            EditSuccess::MoveToMatch { path, index, total } => {
+                log::info!("Moving to match {}/{} at {}", index + 1, total, path)
+            }
+            EditSuccess::ReplaceMatches { count } => log::info!("Replaced {} matches", count),
+            EditSuccess::Put { count } => log::info!("Putting {} nodes after the cursor", count),
+            EditSuccess::MoveToPath(path) => log::info!("Moving to {}", path),
+            EditSuccess::SetMark(name) => log::info!("Setting mark '{}' at the cursor", name),
+            EditSuccess::MoveToMark { name, path } => {
+                log::info!("Moving to mark '{}' at {}", name, path)
+            }
+            EditSuccess::Jump { side, path } => log::info!(
+                "Jumping to {} location {}",
+                match side {
+                    Side::Prev => "older",
+                    Side::Next => "newer",
+                },
+                path
+            ),
+            EditSuccess::Fold(1) => log::info!("Folding 1 node"),
+            EditSuccess::Fold(n) => log::info!("Folding {} nodes", n),
+            EditSuccess::Unfold(1) => log::info!("Unfolding 1 node"),
+            EditSuccess::Unfold(n) => log::info!("Unfolding {} nodes", n),
         }
     }
 }
@@ -78,6 +197,18 @@ pub enum EditErr<C: AstClass> {
     /* MOVEMENT ERRORS */
     /// Trying to move to a sibling of the root
     MoveToSiblingOfRoot,
+    /// Trying to move to a [`Path`] which doesn't point to a node
+    NoSuchNode(Path),
+    /// Trying to move to a mark which hasn't been set
+    NoSuchMark(char),
+    /// Trying to move past the oldest or newest location in the jump list
+    NoJumpLocation(Side),
+
+    /* FOLDING ERRORS */
+    /// Trying to fold a tree where no nodes have children
+    NothingToFold,
+    /// Trying to unfold the cursor when it isn't folded
+    NoFoldAtCursor,
 
     /* EDITING ERRORS */
     /// Trying to undo the earliest change
@@ -114,6 +245,20 @@ pub enum EditErr<C: AstClass> {
     AddSiblingToRoot,
     /// Trying to delete the root
     DeletingRoot,
+    /// Trying to move the root, which has no siblings to move past
+    MovingRoot,
+    /// Trying to move a node past the first or last of its siblings
+    NoSiblingToMovePast(Side),
+    /// Trying to move a node into a sibling which doesn't exist
+    NoSiblingToMoveInto(Side),
+    /// Trying to move a node out of its parent, when that parent is the root
+    MoveOutOfRoot,
+
+    /* SEARCH ERRORS */
+    /// No nodes in the tree matched the search pattern (given as its source string)
+    NoMatches(String),
+    /// A replacement couldn't be built or doesn't fit where it would be placed
+    InvalidReplacement(String),
 }
 
 impl<C: AstClass> EditErr<C> {
@@ -122,6 +267,17 @@ impl<C: AstClass> EditErr<C> {
     fn log_message(self) {
         match self {
             EditErr::MoveToSiblingOfRoot => log::warn!("Can't move to a sibling of the root."),
+            EditErr::NoSuchNode(path) => log::warn!("There's no node at {}.", path),
+            EditErr::NoSuchMark(name) => log::warn!("Mark '{}' isn't set.", name),
+            EditErr::NoJumpLocation(side) => log::warn!(
+                "No {} location in the jump list.",
+                match side {
+                    Side::Prev => "older",
+                    Side::Next => "newer",
+                }
+            ),
+            EditErr::NothingToFold => log::warn!("There are no nodes with children to fold."),
+            EditErr::NoFoldAtCursor => log::warn!("The cursor isn't folded."),
             EditErr::NoChangesToUndo => log::warn!("No changes to undo."),
             EditErr::NoChangesToRedo => log::warn!("No changes to redo."),
             EditErr::NoNodesToInsert => log::warn!("No nodes to insert."),
@@ -148,6 +304,18 @@ impl<C: AstClass> EditErr<C> {
             }
             EditErr::AddSiblingToRoot => log::warn!("Can't add siblings to the root."),
             EditErr::DeletingRoot => log::warn!("Can't delete the root."),
+            EditErr::MovingRoot => log::warn!("Can't move the root."),
+            EditErr::NoSiblingToMovePast(side) => log::warn!(
+                "Can't move the cursor node: there is no sibling {} it.",
+                side.relational_word()
+            ),
+            EditErr::NoSiblingToMoveInto(side) => log::warn!(
+                "Can't move the cursor node: there is no sibling {} it to move into.",
+                side.relational_word()
+            ),
+            EditErr::MoveOutOfRoot => log::warn!("Can't move a node out of the root."),
+            EditErr::NoMatches(pattern) => log::warn!("No nodes match '{}'.", pattern),
+            EditErr::InvalidReplacement(reason) => log::warn!("Invalid replacement: {}", reason),
         }
     }
 }
@@ -237,6 +405,20 @@ pub struct Dag<'arena, Node: Ast<'arena>> {
     history_index: usize,
     current_cursor_path: Path,
     cursor_location_history: HashMap<usize, usize>,
+    /// The locations of the named marks.  These are rebased whenever the root changes, so they
+    /// keep pointing to the same nodes.
+    marks: HashMap<char, Path>,
+    /// The locations that the cursor has jumped away from, oldest first.  These are rebased in
+    /// the same way as [`marks`](Dag::marks).
+    jump_list: Vec<Path>,
+    /// An index into [`jump_list`](Dag::jump_list) of the location the cursor last jumped to.
+    /// This is `jump_list.len()` if the user isn't moving through the jump list.
+    jump_index: usize,
+    /// The root of the tree when it was last saved (or when the `Dag` was created)
+    saved_root: &'arena Node,
+    /// The locations of the folded nodes.  Like [`marks`](Dag::marks), these are rebased whenever
+    /// the root changes, but folds on deleted nodes are removed.
+    folded_paths: HashSet<Path>,
 }
 
 impl<'arena, Node: Ast<'arena>> Dag<'arena, Node> {
@@ -252,9 +434,19 @@ impl<'arena, Node: Ast<'arena>> Dag<'arena, Node> {
             history_index: 0,
             current_cursor_path: cursor_path,
             cursor_location_history: HashMap::new(),
+            marks: HashMap::new(),
+            jump_list: Vec::new(),
+            jump_index: 0,
+            saved_root: root,
+            folded_paths: HashSet::new(),
         }
     }
 
+    /// Returns the arena in which this `Dag`'s nodes are allocated
+    pub fn arena(&self) -> &'arena Arena<Node> {
+        self.arena
+    }
+
     /* NAVIGATION METHODS */
 
     /// Returns a reference to the node that is currently the root of the AST.
@@ -274,6 +466,150 @@ impl<'arena, Node: Ast<'arena>> Dag<'arena, Node> {
         self.current_cursor_path.cursor(self.root())
     }
 
+    /// Returns the [`Path`] from the root to the node under the cursor
+    pub fn cursor_path(&self) -> &Path {
+        &self.current_cursor_path
+    }
+
+    /// Moves the cursor to a given [`Path`], without recording a jump.  `path` must point to a
+    /// node in the current tree.
+    pub fn set_cursor_path(&mut self, path: Path) {
+        debug_assert!(path.try_cursor(self.root()).is_some());
+        self.current_cursor_path = path;
+    }
+
+    /// Move the cursor to the `count`th node which matches a given [`Pattern`], searching in
+    /// pre-order from the cursor towards the given [`Side`] and wrapping around the ends of the
+    /// tree.
+    pub fn move_to_match(
+        &mut self,
+        count: usize,
+        pattern: &Pattern,
+        side: Side,
+    ) -> EditResult<Node::Class> {
+        let matches = pattern.find_all(self.root());
+        if matches.is_empty() {
+            return Err(EditErr::NoMatches(pattern.source().to_owned()));
+        }
+        let total = matches.len();
+        // `find_all` returns the matches sorted by their paths, so we can binary search for the
+        // cursor's position among them
+        let index = match side {
+            Side::Next => {
+                let first_after = matches.partition_point(|m| m.path <= self.current_cursor_path);
+                (first_after + count - 1) % total
+            }
+            Side::Prev => {
+                let num_before = matches.partition_point(|m| m.path < self.current_cursor_path);
+                (num_before + total - count % total) % total
+            }
+        };
+        let path = matches[index].path.clone();
+        self.record_jump();
+        self.current_cursor_path.clone_from(&path);
+        Ok(EditSuccess::MoveToMatch { path, index, total })
+    }
+
+    /// Moves the cursor to the node at a given [`Path`], recording the jump in the jump list
+    pub fn move_to_path(&mut self, path: Path) -> EditResult<Node::Class> {
+        if path.try_cursor(self.root()).is_none() {
+            return Err(EditErr::NoSuchNode(path));
+        }
+        self.record_jump();
+        self.current_cursor_path.clone_from(&path);
+        Ok(EditSuccess::MoveToPath(path))
+    }
+
+    /// Sets the mark `name` to the node under the cursor, replacing any previous location of
+    /// that mark
+    pub fn set_mark(&mut self, name: char) -> EditResult<Node::Class> {
+        self.marks.insert(name, self.current_cursor_path.clone());
+        Ok(EditSuccess::SetMark(name))
+    }
+
+    /// Moves the cursor to the node marked with `name`, recording the jump in the jump list
+    pub fn move_to_mark(&mut self, name: char) -> EditResult<Node::Class> {
+        let path = self
+            .marks
+            .get(&name)
+            .ok_or(EditErr::NoSuchMark(name))?
+            .clone();
+        self.record_jump();
+        self.current_cursor_path.clone_from(&path);
+        Ok(EditSuccess::MoveToMark { name, path })
+    }
+
+    /// Moves the cursor `count` locations through the jump list, towards older locations for
+    /// [`Side::Prev`] and newer locations for [`Side::Next`]
+    pub fn jump(&mut self, count: usize, side: Side) -> EditResult<Node::Class> {
+        let new_index = match side {
+            Side::Prev => {
+                if self.jump_index == 0 {
+                    return Err(EditErr::NoJumpLocation(side));
+                }
+                // If we're not already moving through the jump list, then remember the cursor's
+                // location so that we can jump forward to it again
+                if self.jump_index == self.jump_list.len() {
+                    self.push_jump();
+                }
+                self.jump_index.saturating_sub(count)
+            }
+            Side::Next => {
+                if self.jump_index + 1 >= self.jump_list.len() {
+                    return Err(EditErr::NoJumpLocation(side));
+                }
+                (self.jump_index + count).min(self.jump_list.len() - 1)
+            }
+        };
+        self.jump_index = new_index;
+        let path = self.jump_list[new_index].clone();
+        self.current_cursor_path.clone_from(&path);
+        Ok(EditSuccess::Jump { side, path })
+    }
+
+    /// Adds the cursor's location to the jump list, discarding any newer locations if the user
+    /// has moved back through the list
+    fn record_jump(&mut self) {
+        self.jump_list.truncate(self.jump_index);
+        if self.jump_list.last() != Some(&self.current_cursor_path) {
+            self.push_jump();
+        }
+        self.jump_index = self.jump_list.len();
+    }
+
+    /// Adds the cursor's location to the end of the jump list, forgetting the oldest location if
+    /// the list is full.  [`jump_index`](Self::jump_index) is moved so that it still refers to
+    /// the same location.
+    fn push_jump(&mut self) {
+        self.jump_list.push(self.current_cursor_path.clone());
+        if self.jump_list.len() > MAX_JUMP_LIST_LEN {
+            self.jump_list.remove(0);
+            self.jump_index = self.jump_index.saturating_sub(1);
+        }
+    }
+
+    /// Updates the marks, the jump list and the folds after the root has changed from
+    /// `old_root`, so that they point to the same nodes in the new tree
+    fn rebase_locations(&mut self, old_root: &'arena Node) {
+        let new_root = self.root();
+        // Searching for a node is slow, so only the locations which go through the nodes cloned
+        // by the edit are rebased
+        for path in self.marks.values_mut().chain(self.jump_list.iter_mut()) {
+            if !path.is_unchanged(old_root, new_root) {
+                *path = path.rebase(old_root, new_root);
+            }
+        }
+        self.folded_paths = std::mem::take(&mut self.folded_paths)
+            .into_iter()
+            .filter_map(|path| match path.is_unchanged(old_root, new_root) {
+                true => Some(path),
+                false => path.try_rebase(old_root, new_root),
+            })
+            // A node which has lost all its children can't stay folded
+            .filter(|path| !path.cursor(new_root).children().is_empty())
+            .collect();
+    }
+
     /// Move the cursor a given `distance` in a given [`Direction`] across the tree.
     pub fn move_cursor(
         &mut self,
@@ -337,10 +673,93 @@ impl<'arena, Node: Ast<'arena>> Dag<'arena, Node> {
                 // Return the distance we travelled
                 *index - last_index
             }
+            Direction::FirstSibling | Direction::LastSibling => {
+                let index = self
+                    .current_cursor_path
+                    .last_mut()
+                    .ok_or(EditErr::MoveToSiblingOfRoot)?;
+                let last_index = *index;
+                // As above, the unwrap and subtraction are safe because the cursor has a parent
+                let max_index = cursor_parent.unwrap().children().len() - 1;
+                // The distance counts siblings from the relevant end, so `1` is the first or last
+                // sibling itself
+                *index = if direction == Direction::FirstSibling {
+                    (distance.saturating_sub(1)).min(max_index)
+                } else {
+                    max_index.saturating_sub(distance.saturating_sub(1))
+                };
+                // Return the number of siblings we moved past
+                (*index as isize - last_index as isize).unsigned_abs()
+            }
+            Direction::Root => {
+                // Moving to the root is idempotent, so the distance is ignored
+                let depth = self.current_cursor_path.depth();
+                self.current_cursor_path = Path::root();
+                depth
+            }
+            Direction::NthChild => {
+                let num_children = current_cursor.children().len();
+                if num_children == 0 {
+                    0
+                } else {
+                    // The children are counted from 1, so that no count means the first child
+                    let index = (distance.max(1) - 1).min(num_children - 1);
+                    self.current_cursor_path.push(index);
+                    index + 1
+                }
+            }
+            Direction::PreorderNext => self.move_through_preorder(distance, Side::Next, |_| true),
+            Direction::PreorderPrev => self.move_through_preorder(distance, Side::Prev, |_| true),
+            Direction::NextLeaf => {
+                self.move_through_preorder(distance, Side::Next, |node| node.children().is_empty())
+            }
+            Direction::PrevLeaf => {
+                self.move_through_preorder(distance, Side::Prev, |node| node.children().is_empty())
+            }
+            Direction::NextOfClass | Direction::PrevOfClass => {
+                let class_name = current_cursor.class_name();
+                let side = if direction == Direction::NextOfClass {
+                    Side::Next
+                } else {
+                    Side::Prev
+                };
+                self.move_through_preorder(distance, side, |node| node.class_name() == class_name)
+            }
         };
         Ok(EditSuccess::Move(successful_distance, direction))
     }
 
+    /// Moves the cursor past `count` nodes for which `is_target` returns `true`, travelling
+    /// through a pre-order traversal of the tree towards the given [`Side`].  The cursor stops
+    /// at the last such node if there are fewer than `count` of them.  Returns the number of
+    /// target nodes that the cursor moved past.
+    fn move_through_preorder(
+        &mut self,
+        count: usize,
+        side: Side,
+        is_target: impl Fn(&'arena Node) -> bool,
+    ) -> usize {
+        let root = self.root();
+        let mut path = self.current_cursor_path.clone();
+        let mut num_targets = 0;
+        while num_targets < count {
+            let next_path = match side {
+                Side::Next => path.preorder_next(root),
+                Side::Prev => path.preorder_prev(root),
+            };
+            // Stop if we've reached the end of the traversal
+            path = match next_path {
+                Some(p) => p,
+                None => break,
+            };
+            if is_target(path.cursor(root)) {
+                num_targets += 1;
+                self.current_cursor_path.clone_from(&path);
+            }
+        }
+        num_targets
+    }
+
     /* HISTORY METHODS */
 
     /// Move one step back in the tree history
@@ -351,7 +770,9 @@ impl<'arena, Node: Ast<'arena>> Dag<'arena, Node> {
             return Err(EditErr::NoChangesToUndo);
         }
         // Move the history index back by one to perform the undo
+        let old_root = self.root();
         self.history_index = self.history_index.saturating_sub(steps);
+        self.rebase_locations(old_root);
         // Follow the behaviour of other text editors and update the location of the cursor
         // with its location in the snapshot we are going forward to
         self.current_cursor_path
@@ -368,7 +789,9 @@ impl<'arena, Node: Ast<'arena>> Dag<'arena, Node> {
             return Err(EditErr::NoChangesToRedo);
         }
         // Move the history index forward by one to perform the redo
+        let old_root = self.root();
         self.history_index = (self.history_index + steps).min(self.root_history.len() - 1);
+        self.rebase_locations(old_root);
         // Follow the behaviour of other text editors and update the location of the cursor
         // with its location in the snapshot we are going back to
         self.current_cursor_path
@@ -377,6 +800,51 @@ impl<'arena, Node: Ast<'arena>> Dag<'arena, Node> {
         Ok(EditSuccess::Redo)
     }
 
+    /// Returns the position of the current tree in the undo history.  The tree that the `Dag`
+    /// was created with has position `0`, and each edit increases the position by one.
+    pub fn history_index(&self) -> usize {
+        self.history_index
+    }
+
+    /// Returns the number of edits in the undo history, including any which have been undone.
+    /// This is the largest possible [`history_index`](Self::history_index).
+    pub fn num_edits(&self) -> usize {
+        self.root_history.len() - 1
+    }
+
+    /// Records that the current tree has been saved, so that the `Dag` is no longer
+    /// [dirty](Self::is_dirty)
+    pub fn mark_saved(&mut self) {
+        self.saved_root = self.root();
+    }
+
+    /// Returns `true` if the current tree is different to the one which was last saved.  Undoing
+    /// back to the saved tree makes the `Dag` clean again.
+    pub fn is_dirty(&self) -> bool {
+        !std::ptr::eq(self.root(), self.saved_root)
+    }
+
+    /// Combines every edit made since the history was at position `start` (as returned by
+    /// [`history_index`](Self::history_index)) into one edit, so that they will be undone and
+    /// redone together.  Any changes which could have been redone are discarded.
+    pub fn group_edits_since(&mut self, start: usize) {
+        // There's nothing to do unless at least two edits have been made since `start`
+        if self.history_index <= start + 1 {
+            return;
+        }
+        let cursor_before = self.root_history[start + 1].cursor_before.clone();
+        self.root_history.truncate(self.history_index + 1);
+        // Unwrap is safe because the history is longer than `start + 2`
+        let latest = self.root_history.pop().unwrap();
+        self.root_history.truncate(start + 1);
+        self.root_history.push(Snapshot::new(
+            cursor_before,
+            latest.root,
+            latest.cursor_after,
+        ));
+        self.history_index = start + 1;
+    }
+
     /* EDITING METHODS */
 
     fn perform_edit(
@@ -436,26 +904,44 @@ impl<'arena, Node: Ast<'arena>> Dag<'arena, Node> {
 
         /* UPDATE THE HISTORY */
 
+        // At this point, `node` contains a reference to the root of the new tree, so we just add
+        // this to the history, along with the cursor path.
+        self.push_snapshot(old_cursor_path, node);
+
+        /* RETURN SUCCESS */
+        Ok(success)
+    }
+
+    /// Makes `new_root` the root of the latest tree in the history, discarding any changes which
+    /// could have been redone.  The cursor is assumed to have already been moved to its new
+    /// location.
+    fn push_snapshot(&mut self, cursor_before: Path, new_root: &'arena Node) {
         // Remove future trees from the history vector so that the currently 'checked-out' tree is
         // the most recent tree in the history.
         while self.history_index < self.root_history.len() - 1 {
             // TODO: Deallocate the tree so that we don't get a 'memory leak'
             self.root_history.pop();
         }
-        // At this point, `node` contains a reference to the root of the new tree, so we just add
-        // this to the history, along with the cursor path.
 
         log::debug!("current_cursor_path {:?}", self.current_cursor_path);
+        let old_root = self.root();
         self.root_history.push(Snapshot::new(
-            old_cursor_path.clone(),
-            node,
+            cursor_before,
+            new_root,
             self.current_cursor_path.clone(),
         ));
         // Move the history index on by one so that we are pointing at the latest change
         self.history_index = self.root_history.len() - 1;
+        self.rebase_locations(old_root);
+    }
 
-        /* RETURN SUCCESS */
-        Ok(success)
+    /// Replaces the whole tree with the tree under `new_root` (e.g. when the tree is reloaded from
+    /// disk).  This is a single edit, so it can be undone.  The cursor is kept as close as possible
+    /// to where it was.
+    pub fn replace_tree(&mut self, new_root: &'arena Node) {
+        let cursor_before = self.current_cursor_path.clone();
+        self.current_cursor_path = cursor_before.rebase(self.root(), new_root);
+        self.push_snapshot(cursor_before, new_root);
     }
 
     /// Replaces the current cursor with a node represented by `c`
@@ -555,7 +1041,10 @@ impl<'arena, Node: Ast<'arena>> Dag<'arena, Node> {
                         ))
                     }
                     None => {
-                        match (prefix_count * node_count, cursor.is_valid_root(class)) {
+                        match (
+                            prefix_count.saturating_mul(node_count),
+                            cursor.is_valid_root(class),
+                        ) {
                             // This path is unreachable because its condition would trigger the
                             // short-circuiting call to delete
                             (0, _) => unreachable!(),
@@ -592,7 +1081,7 @@ impl<'arena, Node: Ast<'arena>> Dag<'arena, Node> {
                 Node::Class::from_char(c).ok_or(EditErr::CharNotANode(c))?,
             ),
         };
-        let count = prefix_count * node_count;
+        let count = prefix_count.saturating_mul(node_count);
         // If the count is 0, do nothing and exit
         if count == 0 {
             return Err(EditErr::NoNodesToInsert);
@@ -652,7 +1141,7 @@ impl<'arena, Node: Ast<'arena>> Dag<'arena, Node> {
         };
         // For inserting next to the cursor, we can simply multiply the two counts together to form
         // one single count
-        let count = prefix_count * second_count;
+        let count = prefix_count.saturating_mul(second_count);
         self.perform_edit(
             |this: &mut Self,
              parent_and_index: Option<(&'arena Node, usize)>,
@@ -745,6 +1234,463 @@ impl<'arena, Node: Ast<'arena>> Dag<'arena, Node> {
         )
     }
 
+    /* OPERATOR METHODS */
+
+    /// Returns the nodes that an operator would act on, as the [`Path`] to the first node and the
+    /// number of consecutive siblings starting at that node.
+    ///
+    /// If `motion` is `None`, these are the `count` nodes starting at the cursor.  Otherwise, the
+    /// motion is performed (without moving the cursor) and if it lands on a sibling of the cursor,
+    /// the result is every sibling between the cursor and that sibling.  If the motion lands
+    /// anywhere else (e.g. the parent), the result is just the node it landed on.
+    fn operand(
+        &mut self,
+        count: usize,
+        motion: Option<Direction>,
+    ) -> Result<(Path, usize), EditErr<Node::Class>> {
+        let direction = match motion {
+            Some(direction) => direction,
+            None => return Ok((self.current_cursor_path.clone(), count)),
+        };
+        let start = self.current_cursor_path.clone();
+        let result = self.move_cursor(count, direction);
+        let target = std::mem::replace(&mut self.current_cursor_path, start.clone());
+        result?;
+
+        let (mut start_parent, mut target_parent) = (start.clone(), target.clone());
+        match (start_parent.pop(), target_parent.pop()) {
+            (Some(start_index), Some(target_index)) if start_parent == target_parent => {
+                let first_index = start_index.min(target_index);
+                target_parent.push(first_index);
+                Ok((
+                    target_parent,
+                    start_index.max(target_index) - first_index + 1,
+                ))
+            }
+            _ => Ok((target, 1)),
+        }
+    }
+
+    /// Returns the nodes that [`delete_motion`](Self::delete_motion) would delete, without
+    /// changing the tree.  See [`operand`](Self::operand) for which nodes are returned.
+    pub fn yank(
+        &mut self,
+        count: usize,
+        motion: Option<Direction>,
+    ) -> Result<Vec<&'arena Node>, EditErr<Node::Class>> {
+        let (first, len) = self.operand(count, motion)?;
+        Ok(match first.cursor_and_parent(self.root()) {
+            (_, Some(parent)) => {
+                // Unwrap is safe, because only the root has no parent
+                let first_index = first.last().unwrap();
+                let children = parent.children();
+                children[first_index..(first_index + len).min(children.len())].to_vec()
+            }
+            (root, None) => vec![root],
+        })
+    }
+
+    /// Deletes the nodes that an operator would act on (see [`operand`](Self::operand))
+    pub fn delete_motion(
+        &mut self,
+        count: usize,
+        motion: Option<Direction>,
+    ) -> EditResult<Node::Class> {
+        let (first, len) = self.operand(count, motion)?;
+        let old_cursor_path = std::mem::replace(&mut self.current_cursor_path, first);
+        let result = self.delete_cursor(len);
+        if result.is_err() {
+            self.current_cursor_path = old_cursor_path;
+        }
+        result
+    }
+
+    /// Inserts `count` copies of a sequence of existing `nodes` after the cursor, leaving the
+    /// cursor on the last inserted node.  Every copy is [deep](Ast::deep_copy), so that no node
+    /// appears twice in the tree.
+    pub fn put(&mut self, count: usize, nodes: &[&'arena Node]) -> EditResult<Node::Class> {
+        if nodes.is_empty() {
+            return Err(EditErr::NoNodesToInsert);
+        }
+        self.perform_edit(
+            |this: &mut Self,
+             parent_and_index: Option<(&'arena Node, usize)>,
+             _cursor: &'arena Node| {
+                let (parent, cursor_index) = parent_and_index.ok_or(EditErr::AddSiblingToRoot)?;
+                let mut cloned_parent = parent.clone();
+                let num_nodes = count.saturating_mul(nodes.len());
+                for (i, node) in nodes.iter().cycle().take(num_nodes).enumerate() {
+                    let copy = node.deep_copy(this.arena);
+                    cloned_parent.insert_child(copy, this.arena, cursor_index + 1 + i)?;
+                }
+                // We can unwrap here, because putting nodes next to the root would cause an error
+                *this.current_cursor_path.last_mut().unwrap() = cursor_index + num_nodes;
+                Ok((
+                    cloned_parent,
+                    EditLocation::Parent,
+                    EditSuccess::Put { count: num_nodes },
+                ))
+            },
+        )
+    }
+
+    /* RESTRUCTURING METHODS */
+
+    /// Moves the node under the cursor up to `distance` places through its siblings (towards the
+    /// first sibling if `side` is [`Side::Prev`], or the last sibling if `side` is
+    /// [`Side::Next`]).  The siblings that the node moves past are shuffled along by one to fill
+    /// the gap, and the cursor stays on the moved node.
+    pub fn move_node(&mut self, distance: usize, side: Side) -> EditResult<Node::Class> {
+        self.perform_edit(
+            |this: &mut Self,
+             parent_and_index: Option<(&'arena Node, usize)>,
+             cursor: &'arena Node| {
+                let (parent, cursor_index) = parent_and_index.ok_or(EditErr::MovingRoot)?;
+                let max_index = parent.children().len() - 1;
+                // Calculate where the node will end up, capping the movement if it would run off
+                // either end of the child list
+                let new_index = match side {
+                    Side::Prev => cursor_index.saturating_sub(distance),
+                    Side::Next => (cursor_index + distance).min(max_index),
+                };
+                // If the node can't move at all, then we don't create a new snapshot
+                if new_index == cursor_index {
+                    return Err(EditErr::NoSiblingToMovePast(side));
+                }
+                // Moving a node through its siblings is equivalent to rotating the section of the
+                // child list between the node's old and new locations
+                let mut cloned_parent = parent.clone();
+                let children = cloned_parent.children_mut();
+                match side {
+                    Side::Prev => children[new_index..=cursor_index].rotate_right(1),
+                    Side::Next => children[cursor_index..=new_index].rotate_left(1),
+                }
+                // Keep the cursor on the moved node.  We can unwrap because the cursor isn't the
+                // root.
+                *this.current_cursor_path.last_mut().unwrap() = new_index;
+                Ok((
+                    cloned_parent,
+                    EditLocation::Parent,
+                    EditSuccess::MoveNode {
+                        name: cursor.display_name(),
+                        distance: cursor_index.max(new_index) - cursor_index.min(new_index),
+                        side,
+                    },
+                ))
+            },
+        )
+    }
+
+    /// Moves the node under the cursor into the sibling on a given `side` of it.  If `side` is
+    /// [`Side::Prev`], then the node becomes the last child of the previous sibling.  If `side` is
+    /// [`Side::Next`], then the node becomes the first child of the next sibling.  The cursor
+    /// stays on the moved node.
+    pub fn slurp_cursor(&mut self, side: Side) -> EditResult<Node::Class> {
+        self.perform_edit(
+            |this: &mut Self,
+             parent_and_index: Option<(&'arena Node, usize)>,
+             cursor: &'arena Node| {
+                let (parent, cursor_index) = parent_and_index.ok_or(EditErr::MovingRoot)?;
+                // Find the sibling that the cursor will be moved into
+                let sibling_index = match side {
+                    Side::Prev => cursor_index.checked_sub(1),
+                    Side::Next => Some(cursor_index + 1),
+                }
+                .filter(|i| *i < parent.children().len())
+                .ok_or(EditErr::NoSiblingToMoveInto(side))?;
+                let sibling = parent.children()[sibling_index];
+                // Insert the cursor into a clone of the sibling
+                let mut cloned_sibling = sibling.clone();
+                let insert_index = match side {
+                    Side::Prev => sibling.children().len(),
+                    Side::Next => 0,
+                };
+                cloned_sibling.insert_child(cursor, this.arena, insert_index)?;
+                let new_sibling = this.arena.alloc(cloned_sibling);
+                // Replace the sibling and remove the cursor from the parent.  We replace before
+                // deleting so that `sibling_index` is still correct.
+                let mut cloned_parent = parent.clone();
+                cloned_parent.replace_child(sibling_index, new_sibling);
+                cloned_parent.delete_child(cursor_index)?;
+                let new_sibling_index = match side {
+                    Side::Prev => sibling_index,
+                    Side::Next => sibling_index - 1,
+                };
+                // Move the cursor to the new location of the moved node.  The sibling may have
+                // wrapped the node in extra nodes (e.g. a field when moving into a JSON object),
+                // so we search for the node rather than assuming its location.
+                this.current_cursor_path.pop();
+                this.current_cursor_path.push(new_sibling_index);
+                // We can unwrap because we just inserted `cursor` into `new_sibling`
+                for i in Path::to_node(new_sibling, cursor).unwrap().iter() {
+                    this.current_cursor_path.push(*i);
+                }
+                Ok((
+                    cloned_parent,
+                    EditLocation::Parent,
+                    EditSuccess::Slurp {
+                        name: cursor.display_name(),
+                        side,
+                    },
+                ))
+            },
+        )
+    }
+
+    /// Moves the node under the cursor out of its parent, so that it becomes the sibling of its
+    /// old parent that is on a given `side` of that parent.  The cursor stays on the moved node.
+    pub fn barf_cursor(&mut self, side: Side) -> EditResult<Node::Class> {
+        self.perform_edit(
+            |this: &mut Self,
+             parent_and_index: Option<(&'arena Node, usize)>,
+             cursor: &'arena Node| {
+                let (parent, cursor_index) = parent_and_index.ok_or(EditErr::MovingRoot)?;
+                // Find the grandparent of the cursor, and the index of the parent within it
+                let mut grandparent_path = this.current_cursor_path.clone();
+                grandparent_path.pop();
+                let parent_index = grandparent_path.pop().ok_or(EditErr::MoveOutOfRoot)?;
+                let grandparent = grandparent_path.cursor(this.root());
+                // Remove the cursor from (a clone of) its parent
+                let mut cloned_parent = parent.clone();
+                cloned_parent.delete_child(cursor_index)?;
+                // Replace the parent in the grandparent, and then insert the cursor next to it
+                let mut cloned_grandparent = grandparent.clone();
+                cloned_grandparent.replace_child(parent_index, this.arena.alloc(cloned_parent));
+                let insert_index = match side {
+                    Side::Prev => parent_index,
+                    Side::Next => parent_index + 1,
+                };
+                cloned_grandparent.insert_child(cursor, this.arena, insert_index)?;
+                // Move the cursor to the new location of the moved node.  The node may have been
+                // wrapped in extra nodes (e.g. a field when moving into a JSON object), so we
+                // search for it rather than assuming its location.
+                let inserted_node = cloned_grandparent.children()[insert_index];
+                grandparent_path.push(insert_index);
+                // We can unwrap because we just inserted `cursor` at `insert_index`
+                for i in Path::to_node(inserted_node, cursor).unwrap().iter() {
+                    grandparent_path.push(*i);
+                }
+                this.current_cursor_path = grandparent_path;
+                Ok((
+                    cloned_grandparent,
+                    EditLocation::Grandparent,
+                    EditSuccess::Barf {
+                        name: cursor.display_name(),
+                        side,
+                    },
+                ))
+            },
+        )
+    }
+
+    /* SEARCH-AND-REPLACE METHODS */
+
+    /// Replace every node which matches a [`Pattern`] with the node described by `template` (see
+    /// [`Match::fill_template`](crate::ast::pattern::Match::fill_template)), as a single edit.  If
+    /// matches are nested, only the outermost match is replaced.  The cursor keeps its path, or is
+    /// moved up to the closest ancestor that still exists.
+    pub fn replace_matches(
+        &mut self,
+        pattern: &Pattern,
+        template: &str,
+        format_style: &Node::FormatStyle,
+    ) -> EditResult<Node::Class> {
+        // Build all the replacement nodes before changing anything, so that the edit either
+        // entirely succeeds or entirely fails
+        let mut replacements: Vec<(Path, &'arena Node)> = Vec::new();
+        for m in pattern.find_all(self.root()) {
+            // Matches are in pre-order, so any descendants of the last replaced node come directly
+            // after it
+            if replacements
+                .last()
+                .is_some_and(|(path, _)| m.path.starts_with(path))
+            {
+                continue;
+            }
+            let text = m
+                .fill_template(template, format_style)
+                .map_err(|e| EditErr::InvalidReplacement(e.to_string()))?;
+            let new_node =
+                Node::parse_fragment(&text, self.arena).map_err(EditErr::InvalidReplacement)?;
+            // The new node has to be valid where the old one was.  Nodes without a class (e.g. JSON
+            // fields) are only ever created by their parents, so they can only be swapped for each
+            // other.
+            let (_, parent) = m.path.cursor_and_parent(self.root());
+            let parent_and_index = parent.zip(m.path.last());
+            let is_valid = match (new_node.class(), m.node.class(), parent_and_index) {
+                (Some(class), Some(_), Some((parent, index))) => {
+                    parent.is_valid_child(index, class)
+                }
+                (Some(class), Some(_), None) => m.node.is_valid_root(class),
+                (None, None, _) => true,
+                _ => false,
+            };
+            if !is_valid {
+                return Err(EditErr::InvalidReplacement(format!(
+                    "can't replace {} with {}",
+                    m.node.display_name(),
+                    new_node.display_name()
+                )));
+            }
+            replacements.push((m.path, new_node));
+        }
+        if replacements.is_empty() {
+            return Err(EditErr::NoMatches(pattern.source().to_owned()));
+        }
+
+        let new_root = self.replace_descendants(self.root(), &mut Path::root(), &replacements);
+        // The cursor's node may have been replaced by a smaller subtree, in which case we move
+        // the cursor up until it refers to a real node
+        let cursor_before = self.current_cursor_path.clone();
+        while self.current_cursor_path.try_cursor(new_root).is_none() {
+            self.current_cursor_path.pop();
+        }
+        self.push_snapshot(cursor_before, new_root);
+        Ok(EditSuccess::ReplaceMatches {
+            count: replacements.len(),
+        })
+    }
+
+    /// Helper function for [`replace_matches`](Self::replace_matches), which returns a copy of
+    /// `node` (located at `path`) where every node in `replacements` has been swapped in.
+    /// `replacements` must be sorted by path, and only contain descendants of `node`.  Any
+    /// subtrees without replacements are shared with the old tree.
+    fn replace_descendants(
+        &self,
+        node: &'arena Node,
+        path: &mut Path,
+        replacements: &[(Path, &'arena Node)],
+    ) -> &'arena Node {
+        match replacements.first() {
+            None => return node,
+            Some((replacement_path, new_node)) if replacement_path == path => return new_node,
+            _ => {}
+        }
+        let mut cloned_node = node.clone();
+        let mut remaining = replacements;
+        for (i, child) in node.children().iter().enumerate() {
+            path.push(i);
+            // The replacements inside this child's subtree are contiguous, because `replacements`
+            // is sorted in pre-order
+            let num_in_child = remaining
+                .iter()
+                .take_while(|(p, _)| p.starts_with(path))
+                .count();
+            if num_in_child > 0 {
+                let new_child = self.replace_descendants(child, path, &remaining[..num_in_child]);
+                cloned_node.replace_child(i, new_child);
+                remaining = &remaining[num_in_child..];
+            }
+            path.pop();
+        }
+        self.arena.alloc(cloned_node)
+    }
+
+    /* FOLDING METHODS */
+
+    /// Returns the nodes in the current tree which are folded
+    pub fn folds(&self) -> Folds<Node> {
+        Folds::new(self.root(), &self.folded_paths)
+    }
+
+    /// Opens or closes some folds, depending on the [`Fold`].  Only [`Fold::ToDepth`] uses the
+    /// `count`, as the depth to fold at.  The cursor is never left hidden inside a fold, so
+    /// folding one of its ancestors moves the cursor to that ancestor.
+    pub fn fold(&mut self, count: usize, fold: Fold) -> EditResult<Node::Class> {
+        let is_folded = self.folded_paths.contains(&self.current_cursor_path);
+        match fold {
+            Fold::Toggle if is_folded => self.fold(count, Fold::Open),
+            Fold::Toggle => self.fold(count, Fold::Close),
+            Fold::ToggleRecursive if is_folded => self.fold(count, Fold::OpenRecursive),
+            Fold::ToggleRecursive => self.fold(count, Fold::CloseRecursive),
+            Fold::Close => {
+                // If the cursor is already folded, then its parent gets folded instead
+                let path = self.foldable_cursor(true)?;
+                self.folded_paths.insert(path.clone());
+                self.current_cursor_path = path;
+                Ok(EditSuccess::Fold(1))
+            }
+            Fold::Open => {
+                if self.folded_paths.remove(&self.current_cursor_path) {
+                    Ok(EditSuccess::Unfold(1))
+                } else {
+                    Err(EditErr::NoFoldAtCursor)
+                }
+            }
+            Fold::CloseRecursive => {
+                let path = self.foldable_cursor(false)?;
+                let num_folded = add_containers(
+                    path.cursor(self.root()),
+                    &mut path.clone(),
+                    None,
+                    &mut self.folded_paths,
+                );
+                self.current_cursor_path = path;
+                Ok(EditSuccess::Fold(num_folded))
+            }
+            Fold::OpenRecursive => {
+                let num_folds = self.folded_paths.len();
+                let cursor_path = &self.current_cursor_path;
+                self.folded_paths
+                    .retain(|path| !path.starts_with(cursor_path));
+                match num_folds - self.folded_paths.len() {
+                    0 => Err(EditErr::NoFoldAtCursor),
+                    num_unfolded => Ok(EditSuccess::Unfold(num_unfolded)),
+                }
+            }
+            Fold::ToDepth => {
+                self.folded_paths.clear();
+                let num_folded = add_containers(
+                    self.root(),
+                    &mut Path::root(),
+                    Some(count),
+                    &mut self.folded_paths,
+                );
+                if num_folded == 0 {
+                    return Err(EditErr::NothingToFold);
+                }
+                // Move the cursor out of the fold that now contains it
+                let mut path = Path::root();
+                for &index in self.current_cursor_path.iter() {
+                    if self.folded_paths.contains(&path) {
+                        break;
+                    }
+                    path.push(index);
+                }
+                self.current_cursor_path = path;
+                Ok(EditSuccess::Fold(num_folded))
+            }
+            Fold::OpenAll => {
+                let num_unfolded = self.folded_paths.len();
+                self.folded_paths.clear();
+                Ok(EditSuccess::Unfold(num_unfolded))
+            }
+        }
+    }
+
+    /// Opens any folds which contain the cursor, so that the cursor is visible.  This should be
+    /// called after the cursor moves, since moving into a folded node should show its contents.
+    pub fn open_folds_around_cursor(&mut self) {
+        let mut path = Path::root();
+        for &index in self.current_cursor_path.iter() {
+            self.folded_paths.remove(&path);
+            path.push(index);
+        }
+    }
+
+    /// Returns the [`Path`] of the closest node to the cursor which can be folded.  This is
+    /// either the cursor or its closest ancestor which has children (and isn't already folded, if
+    /// `skip_folded` is `true`).
+    fn foldable_cursor(&self, skip_folded: bool) -> Result<Path, EditErr<Node::Class>> {
+        let mut path = self.current_cursor_path.clone();
+        while path.cursor(self.root()).children().is_empty()
+            || (skip_folded && self.folded_paths.contains(&path))
+        {
+            path.pop().ok_or(EditErr::NothingToFold)?;
+        }
+        Ok(path)
+    }
+
     /* DISPLAY METHODS */
 
     /// Build the text representation of the current tree into the given [`String`]
@@ -816,16 +1762,46 @@ impl<'arena, Node: Ast<'arena>> Dag<'arena, Node> {
     }
 }
 
+/// Adds the [`Path`]s of nodes with children under `node` (which is at `path`) to `paths`, and
+/// returns how many of those nodes weren't already in `paths`.  If `depth` is `None`, then `node`
+/// and all its descendants are added, otherwise only the nodes exactly `depth` levels below
+/// `node` are added.
+fn add_containers<'arena, Node: Ast<'arena>>(
+    node: &'arena Node,
+    path: &mut Path,
+    depth: Option<usize>,
+    paths: &mut HashSet<Path>,
+) -> usize {
+    if node.children().is_empty() {
+        return 0;
+    }
+    let mut num_added = 0;
+    if matches!(depth, None | Some(0)) && paths.insert(path.clone()) {
+        num_added += 1;
+    }
+    if depth == Some(0) {
+        return num_added;
+    }
+    for (index, child) in node.children().iter().enumerate() {
+        path.push(index);
+        num_added += add_containers(*child, path, depth.map(|d| d - 1), paths);
+        path.pop();
+    }
+    num_added
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{Dag, EditErr, EditResult, EditSuccess, Insertable};
+    use super::{Dag, EditErr, EditResult, EditSuccess, Insertable, MAX_JUMP_LIST_LEN};
     use crate::arena::Arena;
+    use crate::ast;
     use crate::ast::json::{add_value_to_arena, Class, Json, JsonFormat};
-    use crate::ast::Ast;
-    use crate::core::{Direction, Path, Side};
-    use crate::editor::normal_mode::Action;
+    use crate::ast::{pattern::Pattern, Ast};
+    use crate::core::{Direction, Fold, Path, Side};
+    use crate::editor::normal_mode::{Action, Operator};
 
     use serde_json::{json, Value};
+    use std::collections::HashSet;
 
     /// Extension trait used to add the `execute_action` and `execute_action_once` methods to Dag
     /// (`execute_action` was removed but is incredibly helpful for unit testing).
@@ -848,7 +1824,30 @@ mod tests {
                 Action::InsertBefore(c) => self.insert_next_to_cursor(count, c, Side::Prev),
                 Action::InsertAfter(c) => self.insert_next_to_cursor(count, c, Side::Next),
                 Action::Delete => self.delete_cursor(count),
-                Action::Quit | Action::Write => unreachable!(),
+                Action::MoveNode(side) => self.move_node(count, side),
+                Action::Slurp(side) => self.slurp_cursor(side),
+                Action::Barf(side) => self.barf_cursor(side),
+                Action::SetMark(name) => self.set_mark(name),
+                Action::MoveToMark(name) => self.move_to_mark(name),
+                Action::Jump(side) => self.jump(count, side),
+                Action::Operate(Operator::Delete, motion) => self.delete_motion(count, motion),
+                Action::Fold(fold) => self.fold(count, fold),
+                Action::Quit
+                | Action::ForceQuit
+                | Action::WriteAndQuit
+                | Action::Operate(Operator::Yank, _)
+                | Action::Put
+                | Action::Write
+                | Action::Help
+                | Action::Search
+                | Action::CommandLine
+                | Action::NextMatch(_)
+                | Action::SearchReplace
+                | Action::RecordMacro(_)
+                | Action::StopRecording
+                | Action::PlayMacro(_)
+                | Action::Scroll(_)
+                | Action::Repeat(_) => unreachable!(),
             }
         }
     }
@@ -1383,6 +2382,45 @@ mod tests {
         );
     }
 
+    #[test]
+    fn move_through_tree() {
+        let tree = json!([true, [false, [null, true]], {"a": true}, false]);
+        for (start, distance, direction, actual_distance, expected) in [
+            // Siblings, counted from either end
+            (vec![1, 1], 1, Direction::FirstSibling, 1, vec![1, 0]),
+            (vec![1], 1, Direction::LastSibling, 2, vec![3]),
+            (vec![0], 2, Direction::LastSibling, 2, vec![2]),
+            (vec![3], 9, Direction::FirstSibling, 0, vec![3]),
+            // The root is always the same distance away, whatever the count
+            (vec![1, 1, 0], 4, Direction::Root, 3, vec![]),
+            // Children are chosen by index, starting from 1
+            (vec![1], 2, Direction::NthChild, 2, vec![1, 1]),
+            (vec![], 7, Direction::NthChild, 4, vec![3]),
+            (vec![0], 1, Direction::NthChild, 0, vec![0]),
+            // Pre-order traversal, stopping at the ends
+            (vec![1, 1], 2, Direction::PreorderNext, 2, vec![1, 1, 1]),
+            (vec![1, 0], 3, Direction::PreorderPrev, 3, vec![]),
+            (vec![3], 2, Direction::PreorderNext, 0, vec![3]),
+            // Leaves
+            (vec![1], 2, Direction::NextLeaf, 2, vec![1, 1, 0]),
+            (vec![2], 1, Direction::PrevLeaf, 1, vec![1, 1, 1]),
+            (vec![2, 0, 1], 5, Direction::NextLeaf, 1, vec![3]),
+            // Nodes of the same class as the cursor
+            (vec![0], 2, Direction::NextOfClass, 2, vec![2, 0, 1]),
+            (vec![3], 1, Direction::PrevOfClass, 1, vec![1, 0]),
+            (vec![1], 1, Direction::NextOfClass, 1, vec![1, 1]),
+        ] {
+            test_capped_movement(
+                tree.clone(),
+                Path::from_vec(start),
+                distance,
+                direction,
+                actual_distance,
+                Path::from_vec(expected),
+            );
+        }
+    }
+
     /* UNDO/REDO */
 
     #[test]
@@ -1686,10 +2724,9 @@ mod tests {
             dag.move_cursor(1, Direction::Up),
             Ok(EditSuccess::Move(1, Direction::Up))
         );
-        // Step 5: Insert a string after the cursor: `[{"": false, <"": "">}, true]`.  This can't be
-        // generated by the json! macro (because the two values have the same key), so we use
-        // string comparison instead
-        let tree_5_str = r#"[{"": false, "": ""}, true]"#;
+        // Step 5: Insert a string after the cursor: `[{"": false, <"1": "">}, true]`.  The new
+        // field can't have an empty key, because the first field already uses it
+        let tree_5_str = r#"[{"": false, "1": ""}, true]"#;
         assert_eq!(
             dag.insert_next_to_cursor(1, Insertable::CountedNode(1, 's'), Side::Next),
             Ok(EditSuccess::InsertNextToCursor {
@@ -1919,6 +2956,188 @@ mod tests {
         );
     }
 
+    /* RESTRUCTURING */
+
+    #[test]
+    fn move_node() {
+        // Moving the root is not allowed
+        run_test_err(
+            json!([true]),
+            Path::root(),
+            Action::MoveNode(Side::Next),
+            EditErr::MovingRoot,
+        );
+        // Moving the first child backwards isn't possible
+        run_test_err(
+            json!([true, false]),
+            Path::from_vec(vec![0]),
+            Action::MoveNode(Side::Prev),
+            EditErr::NoSiblingToMovePast(Side::Prev),
+        );
+        // Swap with the next sibling
+        run_test_ok(
+            json!([true, false, null]),
+            Path::from_vec(vec![0]),
+            Action::MoveNode(Side::Next),
+            EditSuccess::MoveNode {
+                name: "true".to_string(),
+                distance: 1,
+                side: Side::Next,
+            },
+            json!([false, true, null]),
+            Path::from_vec(vec![1]),
+        );
+        // Move several places back, shuffling the other nodes along
+        run_test_ok_count(
+            json!([true, false, null, []]),
+            Path::from_vec(vec![3]),
+            2,
+            Action::MoveNode(Side::Prev),
+            EditSuccess::MoveNode {
+                name: "array".to_string(),
+                distance: 2,
+                side: Side::Prev,
+            },
+            json!([true, [], false, null]),
+            Path::from_vec(vec![1]),
+        );
+        // Overshooting the end caps the movement
+        run_test_ok_count(
+            json!({"a": true, "b": false, "c": null}),
+            Path::from_vec(vec![0]),
+            10,
+            Action::MoveNode(Side::Next),
+            EditSuccess::MoveNode {
+                name: "field".to_string(),
+                distance: 2,
+                side: Side::Next,
+            },
+            json!({"b": false, "c": null, "a": true}),
+            Path::from_vec(vec![2]),
+        );
+    }
+
+    #[test]
+    fn slurp() {
+        // There's no sibling before the first child
+        run_test_err(
+            json!([true, []]),
+            Path::from_vec(vec![0]),
+            Action::Slurp(Side::Prev),
+            EditErr::NoSiblingToMoveInto(Side::Prev),
+        );
+        // Move into the start of the next array
+        run_test_ok(
+            json!([true, [null, false]]),
+            Path::from_vec(vec![0]),
+            Action::Slurp(Side::Next),
+            EditSuccess::Slurp {
+                name: "true".to_string(),
+                side: Side::Next,
+            },
+            json!([[true, null, false]]),
+            Path::from_vec(vec![0, 0]),
+        );
+        // Move into the end of the previous array
+        run_test_ok(
+            json!([[null, false], true, []]),
+            Path::from_vec(vec![1]),
+            Action::Slurp(Side::Prev),
+            EditSuccess::Slurp {
+                name: "true".to_string(),
+                side: Side::Prev,
+            },
+            json!([[null, false, true], []]),
+            Path::from_vec(vec![0, 2]),
+        );
+        // Moving a value into an object wraps it in a field, but the cursor stays on the value
+        run_test_ok(
+            json!([true, {"a": null}]),
+            Path::from_vec(vec![0]),
+            Action::Slurp(Side::Next),
+            EditSuccess::Slurp {
+                name: "true".to_string(),
+                side: Side::Next,
+            },
+            json!([{"": true, "a": null}]),
+            Path::from_vec(vec![0, 0, 1]),
+        );
+        // Each value moved into an object gets a key which isn't used by the other fields
+        run_test_ok(
+            json!([{"": true, "1": false}, null]),
+            Path::from_vec(vec![1]),
+            Action::Slurp(Side::Prev),
+            EditSuccess::Slurp {
+                name: "null".to_string(),
+                side: Side::Prev,
+            },
+            json!([{"": true, "1": false, "2": null}]),
+            Path::from_vec(vec![0, 2, 1]),
+        );
+        // The siblings of a field are always other fields, which can't take any more children
+        run_test_err(
+            json!({"a": true, "b": []}),
+            Path::from_vec(vec![0]),
+            Action::Slurp(Side::Next),
+            EditErr::InsertError(ast::InsertError::TooManyChildren {
+                name: "field".to_string(),
+                max_children: 2,
+            }),
+        );
+    }
+
+    #[test]
+    fn barf() {
+        // There's nothing to move out of if the cursor is the root
+        run_test_err(
+            json!([true]),
+            Path::root(),
+            Action::Barf(Side::Next),
+            EditErr::MovingRoot,
+        );
+        // Children of the root can't be moved out of the root
+        run_test_err(
+            json!([true]),
+            Path::from_vec(vec![0]),
+            Action::Barf(Side::Next),
+            EditErr::MoveOutOfRoot,
+        );
+        // Move out to after the parent
+        run_test_ok(
+            json!([[null, true], false]),
+            Path::from_vec(vec![0, 1]),
+            Action::Barf(Side::Next),
+            EditSuccess::Barf {
+                name: "true".to_string(),
+                side: Side::Next,
+            },
+            json!([[null], true, false]),
+            Path::from_vec(vec![1]),
+        );
+        // Move out to before the parent, leaving it empty
+        run_test_ok(
+            json!([false, [true]]),
+            Path::from_vec(vec![1, 0]),
+            Action::Barf(Side::Prev),
+            EditSuccess::Barf {
+                name: "true".to_string(),
+                side: Side::Prev,
+            },
+            json!([false, true, []]),
+            Path::from_vec(vec![1]),
+        );
+        // Fields can't be moved out of an object and into an array
+        run_test_err(
+            json!([{"a": true}]),
+            Path::from_vec(vec![0, 0]),
+            Action::Barf(Side::Next),
+            EditErr::InsertError(ast::InsertError::InvalidChild {
+                name: "array".to_string(),
+                child_name: "field".to_string(),
+            }),
+        );
+    }
+
     #[test]
     // This is the test cases for issue 27
     fn level_2_undo() {
@@ -2000,6 +3219,417 @@ mod tests {
         assert_eq!(ok_5, Ok(EditSuccess::Move(1, Direction::Down)));
     }
 
+    #[test]
+    fn move_to_match() {
+        let arena: Arena<Json> = Arena::new();
+        let root = add_value_to_arena(json!([true, [false, true], {"a": true}]), &arena);
+        let mut dag = Dag::new(&arena, root, Path::from_vec(vec![1]));
+        let pattern = Pattern::parse("true").unwrap();
+
+        // Search forwards from the cursor
+        for (count, side, exp_path, exp_index) in &[
+            (1, Side::Next, vec![1, 1], 1),
+            // Wrap around the end of the tree
+            (2, Side::Next, vec![0], 0),
+            (1, Side::Prev, vec![2, 0, 1], 2),
+            // Counts larger than the number of matches wrap multiple times
+            (7, Side::Prev, vec![1, 1], 1),
+        ] {
+            let exp_path = Path::from_vec(exp_path.clone());
+            assert_eq!(
+                dag.move_to_match(*count, &pattern, *side),
+                Ok(EditSuccess::MoveToMatch {
+                    path: exp_path.clone(),
+                    index: *exp_index,
+                    total: 3
+                })
+            );
+            assert_eq!(dag.current_cursor_path, exp_path);
+        }
+
+        // Searching for a pattern with no matches doesn't move the cursor
+        assert_eq!(
+            dag.move_to_match(1, &Pattern::parse("null").unwrap(), Side::Next),
+            Err(EditErr::NoMatches("null".to_owned()))
+        );
+        assert_eq!(dag.current_cursor_path, Path::from_vec(vec![1, 1]));
+    }
+
+    #[test]
+    fn operators() {
+        let arena: Arena<Json> = Arena::new();
+        let tree = json!([true, [false, null, true, false], {"a": null}]);
+        let root = add_value_to_arena(tree.clone(), &arena);
+        let mut dag = Dag::new(&arena, root, Path::from_vec(vec![1, 1]));
+        let names = |nodes: Vec<&Json>| nodes.iter().map(|n| n.display_name()).collect::<Vec<_>>();
+
+        // Yanking doesn't change the tree or move the cursor
+        for (count, motion, expected) in [
+            (2, None, vec!["null", "true"]),
+            (
+                1,
+                Some(Direction::LastSibling),
+                vec!["null", "true", "false"],
+            ),
+            (1, Some(Direction::Prev), vec!["false", "null"]),
+            (1, Some(Direction::Up), vec!["array"]),
+            (1, Some(Direction::Root), vec!["array"]),
+            (2, Some(Direction::NextLeaf), vec!["null", "true", "false"]),
+            (4, Some(Direction::NextLeaf), vec!["null"]),
+        ] {
+            assert_eq!(names(dag.yank(count, motion).unwrap()), expected);
+            assert_eq!(*dag.root(), tree);
+            assert_eq!(dag.current_cursor_path, Path::from_vec(vec![1, 1]));
+        }
+
+        // Deleting to the first sibling, then putting the deleted nodes back after the new cursor
+        let deleted = dag.yank(1, Some(Direction::FirstSibling)).unwrap();
+        dag.delete_motion(1, Some(Direction::FirstSibling)).unwrap();
+        assert_eq!(*dag.root(), json!([true, [true, false], {"a": null}]));
+        assert_eq!(dag.current_cursor_path, Path::from_vec(vec![1, 0]));
+        assert_eq!(dag.put(2, &deleted), Ok(EditSuccess::Put { count: 4 }));
+        assert_eq!(
+            *dag.root(),
+            json!([true, [true, false, null, false, null, false], {"a": null}])
+        );
+        assert_eq!(dag.current_cursor_path, Path::from_vec(vec![1, 4]));
+        assert_nodes_unique(dag.root());
+
+        // The root can be yanked, but can't be deleted or have nodes put next to it
+        dag.current_cursor_path = Path::root();
+        assert_eq!(
+            dag.delete_motion(1, Some(Direction::Up)),
+            Err(EditErr::DeletingRoot)
+        );
+        assert_eq!(dag.put(1, &deleted), Err(EditErr::AddSiblingToRoot));
+        assert_eq!(dag.put(1, &[]), Err(EditErr::NoNodesToInsert));
+    }
+
+    /// Asserts that no node appears more than once in the tree under `root`
+    fn assert_nodes_unique(root: &Json) {
+        fn add_nodes<'a>(node: &'a Json<'a>, seen: &mut HashSet<*const Json<'a>>) {
+            assert!(seen.insert(node), "{} appears twice", node.display_name());
+            for child in node.children() {
+                add_nodes(child, seen);
+            }
+        }
+        add_nodes(root, &mut HashSet::new());
+    }
+
+    #[test]
+    fn put_copies_nodes() {
+        let arena: Arena<Json> = Arena::new();
+        let root = add_value_to_arena(json!([true, [false, {"a": null}]]), &arena);
+        let mut dag = Dag::new(&arena, root, Path::from_vec(vec![1]));
+        // Putting a node next to itself, then putting it twice more
+        let yanked = dag.yank(1, None).unwrap();
+        dag.put(1, &yanked).unwrap();
+        assert_nodes_unique(dag.root());
+        dag.put(2, &yanked).unwrap();
+        assert_eq!(
+            *dag.root(),
+            json!([true, [false, {"a": null}], [false, {"a": null}], [false, {"a": null}], [false, {"a": null}]])
+        );
+        assert_nodes_unique(dag.root());
+    }
+
+    #[test]
+    fn marks() {
+        let arena: Arena<Json> = Arena::new();
+        let root = add_value_to_arena(json!([true, [false, null], {"a": true}]), &arena);
+        let mut dag = Dag::new(&arena, root, Path::from_vec(vec![1, 1]));
+        assert_eq!(dag.move_to_mark('a'), Err(EditErr::NoSuchMark('a')));
+        dag.set_mark('a').unwrap();
+        dag.current_cursor_path = Path::from_vec(vec![1]);
+        dag.set_mark('b').unwrap();
+
+        // Inserting a sibling before the marked nodes moves the marks with them
+        dag.current_cursor_path = Path::from_vec(vec![0]);
+        dag.insert_next_to_cursor(1, Insertable::CountedNode(1, 'n'), Side::Prev)
+            .unwrap();
+        assert_eq!(dag.marks[&'a'], Path::from_vec(vec![2, 1]));
+        assert_eq!(dag.marks[&'b'], Path::from_vec(vec![2]));
+        // Editing inside the marked array clones it, but the mark still follows it
+        dag.current_cursor_path = Path::from_vec(vec![2, 0]);
+        dag.delete_cursor(1).unwrap();
+        assert_eq!(dag.marks[&'a'], Path::from_vec(vec![2, 0]));
+        assert_eq!(dag.marks[&'b'], Path::from_vec(vec![2]));
+        // Undoing moves the marks back to where the nodes were
+        dag.undo(2).unwrap();
+        assert_eq!(dag.marks[&'a'], Path::from_vec(vec![1, 1]));
+        assert_eq!(dag.marks[&'b'], Path::from_vec(vec![1]));
+
+        // Moving to a mark or a path records the jump
+        assert_eq!(
+            dag.move_to_path(Path::from_vec(vec![0, 1])),
+            Err(EditErr::NoSuchNode(Path::from_vec(vec![0, 1])))
+        );
+        dag.move_to_path(Path::from_vec(vec![2, 0])).unwrap();
+        assert_eq!(
+            dag.move_to_mark('a'),
+            Ok(EditSuccess::MoveToMark {
+                name: 'a',
+                path: Path::from_vec(vec![1, 1])
+            })
+        );
+        dag.move_to_mark('b').unwrap();
+        // Moving back and forward through the jump list
+        for (count, side, exp_path) in &[
+            (1, Side::Prev, vec![1, 1]),
+            (1, Side::Prev, vec![2, 0]),
+            (2, Side::Next, vec![1]),
+        ] {
+            let path = Path::from_vec(exp_path.clone());
+            assert_eq!(
+                dag.jump(*count, *side),
+                Ok(EditSuccess::Jump {
+                    side: *side,
+                    path: path.clone()
+                })
+            );
+            assert_eq!(dag.current_cursor_path, path);
+        }
+        assert_eq!(
+            dag.jump(1, Side::Next),
+            Err(EditErr::NoJumpLocation(Side::Next))
+        );
+
+        // Only the newest locations are kept in the jump list
+        for _ in 0..MAX_JUMP_LIST_LEN {
+            dag.move_to_path(Path::from_vec(vec![0])).unwrap();
+            dag.move_to_path(Path::from_vec(vec![1])).unwrap();
+        }
+        assert_eq!(dag.jump_list.len(), MAX_JUMP_LIST_LEN);
+        // ...including when jumping back from the end of the list remembers the cursor
+        assert_eq!(
+            dag.jump(1, Side::Prev),
+            Ok(EditSuccess::Jump {
+                side: Side::Prev,
+                path: Path::from_vec(vec![0])
+            })
+        );
+        assert_eq!(dag.jump_list.len(), MAX_JUMP_LIST_LEN);
+        assert_eq!(
+            dag.jump(MAX_JUMP_LIST_LEN, Side::Prev),
+            Ok(EditSuccess::Jump {
+                side: Side::Prev,
+                path: Path::from_vec(vec![0])
+            })
+        );
+        assert_eq!(
+            dag.jump(MAX_JUMP_LIST_LEN, Side::Next),
+            Ok(EditSuccess::Jump {
+                side: Side::Next,
+                path: Path::from_vec(vec![1])
+            })
+        );
+    }
+
+    #[test]
+    fn folds() {
+        let arena: Arena<Json> = Arena::new();
+        let root = add_value_to_arena(json!([true, [false, [null]], {"a": [true]}]), &arena);
+        let mut dag = Dag::new(&arena, root, Path::from_vec(vec![1, 0]));
+        let folded_paths = |dag: &Dag<Json>| {
+            let mut paths: Vec<Path> = dag.folded_paths.iter().cloned().collect();
+            paths.sort();
+            paths
+        };
+        let paths = |paths: &[&[usize]]| {
+            paths
+                .iter()
+                .map(|p| Path::from_vec(p.to_vec()))
+                .collect::<Vec<_>>()
+        };
+
+        // Folding a leaf folds its parent instead
+        assert_eq!(dag.fold(1, Fold::Close), Ok(EditSuccess::Fold(1)));
+        assert_eq!(dag.current_cursor_path, Path::from_vec(vec![1]));
+        assert_eq!(dag.fold(1, Fold::Toggle), Ok(EditSuccess::Unfold(1)));
+        assert_eq!(dag.fold(1, Fold::Open), Err(EditErr::NoFoldAtCursor));
+        // Recursive folds include every descendant with children
+        assert_eq!(dag.fold(1, Fold::CloseRecursive), Ok(EditSuccess::Fold(2)));
+        assert_eq!(folded_paths(&dag), paths(&[&[1], &[1, 1]]));
+        assert_eq!(
+            dag.fold(1, Fold::ToggleRecursive),
+            Ok(EditSuccess::Unfold(2))
+        );
+
+        // Folds follow their nodes through edits, and are removed with them
+        dag.fold(1, Fold::ToDepth).unwrap();
+        assert_eq!(folded_paths(&dag), paths(&[&[1], &[2]]));
+        dag.current_cursor_path = Path::from_vec(vec![0]);
+        dag.insert_next_to_cursor(1, Insertable::CountedNode(1, 'n'), Side::Prev)
+            .unwrap();
+        assert_eq!(folded_paths(&dag), paths(&[&[2], &[3]]));
+        dag.current_cursor_path = Path::from_vec(vec![2]);
+        dag.delete_cursor(1).unwrap();
+        assert_eq!(folded_paths(&dag), paths(&[&[2]]));
+        dag.undo(1).unwrap();
+        assert_eq!(folded_paths(&dag), paths(&[&[3]]));
+
+        // Folding to a depth moves the cursor out of the new folds
+        dag.current_cursor_path = Path::from_vec(vec![3, 0, 0]);
+        assert_eq!(dag.fold(2, Fold::ToDepth), Ok(EditSuccess::Fold(2)));
+        assert_eq!(folded_paths(&dag), paths(&[&[2, 1], &[3, 0]]));
+        assert_eq!(dag.current_cursor_path, Path::from_vec(vec![3, 0]));
+        // Moving the cursor into a fold opens it
+        dag.current_cursor_path = Path::from_vec(vec![3, 0, 0]);
+        dag.open_folds_around_cursor();
+        assert_eq!(folded_paths(&dag), paths(&[&[2, 1]]));
+        assert_eq!(dag.fold(1, Fold::OpenAll), Ok(EditSuccess::Unfold(1)));
+        assert_eq!(dag.fold(5, Fold::ToDepth), Err(EditErr::NothingToFold));
+    }
+
+    #[test]
+    fn replace_matches() {
+        for (start_tree, cursor, pattern, template, expected) in [
+            // Rename a key, keeping its value
+            (
+                json!([{"old": [true]}, {"old": null, "x": false}]),
+                vec![1, 1, 1],
+                ".old$f",
+                r#""new": $f.1"#,
+                Ok((
+                    2,
+                    json!([{"new": [true]}, {"new": null, "x": false}]),
+                    vec![1, 1, 1],
+                )),
+            ),
+            // Only the outermost matches are replaced, and the cursor moves out of replaced nodes
+            (
+                json!([[[true]], false]),
+                vec![0, 0, 0],
+                "array$a",
+                "[$a]",
+                Ok((1, json!([[[[true]], false]]), vec![0, 0, 0])),
+            ),
+            (
+                json!([[true, false], null]),
+                vec![0, 1],
+                "array > array",
+                "null",
+                Ok((1, json!([null, null]), vec![0])),
+            ),
+            // Failed replacements don't change the tree
+            (
+                json!([true]),
+                vec![0],
+                "false",
+                "true",
+                Err(EditErr::NoMatches("false".to_owned())),
+            ),
+            (
+                json!({"a": true}),
+                vec![0],
+                "field",
+                "true",
+                Err(EditErr::InvalidReplacement(
+                    "can't replace field with true".to_owned(),
+                )),
+            ),
+            (
+                json!([{"a": true}]),
+                vec![0],
+                "string",
+                "null",
+                Err(EditErr::InvalidReplacement(
+                    r#"can't replace "a" with null"#.to_owned(),
+                )),
+            ),
+            (
+                json!([true]),
+                vec![0],
+                "true",
+                r#""a": true"#,
+                Err(EditErr::InvalidReplacement(
+                    "can't replace true with field".to_owned(),
+                )),
+            ),
+            (
+                json!([true]),
+                vec![0],
+                "true",
+                "[1]",
+                Err(EditErr::InvalidReplacement(
+                    "Sapling can't edit JSON numbers yet".to_owned(),
+                )),
+            ),
+        ] {
+            println!("Testing {:?} => {:?}", pattern, template);
+            let arena: Arena<Json> = Arena::new();
+            let root = add_value_to_arena(start_tree.clone(), &arena);
+            let mut dag = Dag::new(&arena, root, Path::from_vec(cursor.clone()));
+            let pattern = Pattern::parse(pattern).unwrap();
+            let result = dag.replace_matches(&pattern, template, &JsonFormat::Compact);
+            match expected {
+                Ok((count, expected_tree, expected_cursor)) => {
+                    assert_eq!(result, Ok(EditSuccess::ReplaceMatches { count }));
+                    assert_eq!(*dag.root(), expected_tree);
+                    assert_eq!(dag.current_cursor_path, Path::from_vec(expected_cursor));
+                    // The whole replacement should be undone in one step
+                    dag.undo(1).unwrap();
+                    assert_eq!(*dag.root(), start_tree);
+                    assert_eq!(dag.current_cursor_path, Path::from_vec(cursor));
+                }
+                Err(err) => {
+                    assert_eq!(result, Err(err));
+                    assert_eq!(*dag.root(), start_tree);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn dirty() {
+        let arena: Arena<Json> = Arena::new();
+        let root = add_value_to_arena(json!([true, false]), &arena);
+        let mut dag = Dag::new(&arena, root, Path::from_vec(vec![0]));
+        // A new `Dag` is clean until it's edited
+        assert!(!dag.is_dirty());
+        dag.delete_cursor(1).unwrap();
+        assert!(dag.is_dirty());
+        // Undoing back to the saved tree makes it clean again
+        dag.undo(1).unwrap();
+        assert!(!dag.is_dirty());
+        dag.redo(1).unwrap();
+        dag.mark_saved();
+        assert!(!dag.is_dirty());
+        dag.undo(1).unwrap();
+        assert!(dag.is_dirty());
+    }
+
+    #[test]
+    fn group_edits() {
+        let arena: Arena<Json> = Arena::new();
+        let root = add_value_to_arena(json!([true]), &arena);
+        let mut dag = Dag::new(&arena, root, Path::from_vec(vec![0]));
+
+        dag.insert_next_to_cursor(1, Insertable::CountedNode(1, 'f'), Side::Next)
+            .unwrap();
+        let start = dag.history_index();
+        // Grouping a single edit does nothing
+        dag.insert_next_to_cursor(1, Insertable::CountedNode(1, 'n'), Side::Next)
+            .unwrap();
+        dag.group_edits_since(start);
+        assert_eq!(dag.history_index(), 2);
+        // Group two more edits with the last one
+        dag.delete_cursor(1).unwrap();
+        dag.replace_cursor(1, Insertable::CountedNode(1, 'a'))
+            .unwrap();
+        dag.group_edits_since(start);
+        assert_eq!(dag.history_index(), 2);
+        assert_eq!(*dag.root(), json!([true, []]));
+        assert_eq!(dag.current_cursor_path, Path::from_vec(vec![1]));
+        // Undoing should undo the whole group, returning the cursor to where it started
+        dag.undo(1).unwrap();
+        assert_eq!(*dag.root(), json!([true, false]));
+        assert_eq!(dag.current_cursor_path, Path::from_vec(vec![1]));
+        dag.redo(1).unwrap();
+        assert_eq!(*dag.root(), json!([true, []]));
+        assert_eq!(dag.redo(1), Err(EditErr::NoChangesToRedo));
+    }
+
     /// Regression tests for previous bugs
     mod reg {
         use super::*;
@@ -27,6 +27,12 @@ pub enum Category {
     Replace,
     /// An [`Action`] that causes nodes to be deleted from the tree
     Delete,
+    /// An [`Action`] that moves existing nodes to new locations in the tree
+    Rearrange,
+    /// An [`Action`] that copies nodes without changing the tree
+    Yank,
+    /// An [`Action`] that records or plays back macros
+    Macro,
     /// The action of the keystrokes is that Sapling should quit
     Quit,
     /// An [`Action`] that handles reading and writing from disk
@@ -35,18 +41,55 @@ pub enum Category {
     Undefined,
 }
 
+impl Category {
+    /// Every `Category` of [`Action`] which can be bound to a key, in the order they're shown in
+    /// the help
+    pub const BOUND: [Category; 10] = [
+        Category::Move,
+        Category::Insert,
+        Category::Replace,
+        Category::Delete,
+        Category::Rearrange,
+        Category::Yank,
+        Category::History,
+        Category::Macro,
+        Category::IO,
+        Category::Quit,
+    ];
+
+    /// Returns the title of this `Category`, as shown in the help
+    pub fn title(self) -> &'static str {
+        match self {
+            Category::Move => "Moving",
+            Category::History => "History",
+            Category::Insert => "Inserting",
+            Category::Replace => "Replacing",
+            Category::Delete => "Deleting",
+            Category::Rearrange => "Rearranging",
+            Category::Yank => "Yanking",
+            Category::Macro => "Macros",
+            Category::Quit => "Quitting",
+            Category::IO => "Files and commands",
+            Category::Undefined => "Other",
+        }
+    }
+}
+
 /// Returns the [`Color`] that all [`Action`]s of a given [`Category`] should be
 /// displayed.  This is not implemented as a method on [`Category`], because doing so
 /// would require [`Category`] to rely on the specific terminal backend used.  This way,
 /// we keep the terminal backend as encapsulated as possible.
 impl Category {
-    fn term_color(self) -> Color {
+    pub(super) fn term_color(self) -> Color {
         match self {
             Category::Move => Color::LightBlue,
             Category::History => Color::LightYellow,
             Category::Insert => Color::LightGreen,
             Category::Replace => Color::Cyan,
             Category::Delete => Color::Red,
+            Category::Rearrange => Color::LightMagenta,
+            Category::Yank => Color::Yellow,
+            Category::Macro => Color::Blue,
             Category::Quit => Color::Magenta,
             Category::IO => Color::Green,
             Category::Undefined => Color::LightRed,
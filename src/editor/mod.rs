@@ -1,34 +1,52 @@
 //! The top-level functionality of Sapling
 
+pub mod buffer;
+pub mod command_mode;
 pub mod dag;
 pub mod keystroke_log;
 pub mod normal_mode;
+pub mod pane;
+pub mod search_mode;
 pub mod state;
+mod terminal_guard;
 mod widgets;
 
-use crate::ast::Ast;
-use crate::config::{Config, DEBUG_HIGHLIGHTING};
+use crate::ast::{
+    display_token::{path_to_line, position_of_path},
+    pattern::Pattern,
+    Ast,
+};
+use crate::config::{self, Config, DEBUG_HIGHLIGHTING};
+use crate::core::Side;
 
-use dag::Dag;
+use buffer::{Buffer, SwapAction};
+use dag::LogMessage;
 use keystroke_log::KeyStrokeLog;
+use normal_mode::Scroll;
+use pane::{Pane, Panes};
 use state::State;
+use terminal_guard::TerminalGuard;
 
 use std::borrow::Cow;
+use std::collections::{HashSet, VecDeque};
 use std::io;
+use std::panic;
 use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
-use crossterm::{
-    cursor,
-    event::{Event, KeyEvent},
-    terminal,
-};
+use crossterm::event::{self, Event, KeyEvent, MouseButton, MouseEvent, MouseEventKind};
 use tui::{
     backend::CrosstermBackend,
     layout::{Constraint, Direction, Layout},
+    style::{Color, Style},
+    widgets::{Block, Borders},
 };
 
 pub(crate) type Terminal = tui::Terminal<CrosstermBackend<io::Stdout>>;
 
+/// The longest time between two clicks on the same cell for them to count as a double click
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(500);
+
 /// The [`State`] that Sapling is in during a transition function.  This has to exist, but
 /// none of the methods should ever be called, since doing so would require the transition function
 /// to unexpectedly fail, which is not possible (since the transition function must return a new
@@ -50,6 +68,10 @@ impl<'arena, Node: Ast<'arena>> State<'arena, Node> for IntermediateState {
         panic!("Invalid state should never exist except during state transitions.");
     }
 
+    fn mode_name(&self) -> &'static str {
+        panic!("Invalid state should never exist except during state transitions.");
+    }
+
     fn is_quit(&self) -> bool {
         panic!("Invalid state should never exist except during state transitions.");
     }
@@ -59,12 +81,30 @@ impl<'arena, Node: Ast<'arena>> State<'arena, Node> for IntermediateState {
     }
 }
 
+/// An input which is waiting to be fed into the state machine
+#[derive(Debug, Copy, Clone)]
+enum QueuedInput {
+    /// A keystroke from a macro which is being played back
+    Key(KeyEvent),
+    /// Marks the end of playing back a macro.  At this point, all the edits made by the macro are
+    /// grouped into one undo step.
+    EndOfMacro {
+        /// The register of the macro which has finished
+        register: char,
+        /// The index of the [`Buffer`] that was being edited when the macro started
+        buffer: usize,
+        /// The [`Dag::history_index`](dag::Dag::history_index) from before the macro started
+        history_start: usize,
+    },
+}
+
 /// A singleton struct to hold the top-level components of Sapling.
 pub struct Editor<'arena, Node: Ast<'arena>> {
-    /// The `Dag` that is storing the history of the `Editor`
-    tree: &'arena mut Dag<'arena, Node>,
-    /// The style that the tree is being printed to the screen
-    format_style: Node::FormatStyle,
+    /// The trees which are open for editing.  This is required to have length at least one.
+    buffers: Vec<Buffer<'arena, Node>>,
+    /// The views of the [`Buffer`]s which are on the screen.  The focused [`Pane`] shows the
+    /// [`Buffer`] currently being edited.
+    panes: Panes<'arena, Node>,
     /// The `tuikit` terminal that the `Editor` is rendering to
     term: Terminal,
     /// The current state-machine [`State`] that Sapling is in
@@ -73,47 +113,126 @@ pub struct Editor<'arena, Node: Ast<'arena>> {
     config: Config,
     /// A list of the keystrokes that have been executed, along with a summary of what they mean
     keystroke_log: KeyStrokeLog,
-    file_path: Option<PathBuf>,
+    /// The most recently submitted search [`Pattern`], the matches of which are highlighted
+    search_pattern: Option<Pattern>,
+    /// The macros stored in each register
+    macros: config::Macros,
+    /// The register and keystrokes of the macro currently being recorded (if any)
+    recording: Option<(char, Vec<KeyEvent>)>,
+    /// Inputs which should be processed before reading any more keystrokes from the user
+    queued_input: VecDeque<QueuedInput>,
+    /// The most recent successful edit and the count it was performed with, which can be repeated
+    /// with [`normal_mode::Action::Repeat`]
+    last_edit: Option<(usize, normal_mode::Action)>,
+    /// The nodes most recently yanked or deleted by an [`Operator`](normal_mode::Operator)
+    yank_register: Vec<&'arena Node>,
+    /// The commands typed into the command line, oldest first
+    command_history: Vec<String>,
+    /// `true` if the help popup is being shown, in which case the next key hides it
+    show_help: bool,
+    /// The time and screen cell of the last left click which wasn't part of a double click
+    last_click: Option<(Instant, u16, u16)>,
+    /// The time when the open files were last checked (see [`check_files`](Self::check_files))
+    last_file_check: Instant,
     log: tui_logger::TuiWidgetState,
+    /// Restores the terminal when the `Editor` is dropped, or `None` if the `Editor` never set up
+    /// the terminal (which is only the case in tests)
+    terminal_guard: Option<TerminalGuard>,
 }
 
 impl<'arena, Node: Ast<'arena> + 'arena> Editor<'arena, Node> {
-    /// Create a new [`Editor`] with a given tree
-    pub fn new(
-        tree: &'arena mut Dag<'arena, Node>,
-        format_style: Node::FormatStyle,
+    /// Create a new [`Editor`] with a given [`Buffer`] open, returning an error if the terminal
+    /// couldn't be set up
+    pub fn new(buffer: Buffer<'arena, Node>, config: Config) -> io::Result<Editor<'arena, Node>> {
+        let terminal_guard = TerminalGuard::new()?;
+        let term = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+        let macros = config::load_macros();
+        Ok(Self::with_terminal(
+            buffer,
+            config,
+            macros,
+            term,
+            Some(terminal_guard),
+        ))
+    }
+
+    /// Create an [`Editor`] which never touches the terminal or the user's saved macros, so that
+    /// tests can run commands against it
+    #[cfg(test)]
+    pub(crate) fn headless(buffer: Buffer<'arena, Node>, config: Config) -> Editor<'arena, Node> {
+        let area = tui::layout::Rect::new(0, 0, 80, 24);
+        let options = tui::TerminalOptions {
+            viewport: tui::Viewport::fixed(area),
+        };
+        let term = Terminal::with_options(CrosstermBackend::new(io::stdout()), options).unwrap();
+        Self::with_terminal(buffer, config, config::Macros::new(), term, None)
+    }
+
+    fn with_terminal(
+        buffer: Buffer<'arena, Node>,
         config: Config,
-        file_path: Option<PathBuf>,
+        macros: config::Macros,
+        term: Terminal,
+        terminal_guard: Option<TerminalGuard>,
     ) -> Editor<'arena, Node> {
-        let mut term = Terminal::new(CrosstermBackend::new(io::stdout())).unwrap();
-        crossterm::execute!(term.backend_mut(), terminal::EnterAlternateScreen).unwrap();
-        terminal::enable_raw_mode().unwrap();
+        let panes = Panes::new(Pane::new(0, &buffer.tree));
         Editor {
-            tree,
+            buffers: vec![buffer],
+            panes,
             term,
-            format_style,
             state: Box::new(normal_mode::State::default()),
             config,
             keystroke_log: KeyStrokeLog::new(10),
-            file_path,
+            search_pattern: None,
+            macros,
+            recording: None,
+            queued_input: VecDeque::new(),
+            last_edit: None,
+            yank_register: Vec::new(),
+            command_history: Vec::new(),
+            show_help: false,
+            last_click: None,
+            last_file_check: Instant::now(),
             log: tui_logger::TuiWidgetState::default(),
+            terminal_guard,
         }
     }
 
     /* ===== MAIN FUNCTIONS ===== */
 
-    /// Update the terminal UI display
-    fn update_display(&mut self) {
+    /// Update the terminal UI display, returning an error if the terminal couldn't be drawn to
+    fn update_display(&mut self) -> io::Result<()> {
+        let pending_hints = self.state.pending_hints(self);
         let Self {
             term,
             state,
-            tree,
+            buffers,
+            panes,
             keystroke_log,
             config,
-            format_style,
             log,
+            search_pattern,
+            recording,
+            show_help,
             ..
         } = self;
+        let focus = panes.focus();
+        let num_panes = panes.num_panes();
+        let current_buffer = &buffers[panes.focused().buffer];
+        let tree = &current_buffer.tree;
+        let prompt = state.prompt();
+        let status_bar = widgets::StatusBar {
+            layout: &config.status_line,
+            mode: state.mode_name(),
+            keystroke_buffer: &state.keystroke_buffer(),
+            prompt: prompt.as_deref(),
+            recording: recording.as_ref().map(|(register, _)| *register),
+            file_name: &current_buffer.name(),
+            is_dirty: tree.is_dirty(),
+            breadcrumb: &widgets::breadcrumb(tree.root(), tree.cursor_path()),
+            class_name: tree.cursor().class_name(),
+            undo_position: (tree.history_index(), tree.num_edits()),
+        };
         term.draw(|f| {
             let area = f.size();
             let rows = Layout::default()
@@ -128,88 +247,663 @@ impl<'arena, Node: Ast<'arena> + 'arena> Editor<'arena, Node> {
                 .split(cols[1]);
 
             f.render_widget(tui::widgets::Clear, area);
-            f.render_widget(
-                widgets::StatusBar {
-                    keystroke_buffer: &state.keystroke_buffer(),
-                },
-                rows[1],
-            );
-            f.render_widget(
-                widgets::TextView {
-                    tree: *tree,
-                    color_scheme: &config.color_scheme,
-                    format_style: &*format_style,
-                },
-                cols[0],
-            );
+            f.render_widget(status_bar, rows[1]);
+            let pane_areas = panes.areas(cols[0]);
+            for (i, (pane, mut area)) in panes.panes_mut().into_iter().zip(pane_areas).enumerate() {
+                let buffer = &buffers[pane.buffer];
+                let folds = buffer.tree.folds();
+                // Only the focused pane shows the cursor of the `Dag`, since the cursor is where
+                // the edits are made.  The cursors of the other panes have to follow any edits
+                // made in the focused pane, and may be hidden inside folds made in other panes.
+                let (cursor, cursor_path) = if i == focus {
+                    (buffer.tree.cursor(), buffer.tree.cursor_path())
+                } else {
+                    pane.follow_edits(&buffer.tree);
+                    let root = buffer.tree.root();
+                    (
+                        folds.visible_node(root, pane.cursor_path()),
+                        pane.cursor_path(),
+                    )
+                };
+                let cursor_position = position_of_path(
+                    buffer.tree.root(),
+                    cursor_path,
+                    &buffer.format_style,
+                    &folds,
+                );
+                // If there are several panes, then give each one a border showing which buffer
+                // it's viewing and whether it's focused
+                if num_panes > 1 {
+                    let border_color = if i == focus {
+                        Color::White
+                    } else {
+                        Color::DarkGray
+                    };
+                    let block = Block::default()
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(border_color))
+                        .title(buffer.name());
+                    let inner_area = block.inner(area);
+                    f.render_widget(block, area);
+                    area = inner_area;
+                }
+                pane.fit_view(area, cursor_position, config.scroll_margin);
+                // Find the nodes that should be highlighted as search matches
+                let search_matches: HashSet<*const Node> = search_pattern
+                    .iter()
+                    .flat_map(|pattern| pattern.find_all(buffer.tree.root()))
+                    .map(|m| m.node as *const Node)
+                    .collect();
+                f.render_stateful_widget(
+                    widgets::TextView {
+                        root: buffer.tree.root(),
+                        cursor,
+                        line_offset: pane.line_offset,
+                        column_offset: pane.column_offset,
+                        color_scheme: &config.color_scheme,
+                        format_style: &buffer.format_style,
+                        search_matches: &search_matches,
+                        folds: &folds,
+                    },
+                    area,
+                    &mut pane.node_map,
+                );
+            }
             f.render_widget(&*keystroke_log, details[0]);
             let mut logger = tui_logger::TuiLoggerWidget::default();
             logger.state(&*log);
             f.render_widget(logger, details[1]);
-        })
-        .unwrap();
+            if let Some((title, hints)) = &pending_hints {
+                f.render_widget(widgets::PendingHints { title, hints }, cols[0]);
+            }
+            if *show_help {
+                let sections = widgets::help_sections::<Node::Class>(&config.keymap);
+                f.render_widget(
+                    widgets::HelpPopup {
+                        sections: &sections,
+                    },
+                    area,
+                );
+            }
+        })?;
+        Ok(())
+    }
+
+    /// Responds to a key typed by the user, recording it into the macro being recorded and then
+    /// running any input that it queued up
+    fn handle_typed_key(&mut self, key: KeyEvent) {
+        // Any key closes the help, without being treated as a command or recorded into a macro
+        if self.show_help {
+            self.show_help = false;
+            return;
+        }
+        let was_recording = self.recording.is_some();
+        self.handle_key(key);
+        // Add the key to the macro being recorded.  We check that we were recording before the
+        // key was handled so that the keys which start and stop the recording aren't included in
+        // the macro.
+        if let (true, Some((_, keys))) = (was_recording, &mut self.recording) {
+            keys.push(key);
+        }
+        // Run any keystrokes which were queued up by the last key (e.g. a macro)
+        self.run_queued_input();
     }
 
-    fn mainloop(&mut self) {
+    /// Feed a single keystroke into the state machine, and log the result
+    fn handle_key(&mut self, key: KeyEvent) {
+        // Consume the key and use it to move through the state machine.  Here, we use
+        // `std::mem::replace` to allow us to move `self.state` into `State::transition` by
+        // replacing it with the temporary value of `Box::new(IntermediateState)`.
+        //
+        // `Box::new(IntermediateState)` is creating a `Box` of a zero-size type, which
+        // according to the docs
+        // (https://doc.rust-lang.org/std/boxed/struct.Box.html#method.new) does not
+        // perform a heap allocation.
+        let (new_state, log_entry) = State::transition(
+            std::mem::replace(
+                &mut self.state,
+                Box::new(IntermediateState) as Box<dyn State<'arena, Node>>,
+            ),
+            key,
+            self,
+        );
+
+        self.state = new_state;
+        // Moving the cursor into a folded node shows the contents of that node
+        let current_buffer = self.current_buffer();
+        self.buffers[current_buffer].tree.open_folds_around_cursor();
+
+        // Log the key to the keystroke log, and create a log message if required
+        self.keystroke_log.push_key(key);
+        if let Some((description, category)) = log_entry {
+            self.keystroke_log.log_entry(description, category);
+        }
+    }
+
+    /// Process any queued inputs (e.g. from macros), stopping early if Sapling should quit
+    fn run_queued_input(&mut self) {
+        while !self.state.is_quit() {
+            match self.queued_input.pop_front() {
+                Some(QueuedInput::Key(key)) => self.handle_key(key),
+                Some(QueuedInput::EndOfMacro {
+                    buffer,
+                    history_start,
+                    ..
+                }) => self.end_macro(buffer, history_start),
+                None => break,
+            }
+        }
+    }
+
+    /// Runs Sapling until the user quits, returning an error if the user's input couldn't be read
+    /// or the terminal couldn't be drawn to
+    fn mainloop(&mut self) -> io::Result<()> {
         log::trace!("Starting mainloop");
-        self.update_display();
+        self.update_display()?;
         // Sit in the infinte mainloop
-        while let Ok(event) = crossterm::event::read() {
+        loop {
+            // Wait for the user's input, checking the files whenever they pause
+            if !event::poll(self.config.swap_interval)? {
+                self.check_files();
+                self.update_display()?;
+                continue;
+            }
+            let event = event::read()?;
             /* RESPOND TO THE USER'S INPUT */
-            if let Event::Key(key) = event {
-                // Consume the key and use it to move through the state machine.  Here, we use
-                // `std::mem::replace` to allow us to move `self.state` into `State::transition` by
-                // replacing it with the temporary value of `Box::new(IntermediateState)`.
-                //
-                // `Box::new(IntermediateState)` is creating a `Box` of a zero-size type, which
-                // according to the docs
-                // (https://doc.rust-lang.org/std/boxed/struct.Box.html#method.new) does not
-                // perform a heap allocation.
-                let (new_state, log_entry) = State::transition(
-                    std::mem::replace(
-                        &mut self.state,
-                        Box::new(IntermediateState) as Box<dyn State<'arena, Node>>,
-                    ),
-                    key,
-                    self,
-                );
-
-                self.state = new_state;
-
-                // Log the key to the keystroke log, and create a log message if required
-                self.keystroke_log.push_key(key);
-                if let Some((description, category)) = log_entry {
-                    self.keystroke_log.log_entry(description, category);
-                }
+            match event {
+                Event::Key(key) => self.handle_typed_key(key),
+                Event::Mouse(mouse_event) => self.handle_mouse(mouse_event),
+                Event::Resize(..) => {}
             }
             // If we have reached `state::Quit` then we should exit the main loop
             if self.state.is_quit() {
-                break;
+                return Ok(());
+            }
+            if self.last_file_check.elapsed() >= self.config.swap_interval {
+                self.check_files();
             }
 
             // Make sure that the logger isn't taller than the screen
             self.keystroke_log
-                .set_max_entries(self.term.size().unwrap().height.into());
+                .set_max_entries(self.term.size()?.height.into());
             // Update the screen after every input (if this becomes a bottleneck then we can
             // optimise the number of calls to `update_display` but for now it's not worth the
             // added complexity)
-            self.update_display();
+            self.update_display()?;
+        }
+    }
+
+    /* BUFFERS */
+
+    /// Returns the index of the [`Buffer`] that is currently being edited
+    fn current_buffer(&self) -> usize {
+        self.panes.focused().buffer
+    }
+
+    /// Returns the [`Buffer`] that is currently being edited
+    fn buffer(&self) -> &Buffer<'arena, Node> {
+        &self.buffers[self.current_buffer()]
+    }
+
+    /// Returns a mutable reference to the [`Buffer`] that is currently being edited
+    fn buffer_mut(&mut self) -> &mut Buffer<'arena, Node> {
+        let current_buffer = self.current_buffer();
+        &mut self.buffers[current_buffer]
+    }
+
+    /// Switches to the [`Buffer`] editing the file at `path`, opening it in a new [`Buffer`] if
+    /// it isn't already open
+    fn open_file(&mut self, path: PathBuf) {
+        if let Some(index) = self
+            .buffers
+            .iter()
+            .position(|b| b.file_path.as_ref() == Some(&path))
+        {
+            self.switch_buffer(index);
+            return;
+        }
+        let buffer = self.buffer();
+        match Buffer::open(path, buffer.tree.arena(), buffer.format_style.clone()) {
+            Ok(buffer) => {
+                log::info!("Opened {}", buffer.name());
+                self.buffers.push(buffer);
+                let index = self.buffers.len() - 1;
+                self.panes
+                    .focused_mut()
+                    .show_buffer(index, &self.buffers[index].tree);
+            }
+            Err(message) => log::error!("{}", message),
+        }
+    }
+
+    /// Replaces the tree in the current [`Buffer`] with the contents of its file, as an edit
+    /// which can be undone.  Unless `force` is `true`, this is refused if the [`Buffer`] has
+    /// unsaved changes.
+    fn reload_buffer(&mut self, force: bool) {
+        let buffer = self.buffer_mut();
+        if buffer.tree.is_dirty() && !force {
+            log::warn!(
+                "{} has unsaved changes (add ! to discard them)",
+                buffer.name()
+            );
+            return;
+        }
+        match buffer.reload() {
+            Ok(()) => log::info!("Reloaded {} (use u to undo)", buffer.name()),
+            Err(message) => log::warn!("{}", message),
+        }
+    }
+
+    /// Makes the [`Buffer`] at a given index in [`buffers`](Self::buffers) the current one
+    fn switch_buffer(&mut self, index: usize) {
+        if index >= self.buffers.len() {
+            log::warn!("There is no buffer {}.", index + 1);
+            return;
+        }
+        self.panes
+            .focused_mut()
+            .show_buffer(index, &self.buffers[index].tree);
+        log::info!("Switched to {}", self.buffer().name());
+    }
+
+    /// Moves `count` [`Buffer`]s forwards or backwards through the buffer list, wrapping around
+    /// the ends
+    fn cycle_buffer(&mut self, count: usize, side: Side) {
+        let index = cycle_index(self.current_buffer(), self.buffers.len(), count, side);
+        self.switch_buffer(index);
+    }
+
+    /// Closes the current [`Buffer`].  Every [`Pane`] showing it moves to the [`Buffer`] after
+    /// it.  Unless `force` is `true`, this is refused if the [`Buffer`] has unsaved changes.
+    fn close_buffer(&mut self, force: bool) {
+        let buffer = self.buffer();
+        if self.buffers.len() == 1 {
+            log::warn!("Can't close the only buffer (use :q to quit)");
+            return;
+        }
+        if buffer.tree.is_dirty() && !force {
+            log::warn!(
+                "{} has unsaved changes (add ! to discard them)",
+                buffer.name()
+            );
+            return;
+        }
+        let closed_index = self.current_buffer();
+        let mut closed = self.buffers.remove(closed_index);
+        closed.remove_swap_file();
+        log::info!("Closed {}", closed.name());
+        // Fix the buffer indices of the panes, since they have shifted
+        let replacement = closed_index.min(self.buffers.len() - 1);
+        for pane in self.panes.panes_mut() {
+            if pane.buffer == closed_index {
+                *pane = Pane::new(replacement, &self.buffers[replacement].tree);
+            } else if pane.buffer > closed_index {
+                pane.buffer -= 1;
+            }
+        }
+    }
+
+    /// Logs a line for every open [`Buffer`], marking the current one with `%` and those with
+    /// unsaved changes with `[+]`
+    fn list_buffers(&self) {
+        let current_buffer = self.current_buffer();
+        for (i, buffer) in self.buffers.iter().enumerate() {
+            log::info!(
+                "{:>2}{} {}{}",
+                i + 1,
+                if i == current_buffer { "%" } else { " " },
+                buffer.name(),
+                if buffer.tree.is_dirty() { " [+]" } else { "" }
+            );
+        }
+    }
+
+    /* QUITTING */
+
+    /// Returns `true` if Sapling can quit without losing any unsaved changes, or if `force` is
+    /// `true`.  Otherwise, the first [`Buffer`] with unsaved changes is shown and a warning is
+    /// logged.
+    fn can_quit(&mut self, force: bool) -> bool {
+        if force {
+            return true;
+        }
+        match self.buffers.iter().position(|b| b.tree.is_dirty()) {
+            Some(index) => {
+                if index != self.current_buffer() {
+                    self.switch_buffer(index);
+                }
+                log::warn!(
+                    "{} has unsaved changes (use :wq or ZZ to write them, or :q! or ZQ to \
+                     throw them away)",
+                    self.buffers[index].name()
+                );
+                false
+            }
+            None => true,
+        }
+    }
+
+    /// Writes the current [`Buffer`] (to `path`, if given), and then returns whether Sapling can
+    /// quit (see [`can_quit`](Self::can_quit)).  If `only_if_dirty` is `true` and no `path` is
+    /// given, then the [`Buffer`] is only written if it has unsaved changes.  `overwrite` allows
+    /// the [`Buffer`]'s file to be overwritten if it has been changed by another program, and
+    /// `discard_others` throws away the unsaved changes of the other [`Buffer`]s.
+    fn write_and_quit(
+        &mut self,
+        path: Option<PathBuf>,
+        only_if_dirty: bool,
+        overwrite: bool,
+        discard_others: bool,
+    ) -> bool {
+        let buffer = self.buffer_mut();
+        let needs_writing = !only_if_dirty || path.is_some() || buffer.tree.is_dirty();
+        if needs_writing {
+            let result = buffer.write(path, overwrite);
+            let is_written = result.is_ok();
+            result.log_message();
+            if !is_written {
+                return false;
+            }
+        }
+        self.can_quit(discard_others)
+    }
+
+    /* FILES */
+
+    /// Brings the swap file of every [`Buffer`] up to date, and warns the user about any files
+    /// which have been changed by other programs
+    fn check_files(&mut self) {
+        for buffer in &mut self.buffers {
+            buffer.update_swap_file();
+            buffer.check_for_external_changes();
         }
+        self.last_file_check = Instant::now();
     }
 
-    /// Start the editor and enter the mainloop
-    pub fn run(mut self) {
+    /// Deals with the swap file left behind for the current [`Buffer`]'s file (e.g. by a crash),
+    /// by either restoring it, opening it alongside the current [`Buffer`] or deleting it
+    fn handle_old_swap_file(&mut self, action: SwapAction) {
+        let result = match action {
+            SwapAction::Recover => self.buffer_mut().recover_swap_file(),
+            SwapAction::Discard => self.buffer_mut().discard_swap_file(),
+            SwapAction::Diff => self.buffer().open_swap_file().map(|swap_buffer| {
+                self.buffers.push(swap_buffer);
+                self.split_pane(Direction::Horizontal);
+                let index = self.buffers.len() - 1;
+                self.panes
+                    .focused_mut()
+                    .show_buffer(index, &self.buffers[index].tree);
+                log::info!(
+                    "Opened the swap file in a new buffer (use :recover or :recover discard \
+                     in the original buffer to keep or delete it)"
+                );
+            }),
+        };
+        if let Err(message) = result {
+            log::warn!("{}", message);
+        }
+    }
+
+    /* PANES */
+
+    /// Splits the focused [`Pane`] into two [`Pane`]s showing the same [`Buffer`], stacked in a
+    /// given [`Direction`]
+    fn split_pane(&mut self, direction: Direction) {
+        let current_buffer = self.current_buffer();
+        let new_pane = Pane::new(current_buffer, &self.buffer().tree);
+        self.panes.split(direction, new_pane);
+    }
+
+    /// Closes the focused [`Pane`], unless it's the only one
+    fn close_pane(&mut self) {
+        if !self.panes.close() {
+            log::warn!("Can't close the only pane (use :q to quit)");
+            return;
+        }
+        // The closed pane's cursor is left in its `Dag`, so we move it to the new focused pane's
+        let Self { panes, buffers, .. } = self;
+        let pane = panes.focused_mut();
+        pane.restore_cursor(&mut buffers[pane.buffer].tree);
+    }
+
+    /// Moves the focus `count` [`Pane`]s forwards or backwards, wrapping around the ends
+    fn cycle_pane(&mut self, count: usize, side: Side) {
+        let index = cycle_index(self.panes.focus(), self.panes.num_panes(), count, side);
+        self.focus_pane(index);
+    }
+
+    /// Focuses the [`Pane`] with a given index, moving the [`Dag`](dag::Dag) cursors to match
+    fn focus_pane(&mut self, index: usize) {
+        let Self { panes, buffers, .. } = self;
+        let pane = panes.focused_mut();
+        pane.save_cursor(&buffers[pane.buffer].tree);
+        panes.set_focus(index);
+        let pane = panes.focused_mut();
+        pane.restore_cursor(&mut buffers[pane.buffer].tree);
+    }
+
+    /* MOUSE */
+
+    /// Responds to an event from the mouse.  Clicking on a node moves the cursor to it (or to its
+    /// parent, for a double click) and the scroll wheel scrolls the [`Pane`] under the mouse.
+    /// Either way, the [`Pane`] under the mouse is focused.
+    fn handle_mouse(&mut self, event: MouseEvent) {
+        let MouseEvent {
+            kind, column, row, ..
+        } = event;
+        let pane_index = match self
+            .panes
+            .panes()
+            .iter()
+            .position(|pane| pane.node_map.contains(column, row))
+        {
+            Some(index) => index,
+            None => return,
+        };
+        match kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                let now = Instant::now();
+                let is_double_click = matches!(
+                    self.last_click,
+                    Some((time, c, r)) if (c, r) == (column, row) && now - time < DOUBLE_CLICK_TIME
+                );
+                // The click after a double click starts a new double click
+                self.last_click = (!is_double_click).then_some((now, column, row));
+
+                let pane = self.panes.panes()[pane_index];
+                let mut path = match pane.node_map.path_at(column, row) {
+                    Some(path) => path.clone(),
+                    None => return,
+                };
+                if is_double_click {
+                    path.pop();
+                }
+                self.focus_pane(pane_index);
+                self.buffer_mut().tree.set_cursor_path(path);
+            }
+            MouseEventKind::ScrollDown | MouseEventKind::ScrollUp => {
+                let side = match kind {
+                    MouseEventKind::ScrollUp => Side::Prev,
+                    _ => Side::Next,
+                };
+                self.focus_pane(pane_index);
+                self.scroll(self.config.wheel_scroll_lines, Scroll::Lines(side));
+            }
+            _ => {}
+        }
+    }
+
+    /* SCROLLING */
+
+    /// Scrolls the view of the focused [`Pane`].  Scrolling by half a page or by lines also moves
+    /// the cursor, so that it stays at the same place on the screen.
+    fn scroll(&mut self, count: usize, scroll: Scroll) {
+        let margin = self.config.scroll_margin;
+        let Self { panes, buffers, .. } = self;
+        let pane = panes.focused_mut();
+        let Buffer {
+            tree, format_style, ..
+        } = &mut buffers[pane.buffer];
+        let folds = tree.folds();
+        let cursor_line =
+            position_of_path(tree.root(), tree.cursor_path(), format_style, &folds).line;
+        match scroll {
+            Scroll::HalfPage(side) | Scroll::Lines(side) => {
+                let distance = match scroll {
+                    Scroll::HalfPage(_) => count * (pane.height / 2).max(1),
+                    _ => count,
+                };
+                let target_line = match side {
+                    Side::Prev => {
+                        pane.line_offset = pane.line_offset.saturating_sub(distance);
+                        cursor_line.saturating_sub(distance)
+                    }
+                    Side::Next => {
+                        pane.line_offset += distance;
+                        cursor_line + distance
+                    }
+                };
+                let path = path_to_line(tree.root(), target_line, format_style, &folds);
+                tree.set_cursor_path(path);
+            }
+            Scroll::CursorToTop => pane.line_offset = cursor_line.saturating_sub(margin),
+            Scroll::CursorToMiddle => {
+                pane.line_offset = cursor_line.saturating_sub(pane.height / 2);
+            }
+            Scroll::CursorToBottom => {
+                pane.line_offset = (cursor_line + margin + 1).saturating_sub(pane.height);
+            }
+        }
+    }
+
+    /* MACROS */
+
+    /// Start recording keystrokes into a given macro register
+    fn start_recording(&mut self, register: char) {
+        log::info!("Recording macro into '{}'", register);
+        self.recording = Some((register, Vec::new()));
+    }
+
+    /// Stop recording the current macro, storing it in its register and saving all the macros to
+    /// the config directory
+    fn stop_recording(&mut self) {
+        if let Some((register, keys)) = self.recording.take() {
+            log::info!("Recorded {} keys into '{}'", keys.len(), register);
+            self.macros.insert(register, keys);
+            config::save_macros(&self.macros);
+        }
+    }
+
+    /// Queue up the macro in a given register to be played back `count` times.  All the edits
+    /// made by the macro will be grouped into one undo step.
+    fn play_macro(&mut self, register: char, count: usize) {
+        // If this macro is already being played, then playing it again would never terminate
+        let is_playing = self.queued_input.iter().any(
+            |input| matches!(input, QueuedInput::EndOfMacro { register: r, .. } if *r == register),
+        );
+        if is_playing {
+            log::warn!("Can't play macro '{}' from inside itself.", register);
+            return;
+        }
+        let keys = match self.macros.get(&register) {
+            Some(keys) => keys,
+            None => {
+                log::warn!("No macro recorded in '{}'.", register);
+                return;
+            }
+        };
+        // Add the keys to the front of the queue, so that macros played by other macros are run
+        // immediately
+        self.queued_input.push_front(QueuedInput::EndOfMacro {
+            register,
+            buffer: self.current_buffer(),
+            history_start: self.buffer().tree.history_index(),
+        });
+        for _ in 0..count {
+            for key in keys.iter().rev() {
+                self.queued_input.push_front(QueuedInput::Key(*key));
+            }
+        }
+        log::info!("Playing macro '{}' {} times", register, count);
+    }
+
+    /// Groups the edits made by a macro into one undo step, once the macro has finished
+    fn end_macro(&mut self, buffer: usize, history_start: usize) {
+        if let Some(buffer) = self.buffers.get_mut(buffer) {
+            buffer.tree.group_edits_since(history_start);
+        }
+    }
+
+    /// Stops playing every macro, throwing away their remaining keys.  As in Vim, this is called
+    /// when a command fails, since the rest of the macro would run in an unexpected place.
+    fn abort_macros(&mut self) {
+        if self.queued_input.is_empty() {
+            return;
+        }
+        log::warn!("Stopped playing macro, since a command failed");
+        for input in std::mem::take(&mut self.queued_input) {
+            if let QueuedInput::EndOfMacro {
+                buffer,
+                history_start,
+                ..
+            } = input
+            {
+                self.end_macro(buffer, history_start);
+            }
+        }
+    }
+
+    /// Start the editor and enter the mainloop.  The terminal is restored when this returns, even
+    /// if Sapling panics.  An error is returned if Sapling had to stop because the terminal
+    /// stopped working.
+    pub fn run(mut self) -> io::Result<()> {
         // Start the mainloop, which will not exit until Sapling is ready to close
-        self.mainloop();
-        // Show the cursor before closing so that the cursor isn't permanently disabled
-        // (see issue `lotabout/tuikit#28`: https://github.com/lotabout/tuikit/issues/28)
-        log::trace!("Making the cursor reappear.");
-        crossterm::execute!(
-            self.term.backend_mut(),
-            terminal::LeaveAlternateScreen,
-            cursor::Show
-        )
-        .unwrap();
-        terminal::disable_raw_mode().unwrap();
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| self.mainloop()));
+        match result {
+            Ok(Ok(())) => {
+                // Any unsaved changes have been deliberately thrown away, so they don't need
+                // recovering
+                for buffer in &mut self.buffers {
+                    buffer.remove_swap_file();
+                }
+                Ok(())
+            }
+            // The user didn't ask to quit, so their unsaved changes are written somewhere safe.
+            // The terminal is restored first, so that we can tell them where.
+            Ok(Err(e)) => {
+                self.terminal_guard = None;
+                self.write_emergency_copies();
+                Err(e)
+            }
+            Err(payload) => {
+                // The panic hook has already restored the terminal, so we can write to stderr
+                self.write_emergency_copies();
+                panic::resume_unwind(payload);
+            }
+        }
     }
+
+    /// Writes every [`Buffer`] with unsaved changes somewhere safe after a panic, and tells the
+    /// user where they were written
+    fn write_emergency_copies(&self) {
+        for (index, buffer) in self.buffers.iter().enumerate() {
+            if !buffer.tree.is_dirty() {
+                continue;
+            }
+            match buffer.write_emergency_copy(index) {
+                Ok(path) => eprintln!("Unsaved changes to {} written to {:?}", buffer.name(), path),
+                Err(e) => eprintln!("Couldn't save the changes to {}: {}", buffer.name(), e),
+            }
+        }
+    }
+}
+
+/// Returns the index `count` steps from `index` towards a given [`Side`] of a list with length
+/// `len`, wrapping around the ends of the list
+fn cycle_index(index: usize, len: usize, count: usize, side: Side) -> usize {
+    let offset = match side {
+        Side::Next => count % len,
+        Side::Prev => len - count % len,
+    };
+    (index + offset) % len
 }
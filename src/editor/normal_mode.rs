@@ -1,16 +1,17 @@
 //! The code for 'normal-mode', similar to that of Vim
 
-use super::dag::{Insertable, LogMessage};
-use super::{keystroke_log::Category, state, Editor};
-use crate::ast::Ast;
+use super::dag::{Dag, Insertable, LogMessage};
+use super::widgets::Hint;
+use super::{command_mode, keystroke_log::Category, search_mode, state, Editor};
+use crate::ast::{Ast, AstClass};
 use crate::config::KeyMap;
-use crate::core::{keystrokes_to_string, Direction, Side};
+use crate::core::{keystrokes_to_string, Direction, Fold, Side};
 
 use std::borrow::Cow;
-use std::io::prelude::*;
+use std::collections::HashMap;
 use std::iter::Peekable;
 
-use crossterm::event::{KeyCode, KeyEvent};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 
 /// The struct covering all the [`State`](state::State)s which correspond to Sapling being in
 /// normal mode.
@@ -19,6 +20,7 @@ pub struct State {
     keystroke_buffer: Vec<KeyEvent>,
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for State {
     fn default() -> Self {
         State {
@@ -39,63 +41,24 @@ impl<'arena, Node: Ast<'arena>> state::State<'arena, Node> for State {
     ) {
         self.keystroke_buffer.push(key);
 
-        let tree = &mut editor.tree;
-
-        let log_entry = match parse_command(&editor.config.keymap, &self.keystroke_buffer) {
+        let is_recording = editor.recording.is_some();
+        let keymap = &editor.config.keymap;
+        let log_entry = match parse_command(keymap, &self.keystroke_buffer, is_recording) {
             // If the command buffer is a valid and complete command, then we execute the resulting
             // 'action'
             Ok((count, action)) => {
+                self.keystroke_buffer.clear();
                 // If the count is 0, then the command does not execute.  So we short-circuit in
                 // this case
                 if count == 0 {
                     return (self, Some(("no action".to_owned(), Category::Undefined)));
                 }
-                match action {
-                    // If the command was a 'quit', then immediately make a state transition to the
-                    // 'Quitted' state.  It doesn't matter what the count is, because quitting is
-                    // idempotent
-                    Action::Quit => {
-                        return (
-                            Box::new(state::Quit),
-                            Some((action.description(), action.category())),
-                        );
-                    }
-                    Action::Write => {
-                        if let Some(path) = &editor.file_path {
-                            // If the editor was given a file-path, then write to it
-                            let mut file = std::fs::File::create(path).unwrap();
-                            let mut content = tree.to_text(&editor.format_style);
-                            // Force the file to finish with a newline.  BTW, <str>.chars().last()
-                            // is O(1), regardless of the length of the string.
-                            if !content.ends_with('\n') {
-                                content.push('\n');
-                            }
-                            file.write_all(content.as_bytes()).unwrap();
-                        } else {
-                            // Otherwise, log a warning and do nothing
-                            log::warn!("No file to write to!");
-                        }
-                        // If we haven't returned yet, then clear the buffer
-                        self.keystroke_buffer.clear();
-                        return (self, Some((action.description(), action.category())));
-                    }
-                    // Otherwise, we perform the action on the `Dag`.  This returns the
-                    // `EditResult`, which is logged outside the `match`
-                    Action::Undo => tree.undo(count),
-                    Action::Redo => tree.redo(count),
-                    Action::MoveCursor(direction) => tree.move_cursor(count, direction),
-                    Action::Replace(c) => tree.replace_cursor(count, c),
-                    Action::InsertChild(c) => tree.insert_child(count, c),
-                    Action::InsertBefore(c) => tree.insert_next_to_cursor(count, c, Side::Prev),
-                    Action::InsertAfter(c) => tree.insert_next_to_cursor(count, c, Side::Next),
-                    Action::Delete => tree.delete_cursor(count),
-                }
-                .log_message();
-                (action.description(), action.category())
+                let log_entry = (action.description(), action.category());
+                return (self.execute_action(editor, count, action), Some(log_entry));
             }
             // If the command is incomplete, we early return without clearing the buffer or logging
             // any messages
-            Err(ParseErr::Incomplete) => return (self, None),
+            Err(ParseErr::Incomplete(_)) => return (self, None),
             // If the command is invalid, we report the invalid command as a log message
             Err(ParseErr::Invalid) => (
                 format!(
@@ -111,19 +74,249 @@ impl<'arena, Node: Ast<'arena>> state::State<'arena, Node> for State {
         (self, Some(log_entry))
     }
 
+    fn mode_name(&self) -> &'static str {
+        "NORMAL"
+    }
+
     fn keystroke_buffer(&self) -> Cow<'_, str> {
         Cow::from(keystrokes_to_string(&self.keystroke_buffer))
     }
+
+    fn pending_hints(&self, editor: &Editor<'arena, Node>) -> Option<(String, Vec<Hint>)> {
+        let keymap = &editor.config.keymap;
+        let is_recording = editor.recording.is_some();
+        let expected = match parse_command(keymap, &self.keystroke_buffer, is_recording) {
+            Err(ParseErr::Incomplete(expected)) if !self.keystroke_buffer.is_empty() => expected,
+            _ => return None,
+        };
+        let count_hint = Hint::new("0-9", "count");
+        let (title, hints) = match expected {
+            Expected::Command => {
+                // Only the bindings which start with the keys typed after the count can finish
+                // the command
+                let typed = unfinished_binding(keymap, &self.keystroke_buffer);
+                let mut hints = match typed.is_empty() {
+                    true => vec![count_hint],
+                    false => Vec::new(),
+                };
+                let bindings = keymap.after_keys(typed)?.bindings();
+                hints.extend(group_hints(
+                    bindings
+                        .into_iter()
+                        .map(|(keys, cmd)| (keys, cmd.summary_string().to_owned())),
+                ));
+                ("command", hints)
+            }
+            Expected::Node(cmd) => {
+                let tree = &editor.buffer().tree;
+                let classes = Node::Class::all().iter().map(|class| Hint {
+                    keys: class.to_char().to_string(),
+                    description: class.name().to_owned(),
+                    is_valid: can_insert(tree, cmd, *class),
+                });
+                let hints = std::iter::once(count_hint).chain(classes).collect();
+                (cmd.summary_string(), hints)
+            }
+            Expected::Register(cmd) => {
+                let name = match cmd {
+                    CmdType::SetMark | CmdType::MoveToMark => "name of a mark",
+                    _ => "name of a register",
+                };
+                (cmd.summary_string(), vec![Hint::new("any key", name)])
+            }
+            Expected::Motion(operator) => {
+                let mut hints = vec![count_hint];
+                hints.extend(self.continuations(keymap, expected));
+                (CmdType::Operator(operator).summary_string(), hints)
+            }
+            Expected::SubCommand(cmd) => {
+                (cmd.summary_string(), self.continuations(keymap, expected))
+            }
+        };
+        Some((title.to_owned(), hints))
+    }
+}
+
+impl State {
+    /// Returns [`Hint`]s for the keys which would complete the command in the keystroke buffer,
+    /// if it's waiting for the `expected` motion or sub-command.  Motions are found in `keymap`
+    /// (starting from any keys of the motion which have already been typed), whereas each
+    /// sub-command has a fixed list of [keys](sub_commands).
+    fn continuations(&self, keymap: &KeyMap, expected: Expected) -> Vec<Hint> {
+        let completions: Vec<(Vec<KeyEvent>, String)> = match expected {
+            Expected::Motion(operator) => {
+                let typed = unfinished_binding(keymap, &self.keystroke_buffer);
+                let bindings = keymap
+                    .after_keys(typed)
+                    .map_or(Vec::new(), KeyMap::bindings);
+                bindings
+                    .into_iter()
+                    .filter_map(|(keys, cmd)| {
+                        let motion = match cmd {
+                            CmdType::Motion(direction) => Some(direction),
+                            CmdType::Operator(op) if op == operator => None,
+                            _ => return None,
+                        };
+                        Some((keys, Action::Operate(operator, motion).description()))
+                    })
+                    .collect()
+            }
+            Expected::SubCommand(cmd) => sub_commands(cmd)
+                .iter()
+                .map(|(c, action)| {
+                    (
+                        vec![KeyEvent::from(KeyCode::Char(*c))],
+                        action.description(),
+                    )
+                })
+                .collect(),
+            Expected::Command | Expected::Node(_) | Expected::Register(_) => Vec::new(),
+        };
+        group_hints(completions.into_iter())
+    }
+
+    /// Perform an [`Action`] `count` times, returning the [`State`](state::State) that Sapling
+    /// should be in afterwards
+    fn execute_action<'arena, Node: Ast<'arena>>(
+        self: Box<Self>,
+        editor: &mut Editor<'arena, Node>,
+        count: usize,
+        action: Action,
+    ) -> Box<dyn state::State<'arena, Node>> {
+        let current_buffer = editor.current_buffer();
+        let tree = &mut editor.buffers[current_buffer].tree;
+        let result = match action {
+            // If the command was a 'quit' and there are no unsaved changes, then immediately
+            // make a state transition to the 'Quitted' state.  It doesn't matter what the count
+            // is, because quitting is idempotent
+            Action::Quit | Action::ForceQuit => {
+                return match editor.can_quit(action == Action::ForceQuit) {
+                    true => Box::new(state::Quit),
+                    false => self,
+                };
+            }
+            Action::WriteAndQuit => {
+                return match editor.write_and_quit(None, true, false, false) {
+                    true => Box::new(state::Quit),
+                    false => self,
+                };
+            }
+            Action::Write => {
+                editor.buffer_mut().write(None, false).log_message();
+                return self;
+            }
+            Action::Help => {
+                editor.show_help = !editor.show_help;
+                return self;
+            }
+            // Searching requires the user to type a pattern, so we switch to search mode
+            Action::Search => return Box::new(search_mode::State::search()),
+            Action::CommandLine => {
+                return Box::new(command_mode::State::new(editor.command_history.len()))
+            }
+            Action::SearchReplace => return Box::new(search_mode::State::replace()),
+            // Macros are handled by the `Editor`, since they change which keys are fed to the
+            // state machine
+            Action::RecordMacro(register) => {
+                editor.start_recording(register);
+                return self;
+            }
+            Action::StopRecording => {
+                editor.stop_recording();
+                return self;
+            }
+            Action::PlayMacro(register) => {
+                editor.play_macro(register, count);
+                return self;
+            }
+            // Scrolling is handled by the `Editor`, since each pane has its own view
+            Action::Scroll(scroll) => {
+                editor.scroll(count, scroll);
+                return self;
+            }
+            // Repeating an edit is the same as performing it again, possibly with a new count
+            Action::Repeat(new_count) => match editor.last_edit {
+                Some((last_count, last_action)) => {
+                    return self.execute_action(
+                        editor,
+                        new_count.unwrap_or(last_count),
+                        last_action,
+                    )
+                }
+                None => {
+                    log::warn!("No edit to repeat.");
+                    return self;
+                }
+            },
+            Action::NextMatch(side) => match &editor.search_pattern {
+                Some(pattern) => tree.move_to_match(count, pattern, side),
+                None => {
+                    log::warn!("No search pattern to match against.");
+                    return self;
+                }
+            },
+            // Otherwise, we perform the action on the `Dag`.  This returns the `EditResult`,
+            // which is logged outside the `match`
+            Action::Undo => tree.undo(count),
+            Action::Redo => tree.redo(count),
+            Action::MoveCursor(direction) => tree.move_cursor(count, direction),
+            Action::SetMark(name) => tree.set_mark(name),
+            Action::MoveToMark(name) => tree.move_to_mark(name),
+            Action::Jump(side) => tree.jump(count, side),
+            Action::Replace(c) => tree.replace_cursor(count, c),
+            Action::InsertChild(c) => tree.insert_child(count, c),
+            Action::InsertBefore(c) => tree.insert_next_to_cursor(count, c, Side::Prev),
+            Action::InsertAfter(c) => tree.insert_next_to_cursor(count, c, Side::Next),
+            Action::Delete => tree.delete_cursor(count),
+            // Deleted nodes are kept in the yank register, so that they can be put elsewhere
+            Action::Operate(Operator::Delete, motion) => match tree.yank(count, motion) {
+                Ok(nodes) => {
+                    let result = tree.delete_motion(count, motion);
+                    if result.is_ok() {
+                        editor.yank_register = nodes;
+                    }
+                    result
+                }
+                Err(e) => Err(e),
+            },
+            Action::Operate(Operator::Yank, motion) => match tree.yank(count, motion) {
+                Ok(nodes) => {
+                    log::info!("Yanking {} nodes", nodes.len());
+                    editor.yank_register = nodes;
+                    return self;
+                }
+                Err(e) => Err(e),
+            },
+            Action::Put => tree.put(count, &editor.yank_register),
+            Action::MoveNode(side) => tree.move_node(count, side),
+            Action::Slurp(side) => tree.slurp_cursor(side),
+            Action::Barf(side) => tree.barf_cursor(side),
+            Action::Fold(fold) => tree.fold(count, fold),
+        };
+        // Remember successful edits so that they can be repeated
+        if result.is_ok() && action.is_edit() {
+            editor.last_edit = Some((count, action));
+        }
+        // Macros stop at the first command which fails
+        if result.is_err() {
+            editor.abort_macros();
+        }
+        result.log_message();
+        self
+    }
 }
 
-/// The possible keystroke typed by user without any parameters.  Each `KeyStroke` can be mapped to
-/// an individual [`char`].
+/// The possible keystroke typed by user without any parameters.  Each `CmdType` can be bound to
+/// any number of key sequences by the [`KeyMap`].
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum CmdType {
     /// Quit Sapling
     Quit,
     /// Write current buffer to disk
     Write,
+    /// Quit Sapling after writing the current buffer or throwing away all changes, expects
+    /// another key as an argument
+    Exit,
     /// Replace the selected node, expects an argument
     Replace,
     /// Insert a new node as the last child of the cursor, expects an argument
@@ -134,36 +327,320 @@ pub enum CmdType {
     InsertAfter,
     /// Delete the cursor
     Delete,
-    /// Move cursor in given direction.  The direction is part of the keystroke, since movements in
-    /// all 4 directions are mapped to single characters.
-    MoveCursor(Direction),
+    /// Move the node under the cursor through its siblings
+    MoveNode(Side),
+    /// Move the node under the cursor into the adjacent sibling on a given [`Side`]
+    Slurp(Side),
+    /// Move the node under the cursor out of its parent, to a given [`Side`] of that parent
+    Barf(Side),
+    /// Move cursor in given direction.  The direction is part of the keystroke, since every
+    /// motion has its own key binding.  Motions can also follow an [`Operator`] to choose which
+    /// nodes it acts on.
+    Motion(Direction),
+    /// Start an [`Operator`], which expects a motion (or the same key again) as an argument
+    Operator(Operator),
+    /// Insert the nodes from the yank register after the cursor
+    Put,
+    /// Start typing a search pattern
+    Search,
+    /// Start typing a command into the command line
+    CommandLine,
+    /// Move the cursor to the next or previous match of the current search pattern
+    NextMatch(Side),
+    /// Mark the node under the cursor, expects the mark's name as an argument
+    SetMark,
+    /// Move the cursor to a marked node, expects the mark's name as an argument
+    MoveToMark,
+    /// Move the cursor to an older or newer location in the jump list
+    Jump(Side),
+    /// Scroll half a screen down or up, moving the cursor with the text
+    HalfPage(Side),
+    /// Change the view of the focused pane or the folds of its tree, expects another key as an
+    /// argument
+    View,
+    /// Start typing a search pattern and a replacement for its matches
+    SearchReplace,
+    /// Start recording a macro into a register (given as an argument), or stop recording if a
+    /// macro is already being recorded
+    RecordMacro,
+    /// Play back the macro stored in a register, expects an argument
+    PlayMacro,
+    /// Repeat the last edit at the cursor
+    Repeat,
     /// Undo the last change
     Undo,
     /// Redo a change
     Redo,
+    /// Show or hide the help
+    Help,
 }
 
 impl CmdType {
+    /// Every `CmdType`, in the order they're listed in [`summary_string`](Self::summary_string)
+    pub const ALL: [CmdType; 49] = [
+        CmdType::Quit,
+        CmdType::Write,
+        CmdType::Exit,
+        CmdType::Replace,
+        CmdType::InsertChild,
+        CmdType::InsertBefore,
+        CmdType::InsertAfter,
+        CmdType::Delete,
+        CmdType::Operator(Operator::Delete),
+        CmdType::Operator(Operator::Yank),
+        CmdType::Put,
+        CmdType::MoveNode(Side::Prev),
+        CmdType::MoveNode(Side::Next),
+        CmdType::Slurp(Side::Prev),
+        CmdType::Slurp(Side::Next),
+        CmdType::Barf(Side::Prev),
+        CmdType::Barf(Side::Next),
+        CmdType::Motion(Direction::Down),
+        CmdType::Motion(Direction::Up),
+        CmdType::Motion(Direction::Prev),
+        CmdType::Motion(Direction::Next),
+        CmdType::Motion(Direction::FirstSibling),
+        CmdType::Motion(Direction::LastSibling),
+        CmdType::Motion(Direction::Root),
+        CmdType::Motion(Direction::PreorderNext),
+        CmdType::Motion(Direction::PreorderPrev),
+        CmdType::Motion(Direction::NextLeaf),
+        CmdType::Motion(Direction::PrevLeaf),
+        CmdType::Motion(Direction::NextOfClass),
+        CmdType::Motion(Direction::PrevOfClass),
+        CmdType::Motion(Direction::NthChild),
+        CmdType::Search,
+        CmdType::CommandLine,
+        CmdType::NextMatch(Side::Prev),
+        CmdType::NextMatch(Side::Next),
+        CmdType::SetMark,
+        CmdType::MoveToMark,
+        CmdType::Jump(Side::Prev),
+        CmdType::Jump(Side::Next),
+        CmdType::HalfPage(Side::Prev),
+        CmdType::HalfPage(Side::Next),
+        CmdType::View,
+        CmdType::SearchReplace,
+        CmdType::RecordMacro,
+        CmdType::PlayMacro,
+        CmdType::Repeat,
+        CmdType::Undo,
+        CmdType::Redo,
+        CmdType::Help,
+    ];
+
+    /// Returns the name used to refer to this `CmdType` in the config file (e.g. `"insert-child"`)
+    pub fn name(&self) -> &'static str {
+        match self {
+            CmdType::Quit => "quit",
+            CmdType::Write => "write",
+            CmdType::Exit => "exit",
+            CmdType::Replace => "replace",
+            CmdType::InsertChild => "insert-child",
+            CmdType::InsertBefore => "insert-before",
+            CmdType::InsertAfter => "insert-after",
+            CmdType::Delete => "delete",
+            CmdType::Operator(Operator::Delete) => "delete-motion",
+            CmdType::Operator(Operator::Yank) => "yank-motion",
+            CmdType::Put => "put",
+            CmdType::MoveNode(Side::Prev) => "move-node-prev",
+            CmdType::MoveNode(Side::Next) => "move-node-next",
+            CmdType::Slurp(Side::Prev) => "slurp-prev",
+            CmdType::Slurp(Side::Next) => "slurp-next",
+            CmdType::Barf(Side::Prev) => "barf-prev",
+            CmdType::Barf(Side::Next) => "barf-next",
+            CmdType::Motion(Direction::Down) => "move-down",
+            CmdType::Motion(Direction::Up) => "move-up",
+            CmdType::Motion(Direction::Prev) => "move-prev",
+            CmdType::Motion(Direction::Next) => "move-next",
+            CmdType::Motion(Direction::FirstSibling) => "move-first-sibling",
+            CmdType::Motion(Direction::LastSibling) => "move-last-sibling",
+            CmdType::Motion(Direction::Root) => "move-root",
+            CmdType::Motion(Direction::PreorderNext) => "move-preorder-next",
+            CmdType::Motion(Direction::PreorderPrev) => "move-preorder-prev",
+            CmdType::Motion(Direction::NextLeaf) => "move-next-leaf",
+            CmdType::Motion(Direction::PrevLeaf) => "move-prev-leaf",
+            CmdType::Motion(Direction::NextOfClass) => "move-next-of-class",
+            CmdType::Motion(Direction::PrevOfClass) => "move-prev-of-class",
+            CmdType::Motion(Direction::NthChild) => "move-nth-child",
+            CmdType::Search => "search",
+            CmdType::CommandLine => "command-line",
+            CmdType::NextMatch(Side::Prev) => "prev-match",
+            CmdType::NextMatch(Side::Next) => "next-match",
+            CmdType::SetMark => "set-mark",
+            CmdType::MoveToMark => "move-to-mark",
+            CmdType::Jump(Side::Prev) => "jump-prev",
+            CmdType::Jump(Side::Next) => "jump-next",
+            CmdType::HalfPage(Side::Prev) => "half-page-up",
+            CmdType::HalfPage(Side::Next) => "half-page-down",
+            CmdType::View => "view",
+            CmdType::SearchReplace => "search-replace",
+            CmdType::RecordMacro => "record-macro",
+            CmdType::PlayMacro => "play-macro",
+            CmdType::Repeat => "repeat",
+            CmdType::Undo => "undo",
+            CmdType::Redo => "redo",
+            CmdType::Help => "help",
+        }
+    }
+
+    /// Returns the `CmdType` with a given [`name`](Self::name), or `None` if there isn't one
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|cmd| cmd.name() == name)
+    }
+
     /// Returns a lower-case summary string of the given keystroke
     pub fn summary_string(&self) -> &'static str {
         match self {
             CmdType::Quit => "quit",
             CmdType::Write => "write",
+            CmdType::Exit => "write and quit or force quit",
             CmdType::Replace => "replace",
             CmdType::InsertChild => "insert child",
             CmdType::InsertBefore => "insert before",
             CmdType::InsertAfter => "insert after",
             CmdType::Delete => "delete",
-            CmdType::MoveCursor(Direction::Down) => "move to child",
-            CmdType::MoveCursor(Direction::Up) => "move to parent",
-            CmdType::MoveCursor(Direction::Prev) => "move to previous sibling",
-            CmdType::MoveCursor(Direction::Next) => "move to next sibling",
+            CmdType::Operator(Operator::Delete) => "delete motion",
+            CmdType::Operator(Operator::Yank) => "yank motion",
+            CmdType::Put => "put",
+            CmdType::MoveNode(Side::Prev) => "move node back",
+            CmdType::MoveNode(Side::Next) => "move node forward",
+            CmdType::Slurp(Side::Prev) => "move node into previous sibling",
+            CmdType::Slurp(Side::Next) => "move node into next sibling",
+            CmdType::Barf(Side::Prev) => "move node out before parent",
+            CmdType::Barf(Side::Next) => "move node out after parent",
+            CmdType::Motion(Direction::Down) => "move to child",
+            CmdType::Motion(Direction::Up) => "move to parent",
+            CmdType::Motion(Direction::Prev) => "move to previous sibling",
+            CmdType::Motion(Direction::Next) => "move to next sibling",
+            CmdType::Motion(Direction::FirstSibling) => "move to first sibling",
+            CmdType::Motion(Direction::LastSibling) => "move to last sibling",
+            CmdType::Motion(Direction::Root) => "move to root",
+            CmdType::Motion(Direction::PreorderNext) => "move to next node",
+            CmdType::Motion(Direction::PreorderPrev) => "move to previous node",
+            CmdType::Motion(Direction::NextLeaf) => "move to next leaf",
+            CmdType::Motion(Direction::PrevLeaf) => "move to previous leaf",
+            CmdType::Motion(Direction::NextOfClass) => "move to next node of same type",
+            CmdType::Motion(Direction::PrevOfClass) => "move to previous node of same type",
+            CmdType::Motion(Direction::NthChild) => "move to nth child",
+            CmdType::Search => "search",
+            CmdType::CommandLine => "command line",
+            CmdType::NextMatch(Side::Prev) => "move to previous match",
+            CmdType::NextMatch(Side::Next) => "move to next match",
+            CmdType::SetMark => "set mark",
+            CmdType::MoveToMark => "move to mark",
+            CmdType::Jump(Side::Prev) => "jump to older location",
+            CmdType::Jump(Side::Next) => "jump to newer location",
+            CmdType::HalfPage(Side::Prev) => "scroll half a page up",
+            CmdType::HalfPage(Side::Next) => "scroll half a page down",
+            CmdType::View => "change view",
+            CmdType::SearchReplace => "search and replace",
+            CmdType::RecordMacro => "record macro",
+            CmdType::PlayMacro => "play macro",
+            CmdType::Repeat => "repeat last edit",
             CmdType::Undo => "undo",
             CmdType::Redo => "redo",
+            CmdType::Help => "show help",
+        }
+    }
+
+    /// Returns the [`Category`] of the [`Action`]s which this `CmdType` starts
+    pub fn category(&self) -> Category {
+        match self {
+            CmdType::Replace | CmdType::SearchReplace => Category::Replace,
+            CmdType::InsertChild | CmdType::InsertBefore | CmdType::InsertAfter | CmdType::Put => {
+                Category::Insert
+            }
+            CmdType::Delete | CmdType::Operator(Operator::Delete) => Category::Delete,
+            CmdType::Operator(Operator::Yank) => Category::Yank,
+            CmdType::MoveNode(_) | CmdType::Slurp(_) | CmdType::Barf(_) => Category::Rearrange,
+            CmdType::Motion(_)
+            | CmdType::Search
+            | CmdType::NextMatch(_)
+            | CmdType::SetMark
+            | CmdType::MoveToMark
+            | CmdType::Jump(_)
+            | CmdType::HalfPage(_)
+            | CmdType::View => Category::Move,
+            CmdType::Undo | CmdType::Redo | CmdType::Repeat => Category::History,
+            CmdType::RecordMacro | CmdType::PlayMacro => Category::Macro,
+            CmdType::Quit | CmdType::Exit => Category::Quit,
+            CmdType::Write | CmdType::CommandLine | CmdType::Help => Category::IO,
         }
     }
 }
 
+/// A command which acts on the nodes chosen by a motion
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Operator {
+    /// Delete the nodes, storing them in the yank register
+    Delete,
+    /// Store the nodes in the yank register, without changing the tree
+    Yank,
+}
+
+impl Operator {
+    /// Returns the lower-case verb for this `Operator`
+    pub fn verb(&self) -> &'static str {
+        match self {
+            Operator::Delete => "delete",
+            Operator::Yank => "yank",
+        }
+    }
+}
+
+/// A way of scrolling the view of the focused pane
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Scroll {
+    /// Scroll half a screen down ([`Side::Next`]) or up ([`Side::Prev`]), moving the cursor by
+    /// the same number of lines
+    HalfPage(Side),
+    /// Scroll one line down ([`Side::Next`]) or up ([`Side::Prev`]) per count, moving the cursor
+    /// by the same number of lines
+    Lines(Side),
+    /// Scroll so that the cursor is at the top of the screen
+    CursorToTop,
+    /// Scroll so that the cursor is in the middle of the screen
+    CursorToMiddle,
+    /// Scroll so that the cursor is at the bottom of the screen
+    CursorToBottom,
+}
+
+impl Scroll {
+    /// Returns a lower-case description of this `Scroll`
+    pub fn description(&self) -> &'static str {
+        match self {
+            Scroll::HalfPage(Side::Prev) => "scroll half a page up",
+            Scroll::HalfPage(Side::Next) => "scroll half a page down",
+            Scroll::Lines(Side::Prev) => "scroll up",
+            Scroll::Lines(Side::Next) => "scroll down",
+            Scroll::CursorToTop => "scroll cursor to top",
+            Scroll::CursorToMiddle => "scroll cursor to middle",
+            Scroll::CursorToBottom => "scroll cursor to bottom",
+        }
+    }
+}
+
+/// Returns a lower-case name for the node that a motion in a given [`Direction`] moves to
+fn motion_target_name(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Up => "parent",
+        Direction::Down => "child",
+        Direction::Prev => "previous sibling",
+        Direction::Next => "next sibling",
+        Direction::FirstSibling => "first sibling",
+        Direction::LastSibling => "last sibling",
+        Direction::Root => "root",
+        Direction::PreorderNext => "next node",
+        Direction::PreorderPrev => "previous node",
+        Direction::NextLeaf => "next leaf",
+        Direction::PrevLeaf => "previous leaf",
+        Direction::NextOfClass => "next node of same type",
+        Direction::PrevOfClass => "previous node of same type",
+        Direction::NthChild => "nth child",
+    }
+}
+
 /// The [`Action`] generated by a single normal-mode 'command'.
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub enum Action {
@@ -177,16 +654,61 @@ pub enum Action {
     InsertAfter(Insertable),
     /// Remove the node under the cursor
     Delete,
+    /// Move the node under the cursor past some of its siblings
+    MoveNode(Side),
+    /// Move the node under the cursor into the adjacent sibling on a given [`Side`]
+    Slurp(Side),
+    /// Move the node under the cursor out of its parent, to a given [`Side`] of that parent
+    Barf(Side),
     /// Move the node in a given direction
     MoveCursor(Direction),
+    /// Apply an [`Operator`] to the nodes chosen by a motion in a given [`Direction`], or to the
+    /// cursor (and its next siblings, if a count is given) if there's no motion
+    Operate(Operator, Option<Direction>),
+    /// Insert the nodes from the yank register after the cursor
+    Put,
+    /// Enter search mode to type a new search pattern
+    Search,
+    /// Enter command mode to type a command
+    CommandLine,
+    /// Move the cursor to the next or previous node matching the current search pattern
+    NextMatch(Side),
+    /// Mark the node under the cursor with the name given by a [`char`]
+    SetMark(char),
+    /// Move the cursor to the node marked with the name given by a [`char`]
+    MoveToMark(char),
+    /// Move the cursor to an older ([`Side::Prev`]) or newer ([`Side::Next`]) location in the
+    /// jump list
+    Jump(Side),
+    /// Enter search mode to type a pattern and a replacement for every node that matches it
+    SearchReplace,
+    /// Scroll the view of the focused pane
+    Scroll(Scroll),
+    /// Open or close some folds in the tree
+    Fold(Fold),
+    /// Start recording keystrokes into the macro register given by a [`char`]
+    RecordMacro(char),
+    /// Stop recording the current macro
+    StopRecording,
+    /// Play back the keystrokes stored in the macro register given by a [`char`]
+    PlayMacro(char),
+    /// Perform the last edit again at the cursor.  If a count is given, it replaces the count that
+    /// the edit was originally performed with.
+    Repeat(Option<usize>),
     /// Undo the last change
     Undo,
     /// Redo a change
     Redo,
-    /// Quit Sapling
+    /// Quit Sapling, unless there are unsaved changes
     Quit,
+    /// Quit Sapling, throwing away any unsaved changes
+    ForceQuit,
+    /// Write the current buffer to disk if it has unsaved changes, then quit Sapling
+    WriteAndQuit,
     /// Write current buffer to disk
     Write,
+    /// Show the help, or hide it if it's already shown
+    Help,
 }
 
 impl Action {
@@ -199,31 +721,157 @@ impl Action {
             Action::InsertBefore(c) => format!("insert '{}' before cursor", c),
             Action::InsertAfter(c) => format!("insert '{}' after cursor", c),
             Action::Delete => "delete cursor".to_string(),
-            Action::MoveCursor(Direction::Down) => "move to child".to_string(),
-            Action::MoveCursor(Direction::Up) => "move to parent".to_string(),
-            Action::MoveCursor(Direction::Prev) => "move to previous sibling".to_string(),
-            Action::MoveCursor(Direction::Next) => "move to next sibling".to_string(),
+            Action::MoveNode(Side::Prev) => "move cursor node back".to_string(),
+            Action::MoveNode(Side::Next) => "move cursor node forward".to_string(),
+            Action::Slurp(Side::Prev) => "move cursor into previous sibling".to_string(),
+            Action::Slurp(Side::Next) => "move cursor into next sibling".to_string(),
+            Action::Barf(Side::Prev) => "move cursor out before parent".to_string(),
+            Action::Barf(Side::Next) => "move cursor out after parent".to_string(),
+            Action::MoveCursor(direction) => format!("move to {}", motion_target_name(*direction)),
+            Action::Operate(operator, None) => format!("{} cursor", operator.verb()),
+            Action::Operate(
+                operator,
+                Some(
+                    direction @ (Direction::Prev
+                    | Direction::Next
+                    | Direction::FirstSibling
+                    | Direction::LastSibling),
+                ),
+            ) => format!("{} to {}", operator.verb(), motion_target_name(*direction)),
+            Action::Operate(operator, Some(direction)) => {
+                format!("{} {}", operator.verb(), motion_target_name(*direction))
+            }
+            Action::Put => "put after cursor".to_string(),
+            Action::Search => "start search".to_string(),
+            Action::CommandLine => "start command".to_string(),
+            Action::NextMatch(Side::Prev) => "move to previous match".to_string(),
+            Action::NextMatch(Side::Next) => "move to next match".to_string(),
+            Action::SetMark(name) => format!("set mark '{}'", name),
+            Action::MoveToMark(name) => format!("move to mark '{}'", name),
+            Action::Jump(Side::Prev) => "jump to older location".to_string(),
+            Action::Jump(Side::Next) => "jump to newer location".to_string(),
+            Action::SearchReplace => "start search and replace".to_string(),
+            Action::Scroll(scroll) => scroll.description().to_string(),
+            Action::Fold(fold) => fold.description().to_string(),
+            Action::RecordMacro(register) => format!("record macro into '{}'", register),
+            Action::StopRecording => "stop recording macro".to_string(),
+            Action::PlayMacro(register) => format!("play macro '{}'", register),
+            Action::Repeat(_) => "repeat last edit".to_string(),
             Action::Undo => "undo a change".to_string(),
             Action::Redo => "redo a change".to_string(),
             Action::Quit => "quit Sapling".to_string(),
+            Action::ForceQuit => "quit Sapling without saving".to_string(),
+            Action::WriteAndQuit => "write and quit Sapling".to_string(),
             Action::Write => "write to disk".to_string(),
+            Action::Help => "toggle help".to_string(),
         }
     }
 
     /// Returns the [`Category`] of this `Action`
     pub fn category(&self) -> Category {
         match self {
-            Action::Replace(_) => Category::Replace,
-            Action::InsertChild(_) | Action::InsertBefore(_) | Action::InsertAfter(_) => {
-                Category::Insert
+            Action::Replace(_) | Action::SearchReplace => Category::Replace,
+            Action::InsertChild(_)
+            | Action::InsertBefore(_)
+            | Action::InsertAfter(_)
+            | Action::Put => Category::Insert,
+            Action::Delete | Action::Operate(Operator::Delete, _) => Category::Delete,
+            Action::Operate(Operator::Yank, _) => Category::Yank,
+            Action::MoveNode(_) | Action::Slurp(_) | Action::Barf(_) => Category::Rearrange,
+            Action::MoveCursor(_)
+            | Action::Search
+            | Action::NextMatch(_)
+            | Action::SetMark(_)
+            | Action::MoveToMark(_)
+            | Action::Jump(_)
+            | Action::Scroll(_)
+            | Action::Fold(_) => Category::Move,
+            Action::Undo | Action::Redo | Action::Repeat(_) => Category::History,
+            Action::RecordMacro(_) | Action::StopRecording | Action::PlayMacro(_) => {
+                Category::Macro
             }
-            Action::Delete => Category::Delete,
-            Action::MoveCursor(_) => Category::Move,
-            Action::Undo | Action::Redo => Category::History,
-            Action::Quit => Category::Quit,
-            Action::Write => Category::IO,
+            Action::Quit | Action::ForceQuit | Action::WriteAndQuit => Category::Quit,
+            Action::Write | Action::CommandLine | Action::Help => Category::IO,
         }
     }
+
+    /// Returns `true` if this `Action` modifies the tree, and so can be repeated with
+    /// [`Action::Repeat`]
+    pub fn is_edit(&self) -> bool {
+        matches!(
+            self,
+            Action::Replace(_)
+                | Action::InsertChild(_)
+                | Action::InsertBefore(_)
+                | Action::InsertAfter(_)
+                | Action::Delete
+                | Action::Operate(Operator::Delete, _)
+                | Action::Put
+                | Action::MoveNode(_)
+                | Action::Slurp(_)
+                | Action::Barf(_)
+        )
+    }
+}
+
+/// Turns key sequences and what they do into [`Hint`]s sorted by description, where keys which do
+/// the same thing share a [`Hint`]
+fn group_hints(keys: impl Iterator<Item = (Vec<KeyEvent>, String)>) -> Vec<Hint> {
+    let mut keys_by_description: HashMap<String, Vec<String>> = HashMap::new();
+    for (keys, description) in keys {
+        let key = keystrokes_to_string(&keys);
+        keys_by_description
+            .entry(description)
+            .or_default()
+            .push(key);
+    }
+    let mut hints: Vec<Hint> = keys_by_description
+        .into_iter()
+        .map(|(description, mut keys)| {
+            keys.sort();
+            Hint::new(keys.join(" "), description)
+        })
+        .collect();
+    hints.sort_by(|a, b| a.description.cmp(&b.description));
+    hints
+}
+
+/// Returns the keys at the end of `keys` which start a binding in `keymap` without finishing it
+/// (e.g. the `g` of `3dg`), skipping over counts and any bindings which are finished
+fn unfinished_binding<'k>(keymap: &KeyMap, keys: &'k [KeyEvent]) -> &'k [KeyEvent] {
+    let mut start = 0;
+    let mut bindings = keymap;
+    for (i, key) in keys.iter().enumerate() {
+        let is_count = i == start && plain_char(*key).is_some_and(|c| c.is_ascii_digit());
+        match bindings.after(*key) {
+            Some(next) if !is_count && next.cmd().is_none() => bindings = next,
+            _ => {
+                start = i + 1;
+                bindings = keymap;
+            }
+        }
+    }
+    &keys[start..]
+}
+
+/// Returns `true` if `cmd` could insert a node of a given class at the cursor of `tree` (e.g.
+/// [`CmdType::InsertBefore`] can't insert a node before the root)
+fn can_insert<'arena, Node: Ast<'arena>>(
+    tree: &Dag<'arena, Node>,
+    cmd: CmdType,
+    class: Node::Class,
+) -> bool {
+    let (cursor, parent) = tree.cursor_and_parent();
+    let parent_and_index = parent.zip(tree.cursor_path().last());
+    match (cmd, parent_and_index) {
+        (CmdType::InsertChild, _) => cursor.is_valid_child(cursor.children().len(), class),
+        (CmdType::InsertBefore, Some((parent, index))) => parent.is_valid_child(index, class),
+        (CmdType::InsertAfter, Some((parent, index))) => parent.is_valid_child(index + 1, class),
+        (CmdType::Replace, Some((parent, index))) => parent.is_valid_child(index, class),
+        (CmdType::Replace, None) => cursor.is_valid_root(class),
+        // The root can't have siblings
+        _ => false,
+    }
 }
 
 type ParseResult<T> = Result<T, ParseErr>;
@@ -232,102 +880,268 @@ type ParseResult<T> = Result<T, ParseErr>;
 #[derive(Debug, Clone, Eq, PartialEq)]
 enum ParseErr {
     Invalid,
-    Incomplete,
+    /// The keys are the start of a command, which needs an [`Expected`] thing to be typed next
+    Incomplete(Expected),
+}
+
+/// What has to be typed next to finish an incomplete command
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Expected {
+    /// The name of a command, or more digits of the count before it
+    Command,
+    /// The type of the node(s) that a [`CmdType`] inserts, optionally after a count
+    Node(CmdType),
+    /// The name of the register or mark used by a [`CmdType`]
+    Register(CmdType),
+    /// The motion which an [`Operator`] acts on, optionally after a count
+    Motion(Operator),
+    /// One of the keys which can follow a [`CmdType`] such as [`CmdType::View`]
+    SubCommand(CmdType),
 }
 
 /// Attempt to parse an entire command.  This is the entry point to the parsing code.  This parser
 /// is a recursive descent parser, where there is a separate function for each syntactic element
 /// ([`parse_insertable`], [`parse_count`], etc.).
 ///
+/// `is_recording` should be `true` if a macro is being recorded, in which case the
+/// [`CmdType::RecordMacro`] key stops the recording rather than expecting a register.
+///
 /// Note that this parser will return as soon as a valid command is reached.  Therefore,
 /// `"q489flshb"` will be treated like `"q"`, and will return [`Action::Quit`] even though
 /// `"q489flshb"` is not technically valid.  However, the command buffer is parsed every time the
 /// user types a keystroke character, so the user would not be able to input `"q489flshb"` in one
 /// go because doing so would require them to first input every possible prefix of `"q489flshb"`,
 /// including `"q"`.
-fn parse_command(keymap: &KeyMap, keys: &[KeyEvent]) -> ParseResult<(usize, Action)> {
+fn parse_command(
+    keymap: &KeyMap,
+    keys: &[KeyEvent],
+    is_recording: bool,
+) -> ParseResult<(usize, Action)> {
     // Generate an iterator of keystrokes, which are treated similar to tokens by the parser.
-    let mut key_iter = keys.iter().map(|ev| ev.code).peekable();
-
-    // Parse a count off the front of the command
-    let count = parse_count(&mut key_iter);
-    // The first non-count keystroke represents the command name.  No keystrokes is an incomplete
-    // command.
-    let first_key = key_iter.next().ok_or(ParseErr::Incomplete)?;
-
-    Ok((
-        count,
-        match keymap.get(&first_key).ok_or(ParseErr::Invalid)? {
-            CmdType::InsertChild => Action::InsertChild(parse_insertable(&mut key_iter)?),
-            CmdType::InsertBefore => Action::InsertBefore(parse_insertable(&mut key_iter)?),
-            CmdType::InsertAfter => Action::InsertAfter(parse_insertable(&mut key_iter)?),
-            CmdType::Delete => Action::Delete,
-            CmdType::Replace => Action::Replace(parse_insertable(&mut key_iter)?),
-            CmdType::MoveCursor(direction) => Action::MoveCursor(*direction),
-            CmdType::Undo => Action::Undo,
-            CmdType::Redo => Action::Redo,
-            // "q" quits Sapling
-            CmdType::Quit => Action::Quit,
-            CmdType::Write => Action::Write,
-        },
-    ))
+    let mut key_iter = keys.iter().copied().peekable();
+
+    // Parse a count off the front of the command.  Only the repeat command cares whether or not
+    // the count was typed explicitly
+    let explicit_count = parse_explicit_count(&mut key_iter);
+    let mut count = explicit_count.unwrap_or(1);
+    // The keystrokes after the count represent the command name.  Running out of keystrokes
+    // before a command is bound is an incomplete command.
+    let cmd = parse_cmd_type(keymap, &mut key_iter, Expected::Command)?;
+    let action = match cmd {
+        CmdType::InsertChild => Action::InsertChild(parse_insertable(&mut key_iter, cmd)?),
+        CmdType::InsertBefore => Action::InsertBefore(parse_insertable(&mut key_iter, cmd)?),
+        CmdType::InsertAfter => Action::InsertAfter(parse_insertable(&mut key_iter, cmd)?),
+        CmdType::Delete => Action::Delete,
+        CmdType::MoveNode(side) => Action::MoveNode(side),
+        CmdType::Slurp(side) => Action::Slurp(side),
+        CmdType::Barf(side) => Action::Barf(side),
+        CmdType::Replace => Action::Replace(parse_insertable(&mut key_iter, cmd)?),
+        CmdType::Motion(direction) => Action::MoveCursor(direction),
+        CmdType::Operator(operator) => {
+            let (motion_count, motion) = parse_motion(keymap, &mut key_iter, operator)?;
+            // As in Vim, the count before the operator and the count before the motion are
+            // multiplied together.  Huge counts saturate rather than overflowing.
+            count = count.saturating_mul(motion_count);
+            Action::Operate(operator, motion)
+        }
+        CmdType::Put => Action::Put,
+        CmdType::Search => Action::Search,
+        CmdType::CommandLine => Action::CommandLine,
+        CmdType::NextMatch(side) => Action::NextMatch(side),
+        CmdType::SetMark => Action::SetMark(parse_register(&mut key_iter, cmd)?),
+        CmdType::MoveToMark => Action::MoveToMark(parse_register(&mut key_iter, cmd)?),
+        CmdType::Jump(side) => Action::Jump(side),
+        CmdType::HalfPage(side) => Action::Scroll(Scroll::HalfPage(side)),
+        CmdType::View => parse_sub_command(&mut key_iter, cmd)?,
+        CmdType::SearchReplace => Action::SearchReplace,
+        CmdType::RecordMacro if is_recording => Action::StopRecording,
+        CmdType::RecordMacro => Action::RecordMacro(parse_register(&mut key_iter, cmd)?),
+        CmdType::PlayMacro => Action::PlayMacro(parse_register(&mut key_iter, cmd)?),
+        CmdType::Repeat => Action::Repeat(explicit_count),
+        CmdType::Undo => Action::Undo,
+        CmdType::Redo => Action::Redo,
+        // "q" quits Sapling
+        CmdType::Quit => Action::Quit,
+        CmdType::Write => Action::Write,
+        CmdType::Help => Action::Help,
+        CmdType::Exit => parse_sub_command(&mut key_iter, cmd)?,
+    };
+    Ok((count, action))
+}
+
+/// Attempt to parse the keys of a [`CmdType`], by following them through the trie of `keymap`
+/// until they reach a binding.  If the keys run out first, then the command is incomplete and
+/// `expected` is what still has to be typed.
+fn parse_cmd_type(
+    keymap: &KeyMap,
+    keystroke_iter: &mut Peekable<impl Iterator<Item = KeyEvent>>,
+    expected: Expected,
+) -> ParseResult<CmdType> {
+    let mut bindings = keymap;
+    loop {
+        let key = keystroke_iter
+            .next()
+            .ok_or(ParseErr::Incomplete(expected))?;
+        bindings = bindings.after(key).ok_or(ParseErr::Invalid)?;
+        if let Some(cmd) = bindings.cmd() {
+            return Ok(cmd);
+        }
+    }
+}
+
+/// Attempt to parse the motion after an operator, which is a count followed by either a
+/// [`CmdType::Motion`] or the keys of the operator itself.  Repeating the operator makes it act
+/// on the cursor, which is represented by a motion of `None`.
+fn parse_motion(
+    keymap: &KeyMap,
+    keystroke_iter: &mut Peekable<impl Iterator<Item = KeyEvent>>,
+    operator: Operator,
+) -> ParseResult<(usize, Option<Direction>)> {
+    let count = parse_count(keystroke_iter);
+    match parse_cmd_type(keymap, keystroke_iter, Expected::Motion(operator))? {
+        CmdType::Motion(direction) => Ok((count, Some(direction))),
+        CmdType::Operator(op) if op == operator => Ok((count, None)),
+        _ => Err(ParseErr::Invalid),
+    }
+}
+
+/// The keys which can follow [`CmdType::View`], which choose how the view should change.  As in
+/// Vim, `z` centres the cursor, whilst `t` and `b` move it to the top and bottom.  The other keys
+/// change the folds, also as in Vim: `c`, `o` and `a` close, open and toggle the cursor's fold
+/// (recursively when capitalised), `M` folds to a depth and `R` opens every fold.
+const VIEW_COMMANDS: [(char, Action); 11] = [
+    ('z', Action::Scroll(Scroll::CursorToMiddle)),
+    ('t', Action::Scroll(Scroll::CursorToTop)),
+    ('b', Action::Scroll(Scroll::CursorToBottom)),
+    ('c', Action::Fold(Fold::Close)),
+    ('o', Action::Fold(Fold::Open)),
+    ('a', Action::Fold(Fold::Toggle)),
+    ('C', Action::Fold(Fold::CloseRecursive)),
+    ('O', Action::Fold(Fold::OpenRecursive)),
+    ('A', Action::Fold(Fold::ToggleRecursive)),
+    ('M', Action::Fold(Fold::ToDepth)),
+    ('R', Action::Fold(Fold::OpenAll)),
+];
+
+/// The keys which can follow [`CmdType::Exit`].  As in Vim, `Z` writes the current buffer (if it
+/// has unsaved changes) before quitting and `Q` quits without saving anything.
+const EXIT_COMMANDS: [(char, Action); 2] = [('Z', Action::WriteAndQuit), ('Q', Action::ForceQuit)];
+
+/// Returns the keys which can follow a [`CmdType`] that expects a sub-command, along with the
+/// [`Action`]s that they perform
+fn sub_commands(cmd: CmdType) -> &'static [(char, Action)] {
+    match cmd {
+        CmdType::View => &VIEW_COMMANDS,
+        CmdType::Exit => &EXIT_COMMANDS,
+        _ => &[],
+    }
 }
 
-/// Attempt to parse a sequence of [`KeyCode`]strokes into an [`Insertable`].
+/// Attempt to parse the key after a [`CmdType`] that expects a sub-command, which must be one of
+/// its [`sub_commands`]
+fn parse_sub_command(
+    keystroke_iter: &mut Peekable<impl Iterator<Item = KeyEvent>>,
+    cmd: CmdType,
+) -> ParseResult<Action> {
+    let key = keystroke_iter
+        .next()
+        .ok_or(ParseErr::Incomplete(Expected::SubCommand(cmd)))?;
+    let c = plain_char(key).ok_or(ParseErr::Invalid)?;
+    sub_commands(cmd)
+        .iter()
+        .find(|(sub_command, _)| *sub_command == c)
+        .map(|(_, action)| *action)
+        .ok_or(ParseErr::Invalid)
+}
+
+/// Attempt to parse a sequence of [`KeyEvent`]s into an [`Insertable`] for the command `cmd`.
 ///
 /// Currently an [`Insertable`] only has one form ([`Insertable::CountedNode`]), and so this is a
 /// simple matter of attempting to parse a count and then taking one char of the keystroke.
 fn parse_insertable(
-    keystroke_char_iter: &mut Peekable<impl Iterator<Item = KeyCode>>,
+    keystroke_iter: &mut Peekable<impl Iterator<Item = KeyEvent>>,
+    cmd: CmdType,
 ) -> ParseResult<Insertable> {
     // Parse a count before reading the char
-    let count = parse_count(keystroke_char_iter);
+    let count = parse_count(keystroke_iter);
     // Consume the next key or return incompleteness
-    let key = keystroke_char_iter.next().ok_or(ParseErr::Incomplete)?;
+    let key = keystroke_iter
+        .next()
+        .ok_or(ParseErr::Incomplete(Expected::Node(cmd)))?;
     // If the next keystroke is a `char`, then return it with success otherwise the command is
     // invalid
-    if let KeyCode::Char(c) = key {
+    if let Some(c) = plain_char(key) {
         Ok(Insertable::CountedNode(count, c))
     } else {
         Err(ParseErr::Invalid)
     }
 }
 
-/// Parse a 'count' off the front of an sequence of [`KeyCode`]strokes.  This cannot fail, because if
-/// the first [`KeyCode`] is not a numeral, this returns `1`.
-fn parse_count(keystroke_char_iter: &mut Peekable<impl Iterator<Item = KeyCode>>) -> usize {
+/// Attempt to parse the name of a register or mark (which can be any [`char`]) for the command
+/// `cmd` from a sequence of [`KeyEvent`]s.
+fn parse_register(
+    keystroke_iter: &mut Peekable<impl Iterator<Item = KeyEvent>>,
+    cmd: CmdType,
+) -> ParseResult<char> {
+    let key = keystroke_iter
+        .next()
+        .ok_or(ParseErr::Incomplete(Expected::Register(cmd)))?;
+    plain_char(key).ok_or(ParseErr::Invalid)
+}
+
+/// Parse a 'count' off the front of an sequence of [`KeyEvent`]s.  This cannot fail, because if
+/// the first [`KeyEvent`] is not a numeral, this returns `1`.
+fn parse_count(keystroke_iter: &mut Peekable<impl Iterator<Item = KeyEvent>>) -> usize {
+    parse_explicit_count(keystroke_iter).unwrap_or(1)
+}
+
+/// Parse a 'count' off the front of an sequence of [`KeyEvent`]s, returning `None` if the first
+/// [`KeyEvent`] is not a numeral.
+fn parse_explicit_count(
+    keystroke_iter: &mut Peekable<impl Iterator<Item = KeyEvent>>,
+) -> Option<usize> {
     // accumulated_count tracks the number that is represented by the keystrokes already consumed
     // or None if no numbers have been consumed
     let mut accumulated_count: Option<usize> = None;
-    loop {
-        let new_digit = match keystroke_char_iter.peek() {
-            Some(KeyCode::Char('0')) => 0,
-            Some(KeyCode::Char('1')) => 1,
-            Some(KeyCode::Char('2')) => 2,
-            Some(KeyCode::Char('3')) => 3,
-            Some(KeyCode::Char('4')) => 4,
-            Some(KeyCode::Char('5')) => 5,
-            Some(KeyCode::Char('6')) => 6,
-            Some(KeyCode::Char('7')) => 7,
-            Some(KeyCode::Char('8')) => 8,
-            Some(KeyCode::Char('9')) => 9,
-            _ => break,
-        };
+    while let Some(new_digit) = keystroke_iter
+        .peek()
+        .and_then(|key| plain_char(*key)?.to_digit(10))
+    {
+        let new_digit = new_digit as usize;
         // Pop the digit.  We use lookahead so that we leave the future keystrokes untouched for
         // the next parsing.
-        keystroke_char_iter.next();
-        // Since we read a new digit, we accumulate it to the count
-        accumulated_count = Some(accumulated_count.map_or(new_digit, |x| x * 10 + new_digit));
+        keystroke_iter.next();
+        // Since we read a new digit, we accumulate it to the count (saturating, so that typing
+        // too many digits can't overflow)
+        accumulated_count = Some(accumulated_count.map_or(new_digit, |x| {
+            x.saturating_mul(10).saturating_add(new_digit)
+        }));
+    }
+    accumulated_count
+}
+
+/// Returns the character typed by a keystroke, or `None` if it isn't a character or if it was
+/// typed with modifiers (other than shift, which is already part of the character)
+fn plain_char(key: KeyEvent) -> Option<char> {
+    match key.code {
+        KeyCode::Char(c) if (key.modifiers - KeyModifiers::SHIFT).is_empty() => Some(c),
+        _ => None,
     }
-    accumulated_count.unwrap_or(1)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{parse_command, Action, Insertable, ParseErr};
-    use crate::config::default_keymap;
-    use crate::core::Direction;
+    use super::{
+        can_insert, parse_command, Action, CmdType, Expected, Insertable, Operator, ParseErr,
+        Scroll, State, VIEW_COMMANDS,
+    };
+    use crate::arena::Arena;
+    use crate::ast::json::{add_value_to_arena, Class, Json, JsonFormat};
+    use crate::config::{default_keymap, Config};
+    use crate::core::{parse_keys, Direction, Fold, Path, Side};
+    use crate::editor::{buffer::Buffer, dag::Dag, state, widgets::Hint, Editor};
     use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use serde_json::json;
 
     fn to_char_keys(string: &str) -> Vec<KeyEvent> {
         string
@@ -339,16 +1153,61 @@ mod tests {
             .collect::<Vec<_>>()
     }
 
+    /// Creates a headless [`Editor`] editing `tree`, with the cursor at `cursor`
+    fn editor<'arena>(
+        arena: &'arena Arena<Json<'arena>>,
+        tree: serde_json::Value,
+        cursor: Vec<usize>,
+    ) -> Editor<'arena, Json<'arena>> {
+        let root = add_value_to_arena(tree, arena);
+        let tree = Dag::new(arena, root, Path::from_vec(cursor));
+        let buffer = Buffer::new(tree, None, JsonFormat::Compact);
+        Editor::headless(buffer, Config::default())
+    }
+
+    /// Executes `action` in normal mode, returning the [`State`](state::State) that Sapling
+    /// enters afterwards
+    fn execute<'arena>(
+        editor: &mut Editor<'arena, Json<'arena>>,
+        count: usize,
+        action: Action,
+    ) -> Box<dyn state::State<'arena, Json<'arena>>> {
+        Box::new(State::default()).execute_action(editor, count, action)
+    }
+
+    #[test]
+    fn cmd_type_names() {
+        for cmd in CmdType::ALL.iter() {
+            assert_eq!(CmdType::from_name(cmd.name()), Some(*cmd));
+        }
+        // Every command in the keymap can be named in the config file
+        for (_, cmd) in default_keymap().bindings() {
+            assert!(CmdType::ALL.contains(&cmd), "{:?} is missing", cmd);
+        }
+        assert_eq!(CmdType::from_name("move to child"), None);
+    }
+
     #[test]
     fn parse_single_cmd_valid() {
         let keymap = default_keymap();
         for (keystrokes, expected_effect) in &[
             ("x", Action::Delete),
+            ("?", Action::Help),
             ("h", Action::MoveCursor(Direction::Prev)),
             ("j", Action::MoveCursor(Direction::Next)),
             ("k", Action::MoveCursor(Direction::Prev)),
             ("l", Action::MoveCursor(Direction::Next)),
             ("pajlbsi", Action::MoveCursor(Direction::Up)),
+            ("H", Action::MoveCursor(Direction::FirstSibling)),
+            ("L", Action::MoveCursor(Direction::LastSibling)),
+            ("^", Action::MoveCursor(Direction::Root)),
+            ("J", Action::MoveCursor(Direction::PreorderNext)),
+            ("K", Action::MoveCursor(Direction::PreorderPrev)),
+            ("]", Action::MoveCursor(Direction::NextLeaf)),
+            ("[", Action::MoveCursor(Direction::PrevLeaf)),
+            ("*", Action::MoveCursor(Direction::NextOfClass)),
+            ("#", Action::MoveCursor(Direction::PrevOfClass)),
+            ("C", Action::MoveCursor(Direction::NthChild)),
             ("ra", Action::Replace(Insertable::CountedNode(1, 'a'))),
             ("rg", Action::Replace(Insertable::CountedNode(1, 'g'))),
             ("oX", Action::InsertChild(Insertable::CountedNode(1, 'X'))),
@@ -363,9 +1222,42 @@ mod tests {
                 Action::InsertAfter(Insertable::CountedNode(15, 'x')),
             ),
             ("q", Action::Quit),
+            ("<", Action::MoveNode(Side::Prev)),
+            (">", Action::MoveNode(Side::Next)),
+            ("{", Action::Slurp(Side::Prev)),
+            ("}", Action::Slurp(Side::Next)),
+            ("(", Action::Barf(Side::Prev)),
+            (")", Action::Barf(Side::Next)),
+            ("/", Action::Search),
+            (":", Action::CommandLine),
+            ("n", Action::NextMatch(Side::Next)),
+            ("N", Action::NextMatch(Side::Prev)),
+            ("s", Action::SearchReplace),
+            ("Qa", Action::RecordMacro('a')),
+            ("@q", Action::PlayMacro('q')),
+            (".", Action::Repeat(None)),
+            ("dd", Action::Operate(Operator::Delete, None)),
+            (
+                "dL",
+                Action::Operate(Operator::Delete, Some(Direction::LastSibling)),
+            ),
+            ("yp", Action::Operate(Operator::Yank, Some(Direction::Up))),
+            ("yy", Action::Operate(Operator::Yank, None)),
+            ("P", Action::Put),
+            ("ma", Action::SetMark('a')),
+            ("'a", Action::MoveToMark('a')),
+            ("U", Action::Scroll(Scroll::HalfPage(Side::Prev))),
+            ("zz", Action::Scroll(Scroll::CursorToMiddle)),
+            ("zt", Action::Scroll(Scroll::CursorToTop)),
+            ("zb", Action::Scroll(Scroll::CursorToBottom)),
+            ("zc", Action::Fold(Fold::Close)),
+            ("zA", Action::Fold(Fold::ToggleRecursive)),
+            ("zR", Action::Fold(Fold::OpenAll)),
+            ("ZZ", Action::WriteAndQuit),
+            ("ZQ", Action::ForceQuit),
         ] {
             assert_eq!(
-                parse_command(&keymap, &to_char_keys(keystrokes)),
+                parse_command(&keymap, &to_char_keys(keystrokes), false),
                 Ok((1, *expected_effect))
             );
         }
@@ -382,21 +1274,70 @@ mod tests {
                 12,
                 Action::InsertChild(Insertable::CountedNode(5, 'p')),
             ),
+            ("3.", 3, Action::Repeat(Some(3))),
+            ("4C", 4, Action::MoveCursor(Direction::NthChild)),
+            ("2]", 2, Action::MoveCursor(Direction::NextLeaf)),
+            // The counts before an operator and its motion are multiplied
+            (
+                "2d3j",
+                6,
+                Action::Operate(Operator::Delete, Some(Direction::Next)),
+            ),
+            (
+                "d4k",
+                4,
+                Action::Operate(Operator::Delete, Some(Direction::Prev)),
+            ),
+            ("3yy", 3, Action::Operate(Operator::Yank, None)),
+            ("2D", 2, Action::Scroll(Scroll::HalfPage(Side::Next))),
+            ("3zM", 3, Action::Fold(Fold::ToDepth)),
+            // Counts which are too big to represent saturate instead of overflowing
+            ("99999999999999999999999x", usize::MAX, Action::Delete),
+            (
+                "99999999999d99999999999j",
+                usize::MAX,
+                Action::Operate(Operator::Delete, Some(Direction::Next)),
+            ),
         ] {
             assert_eq!(
-                parse_command(&keymap, &to_char_keys(keystrokes)),
+                parse_command(&keymap, &to_char_keys(keystrokes), false),
                 Ok((*exp_count, *exp_action))
             );
         }
     }
 
+    #[test]
+    fn parse_while_recording() {
+        let keymap = default_keymap();
+        for (keystrokes, expected_action) in &[
+            ("Q", Action::StopRecording),
+            ("x", Action::Delete),
+            ("@a", Action::PlayMacro('a')),
+        ] {
+            assert_eq!(
+                parse_command(&keymap, &to_char_keys(keystrokes), true),
+                Ok((1, *expected_action))
+            );
+        }
+    }
+
     #[test]
     fn parse_keystroke_invalid() {
         let keymap = default_keymap();
-        for keystroke in &["d", "Pxx", "Qsx", "t", "Y", "X", "\""] {
+        for keystroke in &[
+            "dy", "yd", "dx", "y0r", "t", "Y", "X", "\"", "zx", "z3", "Zq",
+        ] {
+            println!("Testing {}", keystroke);
+            assert_eq!(
+                parse_command(&keymap, &to_char_keys(keystroke), false),
+                Err(ParseErr::Invalid)
+            );
+        }
+        // Registers have to be plain characters
+        for keystroke in &["Q ^s", "@ <Esc>", "m <A-a>"] {
             println!("Testing {}", keystroke);
             assert_eq!(
-                parse_command(&keymap, &to_char_keys(keystroke)),
+                parse_command(&keymap, &parse_keys(keystroke).unwrap(), false),
                 Err(ParseErr::Invalid)
             );
         }
@@ -406,13 +1347,244 @@ mod tests {
     fn parse_keystroke_incomplete() {
         let keymap = default_keymap();
         for keystroke in &[
-            "", "r", "o", "a", "i", "o3", "i34", "3", "1o", "0o3", "41523",
+            "", "r", "o", "a", "i", "o3", "i34", "3", "1o", "0o3", "41523", "Q", "3@", "m", "'",
+            "d", "y3", "3d2", "z", "Z",
         ] {
             println!("Testing {}", keystroke);
+            assert!(matches!(
+                parse_command(&keymap, &to_char_keys(keystroke), false),
+                Err(ParseErr::Incomplete(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn parse_expected() {
+        let keymap = default_keymap();
+        for (keystrokes, expected) in &[
+            ("", Expected::Command),
+            ("41", Expected::Command),
+            ("o3", Expected::Node(CmdType::InsertChild)),
+            ("2r", Expected::Node(CmdType::Replace)),
+            ("m", Expected::Register(CmdType::SetMark)),
+            ("@", Expected::Register(CmdType::PlayMacro)),
+            ("d2", Expected::Motion(Operator::Delete)),
+            ("z", Expected::SubCommand(CmdType::View)),
+            ("Z", Expected::SubCommand(CmdType::Exit)),
+            // Keys which are the start of a binding
+            ("g", Expected::Command),
+            ("3g", Expected::Command),
+            ("dg", Expected::Motion(Operator::Delete)),
+        ] {
             assert_eq!(
-                parse_command(&keymap, &to_char_keys(keystroke)),
-                Err(ParseErr::Incomplete)
+                parse_command(&keymap, &to_char_keys(keystrokes), false),
+                Err(ParseErr::Incomplete(*expected))
             );
         }
     }
+
+    #[test]
+    fn continuations() {
+        let keymap = default_keymap();
+        let state = State {
+            keystroke_buffer: to_char_keys("Z"),
+        };
+        assert_eq!(
+            state.continuations(&keymap, Expected::SubCommand(CmdType::Exit)),
+            vec![
+                Hint::new("Q", "quit Sapling without saving"),
+                Hint::new("Z", "write and quit Sapling"),
+            ]
+        );
+        // Every key of a sub-command parses to the action of its hint
+        let hints = state.continuations(&keymap, Expected::SubCommand(CmdType::View));
+        assert_eq!(hints.len(), 11);
+        for (c, action) in &VIEW_COMMANDS {
+            let keys = to_char_keys(&format!("z{}", c));
+            assert_eq!(parse_command(&keymap, &keys, false), Ok((1, *action)));
+            assert!(hints.contains(&Hint::new(c.to_string(), action.description())));
+        }
+        // Keys which do the same thing share a hint
+        let state = State {
+            keystroke_buffer: to_char_keys("3d"),
+        };
+        let hints = state.continuations(&keymap, Expected::Motion(Operator::Delete));
+        assert!(hints.contains(&Hint::new("d", "delete cursor")));
+        assert!(hints.contains(&Hint::new("j l", "delete to next sibling")));
+        // Bindings of several keys are hints too
+        assert!(hints.contains(&Hint::new("^ gg", "delete root")));
+        // ...and once some of their keys have been typed, only the rest of the keys are hinted
+        let state = State {
+            keystroke_buffer: to_char_keys("3d2g"),
+        };
+        assert_eq!(
+            state.continuations(&keymap, Expected::Motion(Operator::Delete)),
+            vec![Hint::new("g", "delete root")]
+        );
+    }
+
+    #[test]
+    fn parse_key_sequences_and_modifiers() {
+        let keymap = default_keymap();
+        let key = |code| KeyEvent::from(code);
+        let ctrl = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL);
+        let alt = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::ALT);
+        let shift = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::SHIFT);
+        let root = Action::MoveCursor(Direction::Root);
+        for (keys, expected) in vec![
+            (vec![ctrl('r')], Ok((1, Action::Redo))),
+            (vec![ctrl('o')], Ok((1, Action::Jump(Side::Prev)))),
+            (vec![key(KeyCode::Tab)], Ok((1, Action::Jump(Side::Next)))),
+            (
+                vec![key(KeyCode::Char('3')), ctrl('o')],
+                Ok((3, Action::Jump(Side::Prev))),
+            ),
+            (to_char_keys("3gg"), Ok((3, root))),
+            (
+                to_char_keys("dgg"),
+                Ok((1, Action::Operate(Operator::Delete, Some(Direction::Root)))),
+            ),
+            (
+                to_char_keys("2d3gg"),
+                Ok((6, Action::Operate(Operator::Delete, Some(Direction::Root)))),
+            ),
+            // Upper-case letters can be reported with or without shift
+            (
+                vec![shift('J')],
+                Ok((1, Action::MoveCursor(Direction::PreorderNext))),
+            ),
+            (
+                vec![key(KeyCode::Char('z')), shift('A')],
+                Ok((1, Action::Fold(Fold::ToggleRecursive))),
+            ),
+            // Modifiers aren't ignored
+            (vec![ctrl('u')], Err(ParseErr::Invalid)),
+            (vec![alt('r')], Err(ParseErr::Invalid)),
+            (
+                vec![key(KeyCode::Char('i')), ctrl('t')],
+                Err(ParseErr::Invalid),
+            ),
+            (
+                vec![key(KeyCode::Char('m')), alt('a')],
+                Err(ParseErr::Invalid),
+            ),
+            (
+                vec![key(KeyCode::Char('2')), ctrl('3'), key(KeyCode::Char('x'))],
+                Err(ParseErr::Invalid),
+            ),
+            (to_char_keys("gx"), Err(ParseErr::Invalid)),
+            (to_char_keys("dgx"), Err(ParseErr::Invalid)),
+        ] {
+            println!("Testing {:?}", keys);
+            assert_eq!(parse_command(&keymap, &keys, false), expected);
+        }
+    }
+
+    #[test]
+    fn insertable_classes() {
+        let arena = Arena::new();
+        let root = add_value_to_arena(json!([{"key": true}]), &arena);
+        let mut tree = Dag::new(&arena, root, Path::root());
+        assert!(can_insert(&tree, CmdType::InsertChild, Class::True));
+        assert!(can_insert(&tree, CmdType::Replace, Class::Null));
+        assert!(!can_insert(&tree, CmdType::InsertBefore, Class::True));
+        // Only strings can go on the left of a field
+        tree.set_cursor_path(Path::from_vec(vec![0, 0, 0]));
+        assert!(can_insert(&tree, CmdType::Replace, Class::Str));
+        assert!(!can_insert(&tree, CmdType::Replace, Class::True));
+        assert!(!can_insert(&tree, CmdType::InsertBefore, Class::True));
+        assert!(can_insert(&tree, CmdType::InsertAfter, Class::True));
+        assert!(!can_insert(&tree, CmdType::InsertChild, Class::True));
+    }
+
+    #[test]
+    fn failed_command_stops_macro() {
+        let arena = Arena::new();
+        let mut editor = editor(&arena, json!([true, false, null, "a"]), vec![0]);
+        editor.macros.insert('a', to_char_keys("jx"));
+        execute(&mut editor, 5, Action::PlayMacro('a'));
+        editor.run_queued_input();
+        // The third `j` fails at the last child, so the rest of the macro isn't run
+        assert!(editor.queued_input.is_empty());
+        assert_eq!(*editor.buffer().tree.root(), json!([true, null]));
+        // The edits made before the failure are still undone together
+        execute(&mut editor, 1, Action::Undo);
+        assert_eq!(
+            *editor.buffer().tree.root(),
+            json!([true, false, null, "a"])
+        );
+    }
+
+    #[test]
+    fn closing_help_isnt_recorded() {
+        let arena = Arena::new();
+        let mut editor = editor(&arena, json!([true, false, null]), vec![0]);
+        editor.start_recording('a');
+        for key in to_char_keys("?jx") {
+            editor.handle_typed_key(key);
+        }
+        // `j` only closed the help, so it doesn't move the cursor and isn't part of the macro
+        assert!(!editor.show_help);
+        assert_eq!(*editor.buffer().tree.root(), json!([false, null]));
+        assert_eq!(editor.recording, Some(('a', to_char_keys("?x"))));
+    }
+
+    #[test]
+    fn repeat_last_edit() {
+        let arena = Arena::new();
+        let mut editor = editor(
+            &arena,
+            json!([true, false, null, "a", [], {}, "b"]),
+            vec![0],
+        );
+        // Nothing has been edited yet, so there's nothing to repeat
+        execute(&mut editor, 1, Action::Repeat(None));
+        assert_eq!(editor.last_edit, None);
+        assert_eq!(
+            *editor.buffer().tree.root(),
+            json!([true, false, null, "a", [], {}, "b"])
+        );
+
+        execute(&mut editor, 1, Action::Delete);
+        assert_eq!(editor.last_edit, Some((1, Action::Delete)));
+        // '.' runs the last edit again, with the count it was first given
+        execute(&mut editor, 1, Action::Repeat(None));
+        assert_eq!(
+            *editor.buffer().tree.root(),
+            json!([null, "a", [], {}, "b"])
+        );
+        // '2.' overrides the count, which is then kept for later repeats
+        execute(&mut editor, 1, Action::Repeat(Some(2)));
+        assert_eq!(*editor.buffer().tree.root(), json!([[], {}, "b"]));
+        assert_eq!(editor.last_edit, Some((2, Action::Delete)));
+
+        // Neither moving the cursor nor a failed edit replace the edit which gets repeated
+        execute(&mut editor, 2, Action::MoveCursor(Direction::Next));
+        assert_eq!(editor.last_edit, Some((2, Action::Delete)));
+        execute(
+            &mut editor,
+            1,
+            Action::InsertChild(Insertable::CountedNode(1, 't')),
+        );
+        assert_eq!(*editor.buffer().tree.root(), json!([[], {}, "b"]));
+        assert_eq!(editor.last_edit, Some((2, Action::Delete)));
+        execute(&mut editor, 2, Action::MoveCursor(Direction::Prev));
+        execute(&mut editor, 1, Action::Repeat(None));
+        assert_eq!(*editor.buffer().tree.root(), json!(["b"]));
+    }
+
+    #[test]
+    fn quit() {
+        let arena = Arena::new();
+        let mut editor = editor(&arena, json!([true, false]), vec![0]);
+        assert!(execute(&mut editor, 1, Action::Quit).is_quit());
+        // 'q' refuses to throw away unsaved changes, but 'ZQ' doesn't
+        execute(&mut editor, 1, Action::Delete);
+        assert!(!execute(&mut editor, 1, Action::Quit).is_quit());
+        assert!(execute(&mut editor, 1, Action::ForceQuit).is_quit());
+        // 'ZZ' can't write the changes without a file, but doesn't need to once they're undone
+        assert!(!execute(&mut editor, 1, Action::WriteAndQuit).is_quit());
+        execute(&mut editor, 1, Action::Undo);
+        assert!(execute(&mut editor, 1, Action::WriteAndQuit).is_quit());
+    }
 }
@@ -0,0 +1,400 @@
+//! Panes, which split the screen into several views.  Each [`Pane`] shows one
+//! [`Buffer`](super::buffer::Buffer), and has its own cursor and scroll offset so that different
+//! parts of the same tree can be viewed at once.
+
+use super::dag::Dag;
+use super::widgets::NodeMap;
+use crate::ast::{display_token::Position, Ast};
+use crate::core::Path;
+
+use tui::layout::{Constraint, Direction, Layout, Rect};
+
+/// A view of a single [`Buffer`](super::buffer::Buffer)
+#[derive(Debug, Clone)]
+pub struct Pane<'arena, Node: Ast<'arena>> {
+    /// The index of the [`Buffer`](super::buffer::Buffer) being shown in this `Pane`
+    pub buffer: usize,
+    /// The cursor of this `Pane`.  This is only used whilst the `Pane` isn't focused; the focused
+    /// `Pane` uses the cursor of its buffer's [`Dag`], so that edits are made at the right place.
+    cursor_path: Path,
+    /// The root of the tree which [`cursor_path`](Self::cursor_path) is a path into.  If the
+    /// [`Dag`] has been edited since then, the cursor is rebased onto the new tree.
+    root: &'arena Node,
+    /// The number of lines of the tree which are scrolled off the top of this `Pane`
+    pub line_offset: usize,
+    /// The number of columns of the tree which are scrolled off the left of this `Pane`
+    pub column_offset: usize,
+    /// The number of lines of the tree which fit in this `Pane`, as of the last time it was drawn
+    pub height: usize,
+    /// Which node was drawn in each cell of this `Pane`, as of the last time it was drawn
+    pub node_map: NodeMap,
+}
+
+impl<'arena, Node: Ast<'arena>> Pane<'arena, Node> {
+    /// Creates a `Pane` showing the buffer at index `buffer`, with its cursor in the same place
+    /// as the cursor of that buffer's `tree`
+    pub fn new(buffer: usize, tree: &Dag<'arena, Node>) -> Self {
+        Pane {
+            buffer,
+            cursor_path: tree.cursor_path().clone(),
+            root: tree.root(),
+            line_offset: 0,
+            column_offset: 0,
+            height: 0,
+            node_map: NodeMap::default(),
+        }
+    }
+
+    /// Makes this `Pane` show the buffer at index `buffer` instead, scrolled to the top and with
+    /// its cursor in the same place as the cursor of that buffer's `tree`
+    pub fn show_buffer(&mut self, buffer: usize, tree: &Dag<'arena, Node>) {
+        *self = Pane::new(buffer, tree);
+    }
+
+    /// Returns the [`Path`] of this `Pane`'s cursor.  This should only be called directly after
+    /// [`follow_edits`](Self::follow_edits).
+    pub fn cursor_path(&self) -> &Path {
+        &self.cursor_path
+    }
+
+    /// Records the size of the `area` that this `Pane` is about to be drawn in, then scrolls as
+    /// little as possible so that the start of the cursor (at `cursor_position`) is visible.  If
+    /// possible, `margin` lines are kept visible above and below the cursor.
+    pub fn fit_view(&mut self, area: Rect, cursor_position: Position, margin: usize) {
+        self.height = area.height as usize;
+        let width = area.width as usize;
+        let margin = margin.min(self.height.saturating_sub(1) / 2);
+        let Position { line, column, .. } = cursor_position;
+        if line < self.line_offset + margin {
+            self.line_offset = line.saturating_sub(margin);
+        } else if line + margin >= self.line_offset + self.height {
+            self.line_offset = (line + margin + 1).saturating_sub(self.height);
+        }
+        if column < self.column_offset {
+            self.column_offset = column;
+        } else if column >= self.column_offset + width {
+            self.column_offset = (column + 1).saturating_sub(width);
+        }
+    }
+
+    /// Moves this `Pane`'s cursor so that it follows any edits made to `tree` since the cursor was
+    /// last updated
+    pub fn follow_edits(&mut self, tree: &Dag<'arena, Node>) {
+        if !std::ptr::eq(self.root, tree.root()) {
+            self.cursor_path = self.cursor_path.rebase(self.root, tree.root());
+            self.root = tree.root();
+        }
+    }
+
+    /// Returns the node under this `Pane`'s cursor.  This should only be called directly after
+    /// [`follow_edits`](Self::follow_edits).
+    pub fn cursor(&self) -> &'arena Node {
+        self.cursor_path.cursor(self.root)
+    }
+
+    /// Remembers the cursor of `tree` as this `Pane`'s cursor.  This is called when this `Pane`
+    /// loses focus.
+    pub fn save_cursor(&mut self, tree: &Dag<'arena, Node>) {
+        self.cursor_path.clone_from(tree.cursor_path());
+        self.root = tree.root();
+    }
+
+    /// Moves the cursor of `tree` to this `Pane`'s cursor.  This is called when this `Pane` gains
+    /// focus.
+    pub fn restore_cursor(&mut self, tree: &mut Dag<'arena, Node>) {
+        self.follow_edits(tree);
+        tree.set_cursor_path(self.cursor_path.clone());
+    }
+}
+
+/// A binary tree of [`Pane`]s, where every split divides its area evenly between its two halves
+#[derive(Debug, Clone)]
+enum PaneTree<'arena, Node: Ast<'arena>> {
+    Leaf(Pane<'arena, Node>),
+    Split(Direction, Box<(Self, Self)>),
+}
+
+impl<'arena, Node: Ast<'arena>> PaneTree<'arena, Node> {
+    /// Returns the number of [`Pane`]s in this tree
+    fn num_panes(&self) -> usize {
+        match self {
+            PaneTree::Leaf(_) => 1,
+            PaneTree::Split(_, children) => children.0.num_panes() + children.1.num_panes(),
+        }
+    }
+
+    /// Adds every [`Pane`] in this tree to `panes`, in order from top-left to bottom-right
+    fn collect_panes<'s>(&'s self, panes: &mut Vec<&'s Pane<'arena, Node>>) {
+        match self {
+            PaneTree::Leaf(pane) => panes.push(pane),
+            PaneTree::Split(_, children) => {
+                children.0.collect_panes(panes);
+                children.1.collect_panes(panes);
+            }
+        }
+    }
+
+    /// Adds a mutable reference to every [`Pane`] in this tree to `panes`, in order from top-left
+    /// to bottom-right
+    fn collect_panes_mut<'s>(&'s mut self, panes: &mut Vec<&'s mut Pane<'arena, Node>>) {
+        match self {
+            PaneTree::Leaf(pane) => panes.push(pane),
+            PaneTree::Split(_, children) => {
+                children.0.collect_panes_mut(panes);
+                children.1.collect_panes_mut(panes);
+            }
+        }
+    }
+
+    /// Adds the area of every [`Pane`] in this tree to `areas`, given the area covered by the
+    /// whole tree
+    fn collect_areas(&self, area: Rect, areas: &mut Vec<Rect>) {
+        match self {
+            PaneTree::Leaf(_) => areas.push(area),
+            PaneTree::Split(direction, children) => {
+                let halves = Layout::default()
+                    .direction(direction.clone())
+                    .constraints(vec![Constraint::Percentage(50), Constraint::Percentage(50)])
+                    .split(area);
+                children.0.collect_areas(halves[0], areas);
+                children.1.collect_areas(halves[1], areas);
+            }
+        }
+    }
+
+    /// Splits the `index`th [`Pane`] in `direction`, putting `new_pane` after it
+    fn split(&mut self, index: usize, direction: Direction, new_pane: Pane<'arena, Node>) {
+        match self {
+            PaneTree::Leaf(pane) => {
+                let old_pane = PaneTree::Leaf(pane.clone());
+                *self = PaneTree::Split(direction, Box::new((old_pane, PaneTree::Leaf(new_pane))));
+            }
+            PaneTree::Split(_, children) => {
+                let first_len = children.0.num_panes();
+                if index < first_len {
+                    children.0.split(index, direction, new_pane);
+                } else {
+                    children.1.split(index - first_len, direction, new_pane);
+                }
+            }
+        }
+    }
+
+    /// Removes the `index`th [`Pane`], giving its area to its neighbour.  This returns `false` if
+    /// this tree is that [`Pane`], in which case the caller has to remove it.
+    fn remove(&mut self, index: usize) -> bool {
+        match self {
+            PaneTree::Leaf(_) => false,
+            PaneTree::Split(_, children) => {
+                let first_len = children.0.num_panes();
+                let (target, target_index, other) = if index < first_len {
+                    (&mut children.0, index, &children.1)
+                } else {
+                    (&mut children.1, index - first_len, &children.0)
+                };
+                if !target.remove(target_index) {
+                    let other = other.clone();
+                    *self = other;
+                }
+                true
+            }
+        }
+    }
+}
+
+/// Every [`Pane`] on the screen, along with which of them is focused.  There is always at least
+/// one `Pane`.
+#[derive(Debug, Clone)]
+pub struct Panes<'arena, Node: Ast<'arena>> {
+    tree: PaneTree<'arena, Node>,
+    /// The index of the focused [`Pane`], counting from the top-left
+    focus: usize,
+}
+
+impl<'arena, Node: Ast<'arena>> Panes<'arena, Node> {
+    /// Creates a set of `Panes` containing only `pane`
+    pub fn new(pane: Pane<'arena, Node>) -> Self {
+        Panes {
+            tree: PaneTree::Leaf(pane),
+            focus: 0,
+        }
+    }
+
+    /// Returns the number of [`Pane`]s on the screen
+    pub fn num_panes(&self) -> usize {
+        self.tree.num_panes()
+    }
+
+    /// Returns the index of the focused [`Pane`]
+    pub fn focus(&self) -> usize {
+        self.focus
+    }
+
+    /// Focuses the [`Pane`] with a given index.  The cursors of the [`Pane`]s aren't saved or
+    /// restored, so the caller is responsible for doing this.
+    pub fn set_focus(&mut self, index: usize) {
+        assert!(
+            index < self.num_panes(),
+            "Focusing a pane which doesn't exist"
+        );
+        self.focus = index;
+    }
+
+    /// Returns every [`Pane`], in order from top-left to bottom-right
+    pub fn panes(&self) -> Vec<&Pane<'arena, Node>> {
+        let mut panes = Vec::new();
+        self.tree.collect_panes(&mut panes);
+        panes
+    }
+
+    /// Returns a mutable reference to every [`Pane`], in order from top-left to bottom-right
+    pub fn panes_mut(&mut self) -> Vec<&mut Pane<'arena, Node>> {
+        let mut panes = Vec::new();
+        self.tree.collect_panes_mut(&mut panes);
+        panes
+    }
+
+    /// Returns the focused [`Pane`]
+    pub fn focused(&self) -> &Pane<'arena, Node> {
+        self.panes()[self.focus]
+    }
+
+    /// Returns a mutable reference to the focused [`Pane`]
+    pub fn focused_mut(&mut self) -> &mut Pane<'arena, Node> {
+        let focus = self.focus;
+        self.panes_mut().swap_remove(focus)
+    }
+
+    /// Returns the area of the screen taken up by every [`Pane`], in the same order as
+    /// [`panes`](Self::panes)
+    pub fn areas(&self, area: Rect) -> Vec<Rect> {
+        let mut areas = Vec::new();
+        self.tree.collect_areas(area, &mut areas);
+        areas
+    }
+
+    /// Splits the focused [`Pane`] in half, putting `new_pane` below it (if `direction` is
+    /// [`Direction::Vertical`]) or to its right (if `direction` is [`Direction::Horizontal`]).
+    /// The focus doesn't change.
+    pub fn split(&mut self, direction: Direction, new_pane: Pane<'arena, Node>) {
+        self.tree.split(self.focus, direction, new_pane);
+    }
+
+    /// Closes the focused [`Pane`], moving the focus to the [`Pane`] which was after it (or the
+    /// last [`Pane`] if the focused one was last).  Returns `false` if the focused [`Pane`] is
+    /// the only one, in which case nothing is closed.
+    pub fn close(&mut self) -> bool {
+        if self.num_panes() == 1 {
+            return false;
+        }
+        self.tree.remove(self.focus);
+        self.focus = self.focus.min(self.num_panes() - 1);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Pane, Panes};
+    use crate::arena::Arena;
+    use crate::ast::display_token::Position;
+    use crate::ast::json::{add_value_to_arena, Json};
+    use crate::core::Path;
+    use crate::editor::dag::Dag;
+
+    use tui::layout::{Direction, Rect};
+
+    #[test]
+    fn split_and_close() {
+        let arena: Arena<Json> = Arena::new();
+        let root = add_value_to_arena(serde_json::json!([true, false]), &arena);
+        let tree = Dag::new(&arena, root, Path::root());
+        let buffers = |panes: &Panes<Json>| -> Vec<usize> {
+            panes.panes().iter().map(|pane| pane.buffer).collect()
+        };
+
+        let mut panes = Panes::new(Pane::new(0, &tree));
+        assert!(!panes.close());
+        // Split into 0 | 1, then split the left half into 0 / 2
+        panes.split(Direction::Horizontal, Pane::new(1, &tree));
+        panes.split(Direction::Vertical, Pane::new(2, &tree));
+        assert_eq!(buffers(&panes), vec![0, 2, 1]);
+        assert_eq!(panes.focused().buffer, 0);
+        assert_eq!(
+            panes.areas(Rect::new(0, 0, 80, 20)),
+            vec![
+                Rect::new(0, 0, 40, 10),
+                Rect::new(0, 10, 40, 10),
+                Rect::new(40, 0, 40, 20)
+            ]
+        );
+        // Closing the bottom-left pane gives its area back to the top-left pane
+        panes.set_focus(1);
+        assert!(panes.close());
+        assert_eq!(buffers(&panes), vec![0, 1]);
+        assert_eq!(panes.focus(), 1);
+        assert_eq!(
+            panes.areas(Rect::new(0, 0, 80, 20)),
+            vec![Rect::new(0, 0, 40, 20), Rect::new(40, 0, 40, 20)]
+        );
+        // Closing the last pane moves the focus backwards
+        assert!(panes.close());
+        assert_eq!(buffers(&panes), vec![0]);
+        assert_eq!(panes.focus(), 0);
+        assert!(!panes.close());
+    }
+
+    #[test]
+    fn view_follows_cursor() {
+        let arena: Arena<Json> = Arena::new();
+        let root = add_value_to_arena(serde_json::json!([true, false]), &arena);
+        let tree = Dag::new(&arena, root, Path::root());
+        let mut pane = Pane::new(0, &tree);
+        let area = Rect::new(0, 0, 20, 10);
+        let at = |line, column| Position {
+            line,
+            column,
+            indentation: 0,
+        };
+        // Cursors which are already on screen don't cause any scrolling
+        pane.fit_view(area, at(5, 10), 2);
+        assert_eq!(
+            (pane.line_offset, pane.column_offset, pane.height),
+            (0, 0, 10)
+        );
+        // Moving below the bottom margin scrolls down, leaving the margin below the cursor
+        pane.fit_view(area, at(8, 10), 2);
+        assert_eq!(pane.line_offset, 1);
+        pane.fit_view(area, at(40, 10), 2);
+        assert_eq!(pane.line_offset, 33);
+        // Moving above the top margin scrolls up
+        pane.fit_view(area, at(34, 10), 2);
+        assert_eq!(pane.line_offset, 32);
+        pane.fit_view(area, at(1, 10), 2);
+        assert_eq!(pane.line_offset, 0);
+        // Columns are scrolled without a margin
+        pane.fit_view(area, at(1, 25), 2);
+        assert_eq!(pane.column_offset, 6);
+        pane.fit_view(area, at(1, 3), 2);
+        assert_eq!(pane.column_offset, 3);
+        // Margins are shrunk to fit in small panes
+        pane.fit_view(Rect::new(0, 0, 20, 2), at(10, 3), 5);
+        assert_eq!(pane.line_offset, 9);
+    }
+
+    #[test]
+    fn cursors_follow_edits() {
+        let arena: Arena<Json> = Arena::new();
+        let root = add_value_to_arena(serde_json::json!([true, false, null]), &arena);
+        let mut tree = Dag::new(&arena, root, Path::from_vec(vec![2]));
+        let mut pane = Pane::new(0, &tree);
+        // Deleting the first child from another pane moves this pane's cursor back by one
+        tree.set_cursor_path(Path::from_vec(vec![0]));
+        tree.delete_cursor(1).unwrap();
+        pane.follow_edits(&tree);
+        assert_eq!(pane.cursor(), &Json::Null);
+        // Focusing the pane moves the tree's cursor
+        pane.restore_cursor(&mut tree);
+        assert_eq!(tree.cursor_path(), &Path::from_vec(vec![1]));
+    }
+}
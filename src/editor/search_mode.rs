@@ -0,0 +1,194 @@
+//! The code for 'search-mode', where the user types a [`Pattern`] to search the tree for (or a
+//! pattern and a replacement template, to replace every match)
+
+use super::dag::{EditSuccess, LogMessage};
+use super::{keystroke_log::Category, normal_mode, state, Editor};
+use crate::ast::{pattern::Pattern, Ast};
+use crate::core::Side;
+
+use std::borrow::Cow;
+
+use crossterm::event::{KeyCode, KeyEvent};
+
+/// What the text typed in search mode will be used for
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+enum Purpose {
+    /// The text is a [`Pattern`] to search for
+    Search,
+    /// The text is a [`Pattern`] and a replacement template, separated by `=>`
+    Replace,
+}
+
+/// The [`State`](state::State) that Sapling enters whilst the user is typing a search pattern.
+#[derive(Debug, Clone)]
+pub struct State {
+    /// What the query will be used for once it's submitted
+    purpose: Purpose,
+    /// The text typed so far
+    query: String,
+}
+
+impl State {
+    /// Creates a `State` for typing a [`Pattern`] to search for
+    pub fn search() -> Self {
+        State {
+            purpose: Purpose::Search,
+            query: String::new(),
+        }
+    }
+
+    /// Creates a `State` for typing a [`Pattern`] and a replacement template (in the form
+    /// `<pattern> => <template>`), which will replace every match in the tree
+    pub fn replace() -> Self {
+        State {
+            purpose: Purpose::Replace,
+            query: String::new(),
+        }
+    }
+}
+
+impl<'arena, Node: Ast<'arena>> state::State<'arena, Node> for State {
+    fn transition(
+        mut self: Box<Self>,
+        key: KeyEvent,
+        editor: &mut Editor<'arena, Node>,
+    ) -> (
+        Box<dyn state::State<'arena, Node>>,
+        Option<(String, Category)>,
+    ) {
+        match key.code {
+            // Submit the query
+            KeyCode::Enter => {
+                let log_entry = match self.purpose {
+                    Purpose::Search => submit_search(&self.query, editor),
+                    Purpose::Replace => submit_replace(&self.query, editor),
+                };
+                (Box::new(normal_mode::State::default()), Some(log_entry))
+            }
+            // Cancel the search, leaving the previous pattern untouched
+            KeyCode::Esc => (
+                Box::new(normal_mode::State::default()),
+                Some(("cancel search".to_owned(), Category::Undefined)),
+            ),
+            KeyCode::Backspace => {
+                self.query.pop();
+                (self, None)
+            }
+            KeyCode::Char(c) => {
+                self.query.push(c);
+                (self, None)
+            }
+            // Ignore any other keys
+            _ => (self, None),
+        }
+    }
+
+    fn mode_name(&self) -> &'static str {
+        match self.purpose {
+            Purpose::Search => "SEARCH",
+            Purpose::Replace => "REPLACE",
+        }
+    }
+
+    fn prompt(&self) -> Option<Cow<'_, str>> {
+        Some(Cow::from(match self.purpose {
+            Purpose::Search => format!("/{}", self.query),
+            Purpose::Replace => format!("s/{}", self.query),
+        }))
+    }
+}
+
+/// Parse a search [`Pattern`] and jump to the first match after the cursor, returning the entry
+/// for the keystroke log
+fn submit_search<'arena, Node: Ast<'arena>>(
+    query: &str,
+    editor: &mut Editor<'arena, Node>,
+) -> (String, Category) {
+    match Pattern::parse(query) {
+        Ok(pattern) => {
+            editor
+                .buffer_mut()
+                .tree
+                .move_to_match(1, &pattern, Side::Next)
+                .log_message();
+            editor.search_pattern = Some(pattern);
+            (format!("search for '{}'", query), Category::Move)
+        }
+        Err(e) => {
+            log::warn!("{}", e);
+            (format!("invalid search '{}'", query), Category::Undefined)
+        }
+    }
+}
+
+/// Parse a query of the form `<pattern> => <template>` and replace every match of the pattern,
+/// returning the entry for the keystroke log
+fn submit_replace<'arena, Node: Ast<'arena>>(
+    query: &str,
+    editor: &mut Editor<'arena, Node>,
+) -> (String, Category) {
+    let invalid_entry = (format!("invalid replace '{}'", query), Category::Undefined);
+    let (pattern, template) = match split_replace_query(query) {
+        Some(parts) => parts,
+        None => {
+            log::warn!("Expected a replacement of the form '<pattern> => <template>'");
+            return invalid_entry;
+        }
+    };
+    let pattern = match Pattern::parse(pattern) {
+        Ok(pattern) => pattern,
+        Err(e) => {
+            log::warn!("{}", e);
+            return invalid_entry;
+        }
+    };
+    let buffer = editor.buffer_mut();
+    let result = buffer
+        .tree
+        .replace_matches(&pattern, template.trim(), &buffer.format_style);
+    let log_entry = match &result {
+        Ok(EditSuccess::ReplaceMatches { count }) => (
+            format!("replace {} matches of '{}'", count, pattern.source().trim()),
+            Category::Replace,
+        ),
+        _ => invalid_entry,
+    };
+    result.log_message();
+    log_entry
+}
+
+/// Splits a query of the form `<pattern> => <template>` at the first `=>` which isn't inside a
+/// quoted string, so that strings in the pattern can contain `=>`
+fn split_replace_query(query: &str) -> Option<(&str, &str)> {
+    let mut is_in_string = false;
+    for (i, c) in query.char_indices() {
+        match c {
+            '"' => is_in_string = !is_in_string,
+            '=' if !is_in_string && query[i..].starts_with("=>") => {
+                return Some((&query[..i], &query[i + 2..]));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_replace_query;
+
+    #[test]
+    fn split_replace() {
+        assert_eq!(
+            split_replace_query("true => false"),
+            Some(("true ", " false"))
+        );
+        assert_eq!(
+            split_replace_query(r#"."a=>b" => "c=>d""#),
+            Some((r#"."a=>b" "#, r#" "c=>d""#))
+        );
+        assert_eq!(split_replace_query("a => b => c"), Some(("a ", " b => c")));
+        assert_eq!(split_replace_query(r#""=>" null"#), None);
+        assert_eq!(split_replace_query("true = > false"), None);
+    }
+}
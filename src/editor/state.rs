@@ -1,6 +1,6 @@
 //! Definition of the state machine of Sapling's editor modes
 
-use super::{keystroke_log::Category, Editor};
+use super::{keystroke_log::Category, widgets::Hint, Editor};
 use crate::ast::Ast;
 
 use std::borrow::Cow;
@@ -20,6 +20,10 @@ impl<'arena, Node: Ast<'arena>> State<'arena, Node> for Quit {
         (self, None)
     }
 
+    fn mode_name(&self) -> &'static str {
+        "QUIT"
+    }
+
     fn is_quit(&self) -> bool {
         true
     }
@@ -30,6 +34,8 @@ impl<'arena, Node: Ast<'arena>> State<'arena, Node> for Quit {
 /// The current states are:
 /// - [`Quit`]
 /// - [`crate::editor::normal_mode::State`]
+/// - [`crate::editor::search_mode::State`]
+/// - [`crate::editor::command_mode::State`]
 /// - `crate::editor::IntermediateState` (link doesn't work because `IntermediateState` is private)
 pub trait State<'arena, Node: Ast<'arena>>: std::fmt::Debug {
     /// Consume a keystroke, returning the `State` after this transition
@@ -39,12 +45,29 @@ pub trait State<'arena, Node: Ast<'arena>>: std::fmt::Debug {
         editor: &mut Editor<'arena, Node>,
     ) -> (Box<dyn State<'arena, Node>>, Option<(String, Category)>);
 
+    /// Returns the name of the mode that this `State` belongs to, which is shown in the status
+    /// line (e.g. `"NORMAL"`)
+    fn mode_name(&self) -> &'static str;
+
     /// Return the keystroke buffer that should be displayed in the bottom right corner of the
     /// screen
     fn keystroke_buffer(&self) -> Cow<'_, str> {
         Cow::from("")
     }
 
+    /// Return the text that the user is currently typing into a prompt (e.g. a search pattern),
+    /// which should be displayed in the bottom left corner of the screen.  By default, there is no
+    /// prompt.
+    fn prompt(&self) -> Option<Cow<'_, str>> {
+        None
+    }
+
+    /// If the user is part way through typing a command, returns a title for the command and
+    /// [`Hint`]s for what can be typed next.  By default, there is no command being typed.
+    fn pending_hints(&self, _editor: &Editor<'arena, Node>) -> Option<(String, Vec<Hint>)> {
+        None
+    }
+
     /// Returns `true` if Sapling should quit.  By default, this returns `false`.  This should
     /// **only** be `true` for [`Quit`].
     fn is_quit(&self) -> bool {
@@ -0,0 +1,67 @@
+//! Putting the terminal into the state Sapling needs, and making sure that it's always put back
+//! afterwards (even if Sapling panics)
+
+use std::io::{self, Write};
+use std::sync::Once;
+
+use crossterm::{cursor, event, terminal};
+
+/// A guard which holds the terminal in raw mode on the alternate screen, with mouse capture
+/// enabled.  The terminal is restored when the guard is dropped, or when Sapling panics.
+pub struct TerminalGuard {
+    // Stops the guard being created without setting up the terminal
+    _private: (),
+}
+
+impl TerminalGuard {
+    /// Sets up the terminal for Sapling, undoing any changes that were made if setting up fails
+    pub fn new() -> io::Result<Self> {
+        install_panic_hook();
+        // Creating the guard first means that it restores the terminal if a later step fails
+        let guard = TerminalGuard { _private: () };
+        terminal::enable_raw_mode()?;
+        crossterm::execute!(
+            io::stdout(),
+            terminal::EnterAlternateScreen,
+            event::EnableMouseCapture
+        )?;
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+/// Puts the terminal back into the state it was in before Sapling started.  This ignores any
+/// errors, since it's called when Sapling is already closing (possibly because of a panic) and
+/// there's nothing better we can do.
+fn restore_terminal() {
+    let mut stdout = io::stdout();
+    // Show the cursor before closing so that the cursor isn't permanently disabled
+    // (see issue `lotabout/tuikit#28`: https://github.com/lotabout/tuikit/issues/28)
+    let _ = crossterm::execute!(
+        stdout,
+        event::DisableMouseCapture,
+        terminal::LeaveAlternateScreen,
+        cursor::Show
+    );
+    let _ = terminal::disable_raw_mode();
+    let _ = stdout.flush();
+}
+
+/// Makes panics restore the terminal before printing their message, so that the message can be
+/// read and the user's shell isn't left in raw mode.  The hook is only installed once, however
+/// many guards are created.
+fn install_panic_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            restore_terminal();
+            default_hook(info);
+        }));
+    });
+}
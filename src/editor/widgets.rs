@@ -1,38 +1,446 @@
+use super::keystroke_log::Category;
+use super::normal_mode::CmdType;
 use super::DEBUG_HIGHLIGHTING;
 use crate::ast::{
-    display_token::{DisplayToken, SyntaxCategory},
-    Ast,
+    display_token::{DisplayToken, Folds, Position, RecTok, SyntaxCategory},
+    Ast, AstClass,
 };
+use crate::config::{KeyMap, StatusItem, StatusLine};
+use crate::core::{keystrokes_to_string, Path};
 
-use std::collections::{hash_map::DefaultHasher, HashSet};
+use std::collections::{hash_map::DefaultHasher, HashMap, HashSet};
+use std::fmt::Write;
 use std::hash::Hasher;
+use std::ops::Range;
 
 use tui::{
     buffer::Buffer,
-    layout::{Alignment, Rect},
+    layout::Rect,
     style::{Color, Style},
-    widgets::{Paragraph, Widget},
+    widgets::{Block, Borders, Clear, StatefulWidget, Widget},
 };
 
+/// The line at the bottom of the screen, which shows the [`StatusItem`]s chosen by the
+/// [`StatusLine`] config
 pub struct StatusBar<'a> {
+    pub layout: &'a StatusLine,
+    pub mode: &'a str,
     pub keystroke_buffer: &'a str,
+    pub prompt: Option<&'a str>,
+    pub recording: Option<char>,
+    pub file_name: &'a str,
+    pub is_dirty: bool,
+    /// The cursor's ancestors, as generated by [`breadcrumb`]
+    pub breadcrumb: &'a str,
+    pub class_name: &'a str,
+    /// The [`Dag::history_index`](super::dag::Dag::history_index) and
+    /// [`Dag::num_edits`](super::dag::Dag::num_edits) of the current buffer
+    pub undo_position: (usize, usize),
 }
+
+impl StatusBar<'_> {
+    /// Returns the text shown for a [`StatusItem`], which is empty if it has nothing to show
+    fn item_text(&self, item: StatusItem) -> String {
+        match item {
+            StatusItem::Mode => self.mode.to_owned(),
+            StatusItem::FileName => self.file_name.to_owned(),
+            StatusItem::Dirty if self.is_dirty => "[+]".to_owned(),
+            StatusItem::Dirty => String::new(),
+            StatusItem::Breadcrumb => self.breadcrumb.to_owned(),
+            StatusItem::ClassName => self.class_name.to_owned(),
+            StatusItem::UndoPosition => {
+                format!("change {}/{}", self.undo_position.0, self.undo_position.1)
+            }
+            StatusItem::Recording => self
+                .recording
+                .map(|register| format!("recording @{}", register))
+                .unwrap_or_default(),
+            StatusItem::Keystrokes => self.keystroke_buffer.to_owned(),
+        }
+    }
+
+    /// Returns the texts of some [`StatusItem`]s separated by spaces, skipping empty items
+    fn items_text(&self, items: &[StatusItem]) -> String {
+        items
+            .iter()
+            .map(|item| self.item_text(*item))
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join("  ")
+    }
+}
+
 impl Widget for StatusBar<'_> {
     fn render(self, area: Rect, buf: &mut Buffer) {
-        Paragraph::new(self.keystroke_buffer)
-            .alignment(Alignment::Right)
-            .render(area, buf);
-        buf.set_string(area.x, area.y, "Press 'q' to exit", Style::default());
+        let left_text = match self.prompt {
+            Some(prompt) => prompt.to_owned(),
+            None => self.items_text(&self.layout.left),
+        };
+        let right_text = self.items_text(&self.layout.right);
+        // The right side is drawn first, and the left side is cut short so that they don't overlap
+        let right_width = right_text.chars().count().min(area.width as usize);
+        buf.set_stringn(
+            area.right() - right_width as u16,
+            area.y,
+            &right_text,
+            right_width,
+            Style::default(),
+        );
+        let left_width = (area.width as usize).saturating_sub(right_width + 1);
+        buf.set_stringn(area.x, area.y, &left_text, left_width, Style::default());
+    }
+}
+
+/// Returns the ancestors of the node at the end of `path` (including that node) as a string like
+/// `root › "servers" › [2] › "port"`.  Nodes with keys are shown by their key and other nodes by
+/// their index, except that the children of a node with a key (e.g. the key and value of a JSON
+/// field) are part of their parent and so aren't shown.
+pub fn breadcrumb<'arena, Node: Ast<'arena>>(root: &'arena Node, path: &Path) -> String {
+    let mut breadcrumb = "root".to_owned();
+    let mut parent = root;
+    for &index in path.iter() {
+        let node = parent.children()[index];
+        if parent.key_name().is_none() {
+            // Writing to a `String` can't fail
+            let _ = match node.key_name() {
+                Some(key) => write!(breadcrumb, " › {:?}", key),
+                None => write!(breadcrumb, " › [{}]", index),
+            };
+        }
+        parent = node;
+    }
+    breadcrumb
+}
+
+/// A titled group of entries in a [`HelpPopup`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct HelpSection {
+    pub title: &'static str,
+    pub color: Color,
+    /// The keys (or other text that the user can type) and what they do
+    pub entries: Vec<(String, String)>,
+}
+
+/// Returns the help for every binding in `keymap`, grouped by [`Category`], followed by the node
+/// types that can be typed after commands such as `i` and `r`.  Keys bound to the same
+/// [`CmdType`] share an entry.
+pub fn help_sections<C: AstClass>(keymap: &KeyMap) -> Vec<HelpSection> {
+    let mut keys_by_cmd: HashMap<CmdType, Vec<String>> = HashMap::new();
+    for (keys, cmd) in keymap.bindings() {
+        keys_by_cmd
+            .entry(cmd)
+            .or_default()
+            .push(keystrokes_to_string(&keys));
+    }
+    let mut sections: Vec<HelpSection> = Category::BOUND
+        .iter()
+        .map(|category| {
+            let mut entries: Vec<(String, String)> = keys_by_cmd
+                .iter()
+                .filter(|(cmd, _)| cmd.category() == *category)
+                .map(|(cmd, keys)| {
+                    let mut keys = keys.clone();
+                    keys.sort();
+                    (keys.join(" "), cmd.summary_string().to_owned())
+                })
+                .collect();
+            entries.sort_by(|(keys_a, desc_a), (keys_b, desc_b)| {
+                desc_a.cmp(desc_b).then(keys_a.cmp(keys_b))
+            });
+            HelpSection {
+                title: category.title(),
+                color: category.term_color(),
+                entries,
+            }
+        })
+        .filter(|section| !section.entries.is_empty())
+        .collect();
+    sections.push(HelpSection {
+        title: "Node types",
+        color: Color::White,
+        entries: C::all()
+            .iter()
+            .map(|class| (class.to_char().to_string(), class.name().to_owned()))
+            .collect(),
+    });
+    sections
+}
+
+/// A popup drawn over the middle of the screen which lists [`HelpSection`]s in as many columns as
+/// fit
+pub struct HelpPopup<'a> {
+    pub sections: &'a [HelpSection],
+}
+
+impl Widget for HelpPopup<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        // Leave a margin so that it's clear the popup is on top of the editor
+        let area = Rect {
+            x: area.x + area.width / 20,
+            y: area.y + area.height / 20,
+            width: area.width - area.width / 10,
+            height: area.height - area.height / 10,
+        };
+        Clear.render(area, buf);
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(" Help (press any key to close) ");
+        let inner = block.inner(area);
+        block.render(area, buf);
+        if inner.height == 0 {
+            return;
+        }
+
+        let entries = self.sections.iter().flat_map(|s| s.entries.iter());
+        let key_width = entries.clone().map(|(k, _)| k.chars().count()).max();
+        let desc_width = entries.map(|(_, d)| d.chars().count()).max();
+        let key_width = key_width.unwrap_or(0);
+        let column_width = (key_width + 2 + desc_width.unwrap_or(0)) as u16;
+        let column_gap = 3;
+
+        let mut x = inner.x;
+        let mut y = inner.y;
+        for section in self.sections {
+            // Start a new column rather than splitting a section, unless the section wouldn't fit
+            // in any column
+            let height = section.entries.len() as u16 + 1;
+            if y > inner.y && y + height > inner.bottom() {
+                x += column_width + column_gap;
+                y = inner.y;
+            }
+            let title_style = Style::default().fg(section.color);
+            let lines = std::iter::once((section.title.to_owned(), title_style)).chain(
+                section.entries.iter().map(|(keys, description)| {
+                    let line = format!("{:<w$}  {}", keys, description, w = key_width);
+                    (line, Style::default())
+                }),
+            );
+            for (line, style) in lines {
+                if y >= inner.bottom() {
+                    x += column_width + column_gap;
+                    y = inner.y;
+                }
+                if x >= inner.right() {
+                    return;
+                }
+                let width = (inner.right() - x).min(column_width) as usize;
+                buf.set_stringn(x, y, line, width, style);
+                y += 1;
+            }
+            // Leave a gap between sections
+            y += 1;
+        }
+    }
+}
+
+/// Something which can be typed to finish an incomplete command, as shown by [`PendingHints`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hint {
+    pub keys: String,
+    pub description: String,
+    /// `false` if typing `keys` would be an error (e.g. inserting a node where it isn't allowed)
+    pub is_valid: bool,
+}
+
+impl Hint {
+    /// Creates a `Hint` which is valid
+    pub fn new(keys: impl Into<String>, description: impl Into<String>) -> Self {
+        Hint {
+            keys: keys.into(),
+            description: description.into(),
+            is_valid: true,
+        }
+    }
+}
+
+/// A popup along the bottom of an area which lists [`Hint`]s for finishing an incomplete command,
+/// in as many columns as fit.  Invalid [`Hint`]s are greyed out.
+pub struct PendingHints<'a> {
+    pub title: &'a str,
+    pub hints: &'a [Hint],
+}
+
+impl Widget for PendingHints<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let key_width = self.hints.iter().map(|h| h.keys.chars().count()).max();
+        let desc_width = self
+            .hints
+            .iter()
+            .map(|h| h.description.chars().count())
+            .max();
+        let key_width = key_width.unwrap_or(0);
+        let column_width = key_width + 2 + desc_width.unwrap_or(0);
+        let column_gap = 3;
+        let inner_width = (area.width as usize).saturating_sub(2);
+        let num_columns = ((inner_width + column_gap) / (column_width + column_gap)).max(1);
+        let num_rows = self.hints.len().div_ceil(num_columns);
+        let height = (num_rows as u16 + 2).min(area.height);
+
+        let area = Rect {
+            y: area.bottom() - height,
+            height,
+            ..area
+        };
+        Clear.render(area, buf);
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" {} ", self.title));
+        let inner = block.inner(area);
+        block.render(area, buf);
+        // The hints go down the columns, so that hints next to each other in the list are shown
+        // next to each other on the screen
+        for (i, hint) in self.hints.iter().enumerate() {
+            let (column, row) = (i / num_rows, i % num_rows);
+            let x = inner.x as usize + column * (column_width + column_gap);
+            let y = inner.y + row as u16;
+            if x >= inner.right() as usize || y >= inner.bottom() {
+                continue;
+            }
+            let style = match hint.is_valid {
+                true => Style::default(),
+                false => Style::default().fg(Color::DarkGray),
+            };
+            let line = format!("{:<w$}  {}", hint.keys, hint.description, w = key_width);
+            let width = (inner.right() as usize - x).min(column_width);
+            buf.set_stringn(x as u16, y, line, width, style);
+        }
     }
 }
 
 pub struct TextView<'a, 'arena, Node: Ast<'arena>> {
-    pub tree: &'a super::Dag<'arena, Node>,
+    pub root: &'arena Node,
+    pub cursor: &'arena Node,
+    /// The number of lines scrolled off the top of the view
+    pub line_offset: usize,
+    /// The number of columns scrolled off the left of the view
+    pub column_offset: usize,
     pub color_scheme: &'a crate::config::ColorScheme,
     pub format_style: &'a Node::FormatStyle,
+    pub search_matches: &'a HashSet<*const Node>,
+    /// The nodes which are drawn as a one-line summary rather than their full text
+    pub folds: &'a Folds<Node>,
 }
-impl<'arena, Node: Ast<'arena>> Widget for TextView<'_, 'arena, Node> {
-    fn render(self, area: Rect, buf: &mut Buffer) {
+impl<'arena, Node: Ast<'arena>> StatefulWidget for TextView<'_, 'arena, Node> {
+    type State = NodeMap;
+
+    fn render(self, area: Rect, buf: &mut Buffer, node_map: &mut NodeMap) {
+        *node_map = NodeMap::new(area);
+        let mut renderer = Renderer {
+            view: &self,
+            area,
+            buf,
+            node_map,
+            position: Position::default(),
+            path: Path::root(),
+            unknown_categories: HashSet::with_capacity(0),
+        };
+        renderer.render_node(self.root);
+
+        // Print warning messages for unknown syntax categories
+        for c in renderer.unknown_categories {
+            log::error!("Unknown highlight category '{}'", c);
+        }
+    }
+}
+
+/// A map from the cells of a rendered [`TextView`] back to the nodes which were drawn in them, so
+/// that mouse clicks can be turned into cursor movements.  This is rebuilt every time the
+/// [`TextView`] is rendered.
+#[derive(Debug, Clone, Default)]
+pub struct NodeMap {
+    /// The area of the screen that the [`TextView`] was rendered to
+    area: Rect,
+    /// For every row of `area`, the screen columns covered by each piece of text on that row and
+    /// the [`Path`] of the node which drew it
+    rows: Vec<Vec<(Range<u16>, Path)>>,
+}
+
+impl NodeMap {
+    /// Creates an empty `NodeMap` covering a given area of the screen
+    fn new(area: Rect) -> Self {
+        NodeMap {
+            area,
+            rows: vec![Vec::new(); area.height as usize],
+        }
+    }
+
+    /// Returns `true` if the screen cell at (`column`, `row`) is part of this `NodeMap`'s area
+    pub fn contains(&self, column: u16, row: u16) -> bool {
+        (self.area.left()..self.area.right()).contains(&column)
+            && (self.area.top()..self.area.bottom()).contains(&row)
+    }
+
+    /// Returns the [`Path`] of the node which was drawn in the screen cell at (`column`, `row`),
+    /// or `None` if no text was drawn there
+    pub fn path_at(&self, column: u16, row: u16) -> Option<&Path> {
+        if !self.contains(column, row) {
+            return None;
+        }
+        self.rows[(row - self.area.top()) as usize]
+            .iter()
+            .find(|(columns, _)| columns.contains(&column))
+            .map(|(_, path)| path)
+    }
+}
+
+/// The state of a [`TextView`] whilst it's being rendered
+struct Renderer<'v, 'a, 'arena, Node: Ast<'arena>> {
+    view: &'v TextView<'a, 'arena, Node>,
+    area: Rect,
+    buf: &'v mut Buffer,
+    node_map: &'v mut NodeMap,
+    /// The position of the next token, relative to the start of the tree
+    position: Position,
+    /// The [`Path`] of the node whose tokens are currently being rendered
+    path: Path,
+    unknown_categories: HashSet<SyntaxCategory>,
+}
+
+impl<'arena, Node: Ast<'arena>> Renderer<'_, '_, 'arena, Node> {
+    /// Returns the first line of the tree which is below the bottom of the view
+    fn end_line(&self) -> usize {
+        self.view.line_offset + self.area.height as usize
+    }
+
+    /// Renders the tokens of `node` and its descendants, returning `false` once the bottom of the
+    /// view has been reached
+    fn render_node(&mut self, node: &'arena Node) -> bool {
+        let folds = self.view.folds;
+        let mut child_index = 0;
+        for tok in folds.display_tokens_rec(node, self.view.format_style) {
+            match tok {
+                RecTok::Child(child) => {
+                    let size = folds.size(child, self.view.format_style);
+                    // Children which finish above the top of the view are skipped without
+                    // generating any of their tokens
+                    if self.position.line + size.lines() < self.view.line_offset {
+                        self.position.skip(size);
+                    } else {
+                        self.path.push(child_index);
+                        let reached_bottom = !self.render_node(child);
+                        self.path.pop();
+                        if reached_bottom {
+                            return false;
+                        }
+                    }
+                    child_index += 1;
+                }
+                RecTok::Tok(tok) => {
+                    if let DisplayToken::Text(s, category) = &tok {
+                        self.render_text(node, s, category);
+                    }
+                    self.position.advance(&tok);
+                    if self.position.line >= self.end_line() {
+                        return false;
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Renders a [`DisplayToken::Text`] belonging to `node` at the current position
+    fn render_text(&mut self, node: &'arena Node, s: &str, category: SyntaxCategory) {
         let cols = [
             Color::Magenta,
             Color::Red,
@@ -49,71 +457,363 @@ impl<'arena, Node: Ast<'arena>> Widget for TextView<'_, 'arena, Node> {
             Color::LightMagenta,
         ];
 
-        /* RENDER MAIN TEXT VIEW */
-        // Mutable variables to track where the terminal cursor should go
-        let mut row = area.top();
-        let mut col = area.left();
-        let mut indentation_amount = 0;
+        let color = if DEBUG_HIGHLIGHTING {
+            // Hash the ref to decide on the colour
+            let mut hasher = DefaultHasher::new();
+            node.hash(&mut hasher);
+            let hash = hasher.finish();
+            cols[hash as usize % cols.len()]
+        } else {
+            let unknown_categories = &mut self.unknown_categories;
+            *self.view.color_scheme.get(category).unwrap_or_else(|| {
+                unknown_categories.insert(category);
+                &Color::LightMagenta
+            })
+        };
+        // Generate the display attributes depending on if the node is selected or matches the
+        // current search
+        let style = if std::ptr::eq(node, self.view.cursor) {
+            Style::default().fg(Color::Black).bg(color)
+        } else if self.view.search_matches.contains(&(node as *const Node)) {
+            Style::default().fg(color).bg(Color::DarkGray)
+        } else {
+            Style::default().fg(color)
+        };
+        // Every line after the first starts at the current indentation level, which is how
+        // `Position::advance` and `Ast::size` expect multi-line tokens to be laid out
+        for (i, text_line) in s.split('\n').enumerate() {
+            let (line, column) = match i {
+                0 => (self.position.line, self.position.column),
+                _ => (self.position.line + i, self.position.indentation),
+            };
+            if line >= self.end_line() {
+                break;
+            }
+            self.render_string(line, column, text_line, style);
+        }
+    }
 
-        let mut unknown_categories: HashSet<SyntaxCategory> = HashSet::with_capacity(0);
+    /// Renders a single-line string at a given line and column of the tree, clipping it to the
+    /// visible area
+    fn render_string(&mut self, line: usize, column: usize, string: &str, style: Style) {
+        if line < self.view.line_offset || line >= self.end_line() {
+            return;
+        }
+        // Remove the characters which are scrolled off the left of the view
+        let hidden_chars = self.view.column_offset.saturating_sub(column);
+        let visible_string = match string.char_indices().nth(hidden_chars) {
+            Some((i, _)) => &string[i..],
+            None => return,
+        };
+        let screen_column = column.max(self.view.column_offset) - self.view.column_offset;
+        if screen_column >= self.area.width as usize {
+            return;
+        }
+        let screen_row = (line - self.view.line_offset) as u16;
+        let start = self.area.left() + screen_column as u16;
+        let (end, _) = self.buf.set_stringn(
+            start,
+            self.area.top() + screen_row,
+            visible_string,
+            self.area.width as usize - screen_column,
+            style,
+        );
+        self.node_map.rows[screen_row as usize].push((start..end, self.path.clone()));
+    }
+}
 
-        for (node, tok) in self.tree.root().display_tokens(self.format_style) {
-            match tok {
-                DisplayToken::Text(s, category) => {
-                    let color = if DEBUG_HIGHLIGHTING {
-                        // Hash the ref to decide on the colour
-                        let mut hasher = DefaultHasher::new();
-                        node.hash(&mut hasher);
-                        let hash = hasher.finish();
-                        cols[hash as usize % cols.len()]
-                    } else {
-                        *self.color_scheme.get(category).unwrap_or_else(|| {
-                            unknown_categories.insert(category);
-                            &Color::LightMagenta
-                        })
-                    };
-                    // Generate the display attributes depending on if the node is selected
-                    let style = if std::ptr::eq(node, self.tree.cursor()) {
-                        Style::default().fg(Color::Black).bg(color)
-                    } else {
-                        Style::default().fg(color)
-                    };
-                    let mut lines = s.lines();
-                    col = buf
-                        .set_stringn(
-                            col,
-                            row,
-                            lines.next().unwrap(),
-                            (area.right() - col).into(),
-                            style,
-                        )
-                        .0;
-                    for _ in lines {
-                        todo!("implement multiline tokens");
-                    }
-                }
-                DisplayToken::Whitespace(n) => {
-                    col += n as u16;
-                }
-                DisplayToken::Newline => {
-                    row += 1;
-                    if row == area.bottom() {
-                        break;
-                    }
-                    col = indentation_amount;
-                }
-                DisplayToken::Indent => {
-                    indentation_amount += 4;
-                }
-                DisplayToken::Dedent => {
-                    indentation_amount -= 4;
-                }
+#[cfg(test)]
+mod tests {
+    use super::{
+        breadcrumb, help_sections, HelpPopup, HelpSection, Hint, NodeMap, PendingHints, StatusBar,
+        TextView,
+    };
+    use crate::arena::Arena;
+    use crate::ast::json::{add_value_to_arena, Class, JsonFormat};
+    use crate::ast::{display_token::Folds, Ast};
+    use crate::config::{default_color_scheme, default_keymap, StatusLine};
+    use crate::core::Path;
+
+    use std::collections::HashSet;
+
+    use serde_json::json;
+    use tui::{
+        buffer::Buffer,
+        layout::Rect,
+        style::Color,
+        widgets::{StatefulWidget, Widget},
+    };
+
+    /// Returns the text of each row of `buf`, without trailing spaces
+    fn rows(buf: &Buffer) -> Vec<String> {
+        let area = buf.area;
+        (area.top()..area.bottom())
+            .map(|y| {
+                let row: String = (area.left()..area.right())
+                    .map(|x| buf.get(x, y).symbol.as_str())
+                    .collect();
+                row.trim_end().to_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn multiline_tokens() {
+        let arena = Arena::new();
+        let root = add_value_to_arena(json!([true, "foo\nbar\nbaz", false]), &arena);
+        let string = root.children()[1];
+        let color_scheme = default_color_scheme();
+        let search_matches = HashSet::new();
+        let folds = Folds::default();
+        let render = |area: Rect, line_offset, column_offset| {
+            let mut buf = Buffer::empty(area);
+            TextView {
+                root,
+                cursor: string,
+                line_offset,
+                column_offset,
+                color_scheme: &color_scheme,
+                format_style: &JsonFormat::Pretty(4),
+                search_matches: &search_matches,
+                folds: &folds,
             }
+            .render(area, &mut buf, &mut NodeMap::default());
+            buf
+        };
+
+        // Continuation lines start at the indentation level, and the tokens after a multi-line
+        // token continue from the end of its last line
+        let buf = render(Rect::new(0, 0, 12, 8), 0, 0);
+        assert_eq!(
+            rows(&buf),
+            vec![
+                "[",
+                "    true,",
+                "    \"foo",
+                "    bar",
+                "    baz\",",
+                "    false",
+                "]",
+                ""
+            ]
+        );
+        // Every line of the cursor is highlighted, but not the indentation before it
+        for (x, y) in [(4, 2), (7, 2), (4, 3), (4, 4), (7, 4)] {
+            assert_ne!(buf.get(x, y).bg, Color::Reset, "({}, {})", x, y);
+        }
+        for (x, y) in [(0, 3), (3, 4), (8, 4), (4, 1)] {
+            assert_eq!(buf.get(x, y).bg, Color::Reset, "({}, {})", x, y);
         }
 
-        // Print warning messages for unknown syntax categories
-        for c in unknown_categories {
-            log::error!("Unknown highlight category '{}'", c);
+        // Scrolling into the middle of the token only draws the lines that are on screen, and
+        // the view can be offset from the top-left of the screen
+        let buf = render(Rect::new(2, 1, 8, 3), 3, 0);
+        assert_eq!(rows(&buf), vec!["    bar", "    baz\"", "    fals"]);
+        let buf = render(Rect::new(0, 0, 6, 3), 2, 5);
+        assert_eq!(rows(&buf), vec!["foo", "ar", "az\","]);
+    }
+
+    #[test]
+    fn node_map() {
+        let arena = Arena::new();
+        // [
+        //     true,
+        //     {
+        //         "a": null
+        //     }
+        // ]
+        let root = add_value_to_arena(json!([true, {"a": null}]), &arena);
+        let color_scheme = default_color_scheme();
+        let search_matches = HashSet::new();
+        let render = |folds: &Folds<_>| {
+            let area = Rect::new(2, 1, 20, 6);
+            let mut node_map = NodeMap::default();
+            TextView {
+                root,
+                cursor: root,
+                line_offset: 0,
+                column_offset: 0,
+                color_scheme: &color_scheme,
+                format_style: &JsonFormat::Pretty(4),
+                search_matches: &search_matches,
+                folds,
+            }
+            .render(area, &mut Buffer::empty(area), &mut node_map);
+            node_map
+        };
+
+        let node_map = render(&Folds::default());
+        for (column, row, path) in [
+            (2, 1, Some(vec![])),
+            (6, 2, Some(vec![0])),
+            (9, 2, Some(vec![0])),
+            // The comma belongs to the root, not to `true`
+            (10, 2, Some(vec![])),
+            (6, 3, Some(vec![1])),
+            (10, 4, Some(vec![1, 0, 0])),
+            (13, 4, Some(vec![1, 0])),
+            (16, 4, Some(vec![1, 0, 1])),
+            // Indentation, the space after the text and anything outside the view have no node
+            (3, 2, None),
+            (19, 4, None),
+            (1, 1, None),
+            (2, 7, None),
+        ] {
+            assert_eq!(
+                node_map.contains(column, row),
+                column >= 2 && (1..7).contains(&row)
+            );
+            assert_eq!(
+                node_map.path_at(column, row),
+                path.map(Path::from_vec).as_ref(),
+                "({}, {})",
+                column,
+                row
+            );
+        }
+
+        // A folded node is drawn as one piece of text
+        let node_map = render(&Folds::new(root, &[Path::from_vec(vec![1])]));
+        for column in 6..17 {
+            assert_eq!(node_map.path_at(column, 3), Some(&Path::from_vec(vec![1])));
+        }
+        assert_eq!(node_map.path_at(2, 4), Some(&Path::root()));
+    }
+
+    #[test]
+    fn breadcrumbs() {
+        let arena = Arena::new();
+        let root = add_value_to_arena(json!({"servers": [true, null, {"port": null}]}), &arena);
+        for (path, expected) in [
+            (vec![], "root"),
+            (vec![0], "root › \"servers\""),
+            // The key and value of a field are part of the field
+            (vec![0, 0], "root › \"servers\""),
+            (vec![0, 1], "root › \"servers\""),
+            (vec![0, 1, 2], "root › \"servers\" › [2]"),
+            (vec![0, 1, 2, 0, 1], "root › \"servers\" › [2] › \"port\""),
+        ] {
+            assert_eq!(breadcrumb(root, &Path::from_vec(path)), expected);
+        }
+    }
+
+    #[test]
+    fn status_bar() {
+        let layout = StatusLine::default();
+        let render = |width, prompt, is_dirty| {
+            let area = Rect::new(0, 0, width, 1);
+            let mut buf = Buffer::empty(area);
+            StatusBar {
+                layout: &layout,
+                mode: "NORMAL",
+                keystroke_buffer: "2d",
+                prompt,
+                recording: None,
+                file_name: "a.json",
+                is_dirty,
+                breadcrumb: "root › [1]",
+                class_name: "array",
+                undo_position: (3, 4),
+            }
+            .render(area, &mut buf);
+            rows(&buf).remove(0)
+        };
+        assert_eq!(
+            render(60, None, true),
+            "NORMAL  a.json  [+]  root › [1]        2d  array  change 3/4"
+        );
+        // Empty items are skipped, and the prompt replaces the left side
+        assert_eq!(
+            render(60, Some("/foo"), false),
+            "/foo                                   2d  array  change 3/4"
+        );
+        // The left side is cut short if there isn't enough space
+        assert_eq!(
+            render(35, None, false),
+            "NORMAL  a.jso 2d  array  change 3/4"
+        );
+        assert_eq!(render(30, None, false), "NORMAL   2d  array  change 3/4");
+    }
+
+    #[test]
+    fn help() {
+        let sections = help_sections::<Class>(&default_keymap());
+        let titles: Vec<_> = sections.iter().map(|s| s.title).collect();
+        assert_eq!(titles[0], "Moving");
+        assert_eq!(titles.last(), Some(&"Node types"));
+        // Keys bound to the same command share an entry
+        let entry = |keys: &str, description: &str| (keys.to_owned(), description.to_owned());
+        assert!(sections[0]
+            .entries
+            .contains(&entry("h k", "move to previous sibling")));
+        assert!(sections[0]
+            .entries
+            .contains(&entry("^o", "jump to older location")));
+        assert!(sections[0]
+            .entries
+            .contains(&entry("<Tab>", "jump to newer location")));
+        assert_eq!(sections.last().unwrap().entries[0], entry("t", "true"));
+
+        // Sections which don't fit in a column are moved to the next one
+        let sections = [
+            HelpSection {
+                title: "First",
+                color: Color::White,
+                entries: vec![entry("a", "one"), entry("b", "two")],
+            },
+            HelpSection {
+                title: "Second",
+                color: Color::White,
+                entries: vec![entry("cc", "three")],
+            },
+        ];
+        let area = Rect::new(0, 0, 26, 7);
+        let mut buf = Buffer::empty(area);
+        HelpPopup {
+            sections: &sections,
+        }
+        .render(area, &mut buf);
+        assert_eq!(
+            rows(&buf),
+            vec![
+                " ┌ Help (press any key t┐",
+                " │First       Second    │",
+                " │a   one     cc  three │",
+                " │b   two               │",
+                " │                      │",
+                " │                      │",
+                " └──────────────────────┘",
+            ]
+        );
+    }
+
+    #[test]
+    fn pending_hints() {
+        let mut hints = vec![
+            Hint::new("0-9", "count"),
+            Hint::new("a", "array"),
+            Hint::new("t", "true"),
+        ];
+        hints[2].is_valid = false;
+        let area = Rect::new(0, 0, 30, 6);
+        let mut buf = Buffer::empty(area);
+        PendingHints {
+            title: "insert child",
+            hints: &hints,
         }
+        .render(area, &mut buf);
+        assert_eq!(
+            rows(&buf),
+            vec![
+                "",
+                "",
+                "┌ insert child ──────────────┐",
+                "│0-9  count   t    true      │",
+                "│a    array                  │",
+                "└────────────────────────────┘",
+            ]
+        );
+        assert_eq!(buf.get(14, 3).fg, Color::DarkGray);
+        assert_eq!(buf.get(1, 4).fg, Color::Reset);
     }
 }
@@ -14,49 +14,88 @@ pub mod editor;
 
 use crate::arena::Arena;
 use crate::ast::json::{add_value_to_arena, JsonFormat};
+use crate::ast::Format;
 use crate::config::Config;
 use crate::core::Path;
-use crate::editor::{dag::Dag, Editor};
+use crate::editor::{buffer::Buffer, dag::Dag, Editor};
 
+use std::fmt;
+use std::io;
 use std::path::PathBuf;
+use std::process::ExitCode;
 
-use serde_json::Value;
+/// An error which stops Sapling from starting up, or forces it to stop running
+#[derive(Debug)]
+enum StartupErr {
+    /// The logger couldn't be initialised
+    Logger(log::SetLoggerError),
+    /// The file given on the command line couldn't be opened (the message is for the user)
+    OpenFile(String),
+    /// The terminal couldn't be put into the state that Sapling needs
+    Terminal(io::Error),
+    /// The terminal stopped working while Sapling was running (e.g. the user's input couldn't be
+    /// read)
+    Running(io::Error),
+}
+
+impl fmt::Display for StartupErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StartupErr::Logger(e) => write!(f, "Error initialising the logger: {}", e),
+            StartupErr::OpenFile(message) => write!(f, "{}", message),
+            StartupErr::Terminal(e) => write!(f, "Error setting up the terminal: {}", e),
+            StartupErr::Running(e) => write!(f, "Error using the terminal: {}", e),
+        }
+    }
+}
 
 /// The entry point of Sapling.
 ///
-/// The main function is tasked with initialising everything, then passing control to
-/// [`Editor::run`].
-fn main() {
+/// The main function hands over to [`run`], and reports any error which stopped Sapling from
+/// starting or running.
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Initialises everything, then passes control to [`Editor::run`].
+fn run() -> Result<(), StartupErr> {
     // Initialise the logging and startup
-    tui_logger::init_logger(log::LevelFilter::Info).unwrap();
+    tui_logger::init_logger(log::LevelFilter::Info).map_err(StartupErr::Logger)?;
     log::info!("Starting up...");
 
-    // Read a file name as the CLI argument
-    let (file_path, initial_json) = if let Some(first_arg) = std::env::args().nth(1) {
-        let path = PathBuf::from(first_arg);
-        let file = match std::fs::File::open(&path) {
-            Ok(x) => x,
-            Err(e) => {
-                eprintln!("Error opening {:?}: {}", path, e);
-                return;
-            }
-        };
-        (
-            Some(path),
-            serde_json::from_reader::<_, Value>(file).unwrap(),
-        )
-    } else {
-        log::warn!("Expected a file-name as an argument.  Using default JSON instead.");
-        (None, serde_json::json!([true, false, { "value": false }]))
-    };
+    // Load the user's configuration, falling back on the default format style if the configured
+    // one doesn't exist
+    let config = Config::load();
+    let format_style = config
+        .format_style
+        .parse::<JsonFormat>()
+        .unwrap_or_else(|e| {
+            log::warn!("Ignoring invalid config: 'format': {}", e);
+            JsonFormat::Pretty(config.indent_width)
+        })
+        .with_indent_width(config.indent_width);
 
     // Create an empty arena for Sapling to use
     log::trace!("Creating arena");
     let arena = Arena::new();
-    // For the time being, start the editor with some pre-made Json
-    let root = add_value_to_arena(initial_json, &arena);
 
-    let mut tree = Dag::new(&arena, root, Path::root());
-    let editor = Editor::new(&mut tree, JsonFormat::Pretty, Config::default(), file_path);
-    editor.run();
+    // Read a file name as the CLI argument
+    let buffer = if let Some(first_arg) = std::env::args().nth(1) {
+        Buffer::open(PathBuf::from(first_arg), &arena, format_style)
+            .map_err(StartupErr::OpenFile)?
+    } else {
+        log::warn!("Expected a file-name as an argument.  Using default JSON instead.");
+        // For the time being, start the editor with some pre-made Json
+        let root = add_value_to_arena(serde_json::json!([true, false, { "value": false }]), &arena);
+        Buffer::new(Dag::new(&arena, root, Path::root()), None, format_style)
+    };
+
+    let editor = Editor::new(buffer, config).map_err(StartupErr::Terminal)?;
+    editor.run().map_err(StartupErr::Running)
 }